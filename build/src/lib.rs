@@ -1,29 +1,10 @@
 #![no_std]
 
-use core::arch::asm;
-
 use panic_halt as _;
-use kernel::allocator::{Heap};
-use kernel::syscalls::task_switch;
-use cortex_m_rt::exception;
-
 
 /*
-    The SysTick handler is platform-agnostic
-    Its initialization is performed in kernel_init() during boot routine
+    This crate only bundles the kernel, and the C API it exports, into the
+    `pios` static library. The exception handlers, SysTick included, and
+    the scheduling policy belong to the kernel crate.
 */
-
-static mut systick_counter: u8 = 0;
-const TASK_TIME_UNIT: u8 = 10;
-
-#[exception]
-fn SysTick(){
-    unsafe{ 
-        systick_counter += 1;
-        if systick_counter ==  TASK_TIME_UNIT{
-            systick_counter = 0;
-            task_switch();
-            asm!("POP {{pc}}");
-        }
-    }
-}
\ No newline at end of file
+extern crate kernel;
@@ -5,18 +5,259 @@
 #include <stdlib.h>
 
 
+#define CPU_LOAD_WINDOW 1000
+
+#define DEFAULT_QUANTUM_MS 100
+
+#define EVENT_CLEAR_ON_EXIT (1 << 1)
+
+#define EVENT_WAIT_ALL (1 << 0)
+
 #define HEAP_SIZE 32768
 
+#define MAX_ACTIVE_TIMERS 32
+
+#define MAX_KERNEL_OBJECTS 32
+
+#define MAX_TIMEOUT 2147483647
+
 #define MAX_PRIORITY 10
 
+#define NO_DEADLINE 0
+
+#define NO_TIME_SLICE 0
+
+#define NO_WAIT 0
+
+#define SUCCESS 0
+
+#define TICK_RATE_HZ 100
+
+#define WAIT_FOREVER 4294967295
+
+typedef enum KernelError {
+  KernelError_OutOfMemory = 1,
+  KernelError_TooManyTasks = 2,
+  KernelError_InvalidArgument = 3,
+  KernelError_Timeout = 4,
+  KernelError_WouldBlock = 5,
+  KernelError_NotOwner = 6,
+  KernelError_Deadlock = 7,
+  KernelError_Full = 8,
+  KernelError_TooManyObjects = 9,
+  KernelError_Pending = 255,
+} KernelError;
+
+typedef enum OomAction {
+  OomAction_Fail = 0,
+  OomAction_Retry = 1,
+  OomAction_KillTask = 2,
+  OomAction_Reset = 3,
+} OomAction;
+
+typedef enum HeapRegionTag {
+  HeapRegionTag_Default = 0,
+  HeapRegionTag_Fast = 1,
+  HeapRegionTag_Dma = 2,
+} HeapRegionTag;
+
+typedef enum TaskState {
+  TaskState_Ready,
+  TaskState_Running,
+  TaskState_Blocked,
+  TaskState_Suspended,
+  TaskState_Terminated,
+} TaskState;
+
+typedef enum NotifyAction {
+  NotifyAction_SetBits = 0,
+  NotifyAction_Increment = 1,
+  NotifyAction_Overwrite = 2,
+} NotifyAction;
+
+typedef enum ObjectKind {
+  ObjectKind_MutexObject = 0,
+  ObjectKind_SemaphoreObject = 1,
+  ObjectKind_QueueObject = 2,
+  ObjectKind_EventGroupObject = 3,
+  ObjectKind_StreamBufferObject = 4,
+  ObjectKind_BarrierObject = 5,
+  ObjectKind_TimerObject = 6,
+} ObjectKind;
+
+typedef struct Barrier Barrier;
+
+typedef struct EventGroup EventGroup;
+
+typedef struct LockedPool LockedPool;
+
+typedef struct MessageQueue MessageQueue;
+
+typedef struct Semaphore Semaphore;
+
+typedef struct StreamBuffer StreamBuffer;
+
+typedef struct TaskMutex TaskMutex;
+
+typedef struct Timer Timer;
+
+typedef struct HeapStats {
+  size_t total_size;
+  size_t used;
+  size_t free;
+  size_t largest_free_block;
+  size_t min_free;
+  size_t allocations;
+  size_t frees;
+  size_t failed_allocations;
+  uint32_t fragmentation;
+} HeapStats;
+
+typedef struct KernelObject {
+  ObjectKind kind;
+  size_t address;
+} KernelObject;
+
+typedef struct TaskStats {
+  size_t id;
+  size_t priority;
+  TaskState state;
+  bool idle;
+  uint64_t cpu_time;
+  uint32_t cpu_percent;
+  uint32_t context_switches;
+  uint32_t last_run;
+  uint32_t deadline_misses;
+} TaskStats;
+
+typedef OomAction (*OomHook)(size_t size, size_t align, size_t task_id);
+
+typedef void (*DeadlineMissHook)(size_t);
+
+typedef void (*IdleHook)(void);
+
 extern const uint32_t HEAP_MEMORY;
 
-void create_task(void (*code)(uint8_t*), uint8_t *args, uint8_t priority);
+size_t barrier_wait(Barrier *barrier, uint32_t timeout);
+
+size_t complete_job(void);
+
+bool cpu_time_in_cycles(void);
+
+Barrier *create_barrier(size_t parties);
+
+EventGroup *create_event_group(void);
+
+MessageQueue *create_message_queue(size_t item_size, size_t capacity);
+
+size_t create_periodic_task(void (*code)(uint8_t*), uint8_t *args, size_t priority, uint32_t period_ms, uint32_t deadline_ms);
+
+LockedPool *create_pool(size_t block_size, size_t blocks);
+
+Semaphore *create_semaphore(size_t initial_count, size_t max_count);
+
+StreamBuffer *create_stream_buffer(size_t size, size_t trigger_level);
+
+size_t create_task(void (*code)(uint8_t*), uint8_t *args, uint8_t priority);
+
+TaskMutex *create_task_mutex(bool recursive);
+
+size_t create_task_with_deadline(void (*code)(uint8_t*), uint8_t *args, size_t priority, uint32_t deadline_ms);
+
+size_t create_task_with_quantum(void (*code)(uint8_t*), uint8_t *args, size_t priority, uint32_t quantum_ms);
+
+size_t create_task_with_quota(void (*code)(uint8_t*), uint8_t *args, size_t priority, size_t heap_quota);
+
+Timer *create_timer(void (*callback)(uint8_t*), uint8_t *arg, uint32_t period, bool auto_reload);
+
+uint32_t event_group_clear(EventGroup *group, uint32_t bits);
+
+uint32_t event_group_set(EventGroup *group, uint32_t bits);
+
+uint32_t event_group_set_from_isr(EventGroup *group, uint32_t bits);
+
+size_t event_group_wait(EventGroup *group, uint32_t bits, uint32_t options, uint32_t timeout, uint32_t *value);
+
+void exit_task(void);
+
+uint32_t get_cpu_load(void);
 
 uint32_t get_heap_addr(void);
 
+void get_heap_stats(HeapStats *stats);
+
+size_t get_kernel_objects(KernelObject *objects, size_t len);
+
+size_t get_priority(size_t task_id, size_t *priority);
+
+size_t get_task_id(void);
+
 void heap_init_wrapper(size_t start_addr, size_t size);
 
-void kernel_init(size_t heap_start, uint32_t reload_value);
+bool kernel_add_heap_region(uint32_t tag, size_t start, size_t size);
+
+void kernel_init(size_t heap_start, size_t heap_size, uint32_t cpu_clock_hz);
+
+void kernel_set_deadline_miss_hook(DeadlineMissHook hook);
+
+void kernel_set_idle_hook(IdleHook hook);
+
+void kernel_set_oom_hook(OomHook hook);
+
+void kernel_tick(void);
+
+size_t message_queue_peek(MessageQueue *queue, uint8_t *buffer);
+
+size_t message_queue_receive(MessageQueue *queue, uint8_t *buffer, uint32_t timeout);
+
+size_t message_queue_send(MessageQueue *queue, const uint8_t *item, uint32_t timeout);
+
+size_t message_queue_send_from_isr(MessageQueue *queue, const uint8_t *item);
+
+size_t notify(size_t task_id, uint32_t value, NotifyAction action);
+
+size_t notify_from_isr(size_t task_id, uint32_t value, NotifyAction action);
+
+size_t notify_take(uint32_t timeout, uint32_t *value);
+
+size_t notify_wait(uint32_t timeout, uint32_t *value);
+
+uint8_t *pool_alloc(LockedPool *pool);
+
+void pool_free(LockedPool *pool, uint8_t *ptr);
 
 void prova(void);
+
+size_t resume_task(size_t task_id);
+
+size_t semaphore_give(Semaphore *semaphore);
+
+size_t semaphore_give_from_isr(Semaphore *semaphore);
+
+size_t semaphore_take(Semaphore *semaphore, uint32_t timeout);
+
+size_t set_priority(size_t task_id, size_t priority);
+
+size_t stream_buffer_read(StreamBuffer *stream, uint8_t *buffer, size_t len, uint32_t timeout);
+
+size_t stream_buffer_write(StreamBuffer *stream, const uint8_t *data, size_t len);
+
+size_t stream_buffer_write_from_isr(StreamBuffer *stream, const uint8_t *data, size_t len);
+
+size_t suspend_task(size_t task_id);
+
+size_t task_mutex_lock(TaskMutex *mutex, uint32_t timeout);
+
+size_t task_mutex_unlock(TaskMutex *mutex);
+
+size_t task_stats(TaskStats *buffer, size_t len);
+
+size_t timer_change_period(Timer *timer, uint32_t period);
+
+size_t timer_reset(Timer *timer);
+
+size_t timer_start(Timer *timer);
+
+size_t timer_stop(Timer *timer);
+
+size_t wait_next_period(void);
@@ -1,39 +1,167 @@
 use core::mem;
 use crate::{mutex::MutexGuard};
+use crate::task::{self, KERNEL_ID};
+use crate::pool::LockedPool;
 use core::cmp::max;
 use super::mutex::Mutex;
 
 pub const HEAP_SEG_HEADER_SIZE: usize = mem::size_of::<HeapSegment>();
 
+// Maximum number of fixed-size pools that can be registered with the heap
+pub const MAX_HEAP_POOLS: usize = 8;
+
+// Maximum number of disjoint memory regions managed by the heap
+pub const MAX_HEAP_REGIONS: usize = 4;
+
+// Number of distinct region tags
+pub const HEAP_REGION_TAGS: usize = 3;
+
 type SegmentLink = Option<&'static mut HeapSegment>;
 
 /*
 HeapSegments are the 'header' of each memory block that is NOT allocated on the
 Heap. This representation allows us to store the heap as a linked list of 
-memory segments.
+memory segments. The layout is fixed, with the size in the first word, so
+that the integrity checks can be tested against a corrupted header.
 */
 
+#[repr(C)]
 pub struct HeapSegment {
     size: usize,
     next: SegmentLink,
 }
 
 /*
-The Heap simply contains a reference to the first available block of memory
+The Heap contains a reference to the first available block of memory, and
+the bounds of the memory it was initialized with. The bounds are only used
+to check the heap's integrity, and are left to 0 if the heap is built out of
+single segments through `add_free_segment`.
 */
 
 pub struct Heap {
     head: SegmentLink,
+    start: usize,
+    end: usize,
+    free: usize,
+}
+
+/*
+The errors detected by `Heap::check_integrity()`. Each of them carries the
+address of the offending segment header.
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeapError {
+    // The segment lies outside of the heap's memory
+    OutOfBounds(usize),
+    // The segment is misaligned, or its size is not valid
+    BadHeader(usize),
+    // The segment starts before the previous one in the list
+    Unordered(usize),
+    // The segment starts before the end of the previous one
+    Overlapping(usize),
+}
+
+/*
+Boards usually have several disjoint RAM banks, with different speed and DMA
+capabilities. Each bank is managed by the kernel as a separate region, with
+its own free list, and is identified by a tag.
+*/
+
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HeapRegionTag {
+    Default = 0,
+    Fast = 1,
+    Dma = 2,
+}
+
+impl HeapRegionTag {
+    /* Returns the tag with the given value, which comes from C code */
+    pub fn from_raw(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(Self::Default),
+            1 => Some(Self::Fast),
+            2 => Some(Self::Dma),
+            _ => None,
+        }
+    }
+}
+
+pub struct HeapRegion {
+    tag: HeapRegionTag,
+    heap: Heap,
 }
 
 /*
-This type wraps an Heap into a mutex, providing mutual access to it. It is
-needed to implement the trait `GlobalAllocator`, more on GlobalAllocator at
-the end of the file.
+The set of regions that make up the kernel's heap. `fallback` lists the tags
+of the regions the global allocator may use, in the order they are tried.
+*/
+
+pub struct HeapRegions {
+    regions: [Option<HeapRegion>; MAX_HEAP_REGIONS],
+    fallback: [Option<HeapRegionTag>; HEAP_REGION_TAGS],
+    min_free: usize,
+    allocations: usize,
+    frees: usize,
+    failed_allocations: usize,
+    allocated: *mut AllocationHeader,
+}
+
+/*
+Every allocation served by the heap starts with an AllocationHeader, which
+records the task that owns the memory and the size it requested. Headers
+are linked into a list of all the outstanding allocations, so that the
+memory still owned by a task can be reclaimed when the task terminates.
+*/
+
+#[repr(C)]
+pub struct AllocationHeader {
+    owner: usize,
+    size: usize,
+    prev: *mut AllocationHeader,
+    next: *mut AllocationHeader,
+}
+
+pub const ALLOC_HEADER_SIZE: usize = mem::size_of::<AllocationHeader>();
+
+/*
+A snapshot of the heap's health. The counters cover the whole lifetime of
+the heap, and include the allocations served by the fixed-size pools.
+
+`fragmentation` is the percentage of free memory that is not part of the
+largest free block: 0 means that all the free memory is contiguous, while
+values close to 100 mean that even small allocations might fail.
+*/
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct HeapStats {
+    pub total_size: usize,
+    pub used: usize,
+    pub free: usize,
+    pub largest_free_block: usize,
+    pub min_free: usize,
+    pub allocations: usize,
+    pub frees: usize,
+    pub failed_allocations: usize,
+    pub fragmentation: u32,
+}
+
+/*
+This type wraps the heap's regions into a mutex, providing mutual access to
+them. It is needed to implement the trait `GlobalAllocator`, more on
+GlobalAllocator at the end of the file.
+
+The heap can also be backed by a set of fixed-size pools (see the `pool`
+module): the kernel allocates its objects from them explicitly, through
+`alloc_pooled`, and falls back to the heap when a pool is empty. Freed
+memory that belongs to a registered pool goes back to it.
 */
 
 pub struct LockedHeap {
-    heap: Mutex<Heap>,
+    regions: Mutex<HeapRegions>,
+    pools: Mutex<[Option<&'static LockedPool>; MAX_HEAP_POOLS]>,
 }
 
 /*
@@ -47,35 +175,488 @@ pub struct HeapIterator<'a> {
 
 impl LockedHeap {
     pub const fn new() -> Self {
-        Self { heap: Mutex::new(Heap::new()) }
+        Self {
+            regions: Mutex::new(HeapRegions::new()),
+            pools: Mutex::new([None; MAX_HEAP_POOLS]),
+        }
     }
 
-    pub fn lock(&self) -> MutexGuard<'_, Heap> {
-        self.heap.lock()
+    pub fn lock(&self) -> MutexGuard<'_, HeapRegions> {
+        self.regions.lock()
     }
 
+    /* The memory block is added to the heap as the default region */
+
     pub fn init(&self, start_address: usize, size: usize) {
-        self.lock().add_free_segment(start_address, size);
+        self.add_region(HeapRegionTag::Default, start_address, size);
     } 
 
+    /*
+    Adds a memory region to the heap. It returns false if the maximum
+    number of regions has been reached.
+    */
+
+    pub fn add_region(&self, tag: HeapRegionTag, start_address: usize, size: usize) -> bool {
+        self.lock().add_region(tag, start_address, size)
+    }
+
+    /*
+    Sets the order in which the global allocator tries the regions. Regions
+    whose tag is not listed are only used by explicit `alloc_in` requests.
+    */
+
+    pub fn set_fallback_order(&self, order: &[HeapRegionTag]) {
+        self.lock().set_fallback_order(order);
+    }
+
+    /* Allocates memory from the regions with the given tag only */
+
+    pub fn alloc_in(&self, tag: HeapRegionTag, layout: Layout) -> *mut u8 {
+        if !self.check_align(&layout) {
+            return ptr::null_mut();
+        }
+        handle_oom(layout, || self.heap_alloc(Some(tag), layout))
+    }
+
+    /*
+    Allocations aligned to more than HEAP_MAX_ALIGN are not supported: they
+    fail right away, without calling the OOM hook.
+    */
+
+    fn check_align(&self, layout: &Layout) -> bool {
+        if layout.align() <= HEAP_MAX_ALIGN {
+            return true;
+        }
+        self.lock().record_allocation(false);
+        false
+    }
+
+    /*
+    Allocates memory from the heap, on behalf of the running task. The
+    memory is charged to the task, and the allocation fails if that would
+    exceed the task's quota. If `tag` is None, the regions are tried in
+    the fallback order.
+    */
+
+    fn heap_alloc(&self, tag: Option<HeapRegionTag>, layout: Layout) -> *mut u8 {
+        let owner = task::current_task_id();
+        if !task::charge_heap(owner, layout.size()) {
+            self.lock().record_allocation(false);
+            return ptr::null_mut();
+        }
+
+        let size = guarded_size(layout.size());
+        let mut heap = self.lock();
+        let block = match tag {
+            Some(tag) => heap.allocate_segment_in(tag, size),
+            None => heap.allocate_segment(size),
+        };
+        heap.record_allocation(block.is_some());
+
+        match block {
+            None => {
+                drop(heap);
+                task::uncharge_heap(owner, layout.size());
+                ptr::null_mut()
+            }
+            Some(block) => unsafe {
+                heap.track_allocation(block, owner, layout.size());
+                put_guards(block, layout.size())
+            }
+        }
+    }
+
+    /* Gives memory back to the heap, and uncharges it from its owner */
+
+    unsafe fn heap_dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // Memory that the heap does not own is left alone
+        if self.lock().region_containing(ptr as usize).is_none() {
+            return;
+        }
+
+        let block = check_guards(ptr, layout.size());
+        let header = block as *mut AllocationHeader;
+        let owner = (*header).owner;
+
+        let mut heap = self.lock();
+
+        // A corrupted free list is reported before compaction walks it
+        #[cfg(feature = "heap-debug")]
+        if let Err(error) = heap.check_integrity() {
+            panic!("heap corruption detected: {:?}", error);
+        }
+
+        heap.untrack_allocation(header);
+        let size = guarded_size(layout.size());
+        poison(block, size);
+        if heap.free_segment(block as usize, size).is_ok() {
+            heap.record_free();
+        }
+        drop(heap);
+
+        task::uncharge_heap(owner, layout.size());
+    }
+
+    /*
+    Frees all the outstanding allocations owned by the task, and returns
+    the number of bytes that were reclaimed. It is called when a task
+    terminates, so that a leaky task can't starve the others.
+    */
+
+    pub fn reclaim_task_allocations(&self, owner: usize) -> usize {
+        if owner == KERNEL_ID {
+            return 0;
+        }
+
+        let mut heap = self.lock();
+        let mut reclaimed = 0;
+        let mut cursor = heap.allocated;
+        while !cursor.is_null() {
+            unsafe {
+                let header = &*cursor;
+                let next = header.next;
+                if header.owner == owner {
+                    let size = guarded_size(header.size);
+                    reclaimed += header.size;
+                    heap.untrack_allocation(cursor);
+                    poison(cursor as *mut u8, size);
+                    if heap.free_segment(cursor as usize, size).is_ok() {
+                        heap.record_free();
+                    }
+                }
+                cursor = next;
+            }
+        }
+        reclaimed
+    }
+
+    /* Returns the number of outstanding allocations owned by the task, and their size */
+
+    pub fn task_allocations(&self, owner: usize) -> (usize, usize) {
+        self.lock().task_allocations(owner)
+    }
+
     pub fn available_space(&self) -> usize {
         self.lock().available_space()
     }
 
+    pub fn available_space_in(&self, tag: HeapRegionTag) -> usize {
+        self.lock().available_space_in(tag)
+    }
+
     pub fn count_segments(&self) -> usize {
         self.lock().count_segments()
     }
+
+    pub fn check_integrity(&self) -> Result<(), HeapError> {
+        self.lock().check_integrity()
+    }
+
+    pub fn stats(&self) -> HeapStats {
+        self.lock().stats()
+    }
+
+    /*
+    Registers a pool with the allocator. It returns false if there is no
+    room left for another pool.
+    */
+
+    pub fn register_pool(&self, pool: &'static LockedPool) -> bool {
+        let mut pools = self.pools.lock();
+        for slot in pools.iter_mut() {
+            if slot.is_none() {
+                *slot = Some(pool);
+                return true;
+            }
+        }
+        false
+    }
+
+    /*
+    Allocates memory for a kernel object from the given pool, or from the
+    heap if the pool is exhausted or not registered with the allocator. See
+    `pool::try_new_pooled`. Pool blocks are neither charged nor tracked, so
+    they are only handed out to the kernel: an object allocated on behalf of
+    a task comes from the heap, and it is charged to the task.
+    */
+
+    pub fn alloc_pooled(&self, pool: &'static LockedPool, layout: Layout) -> *mut u8 {
+        let registered = self.pools.lock().iter()
+            .flatten()
+            .any(|registered| ptr::eq(*registered, pool));
+        let kernel = task::current_task_id() == KERNEL_ID;
+        if kernel && registered && pool.block_size() >= layout.size() {
+            if let Some(ptr) = pool.alloc() {
+                self.lock().record_allocation(true);
+                return ptr;
+            }
+        }
+        unsafe{ self.alloc(layout) }
+    }
+
+    /* Returns the registered pool the memory pointed to by `ptr` belongs to */
+
+    fn pool_containing(&self, ptr: *const u8) -> Option<&'static LockedPool> {
+        let pools = self.pools.lock();
+        pools.iter()
+            .flatten()
+            .find(|pool| pool.contains(ptr))
+            .copied()
+    }
+}
+
+impl HeapRegions {
+    const NO_REGION: Option<HeapRegion> = None;
+
+    pub const fn new() -> Self {
+        Self {
+            regions: [Self::NO_REGION; MAX_HEAP_REGIONS],
+            fallback: [
+                Some(HeapRegionTag::Default),
+                Some(HeapRegionTag::Fast),
+                Some(HeapRegionTag::Dma),
+            ],
+            min_free: 0,
+            allocations: 0,
+            frees: 0,
+            failed_allocations: 0,
+            allocated: ptr::null_mut(),
+        }
+    }
+
+    pub fn add_region(&mut self, tag: HeapRegionTag, start_address: usize, size: usize) -> bool {
+        // The region is trimmed to a multiple of HEAP_MAX_ALIGN, so that the
+        // blocks carved out of it are aligned
+        let start = align_up(start_address, HEAP_MAX_ALIGN);
+        let size = size.saturating_sub(start - start_address) & !(HEAP_MAX_ALIGN - 1);
+        let start_address = start;
+        for slot in self.regions.iter_mut() {
+            if slot.is_none() {
+                let mut heap = Heap::new();
+                heap.init(start_address, size);
+                *slot = Some(HeapRegion { tag, heap });
+                self.min_free += size;
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn set_fallback_order(&mut self, order: &[HeapRegionTag]) {
+        self.fallback = [None; HEAP_REGION_TAGS];
+        for (slot, tag) in self.fallback.iter_mut().zip(order.iter()) {
+            *slot = Some(*tag);
+        }
+    }
+
+    /* Allocates a memory segment, trying the regions in the fallback order */
+
+    pub fn allocate_segment(&mut self, size: usize) -> Option<*mut u8> {
+        let fallback = self.fallback;
+        fallback.iter()
+            .flatten()
+            .find_map(|tag| self.allocate_segment_in(*tag, size))
+    }
+
+    /* Allocates a memory segment from the regions with the given tag */
+
+    pub fn allocate_segment_in(&mut self, tag: HeapRegionTag, size: usize) -> Option<*mut u8> {
+        self.regions.iter_mut()
+            .flatten()
+            .filter(|region| region.tag == tag)
+            .find_map(|region| region.heap.allocate_segment(size))
+    }
+
+    /*
+    The segment is given back to the region it belongs to. OutOfBounds is
+    returned if it lies outside of every region.
+    */
+
+    pub fn free_segment(&mut self, start_address: usize, size: usize) -> Result<(), HeapError> {
+        let region = self.region_containing(start_address)
+            .ok_or(HeapError::OutOfBounds(start_address))?;
+        region.heap.free_segment(start_address, size);
+        Ok(())
+    }
+
+    pub fn region_containing(&mut self, address: usize) -> Option<&mut HeapRegion> {
+        self.regions.iter_mut()
+            .flatten()
+            .find(|region| region.contains(address))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &HeapRegion> {
+        self.regions.iter().flatten()
+    }
+
+    pub fn available_space(&self) -> usize {
+        self.iter().map(|region| region.heap.available_space()).sum()
+    }
+
+    pub fn available_space_in(&self, tag: HeapRegionTag) -> usize {
+        self.iter()
+            .filter(|region| region.tag == tag)
+            .map(|region| region.heap.available_space())
+            .sum()
+    }
+
+    pub fn count_segments(&self) -> usize {
+        self.iter().map(|region| region.heap.count_segments()).sum()
+    }
+
+    /* Total size of the free memory, across all the regions */
+
+    pub fn free_bytes(&self) -> usize {
+        self.iter().map(|region| region.heap.free_bytes()).sum()
+    }
+
+    /*
+    The outcome of an allocation is recorded in the heap's statistics, and
+    the low-water mark of free memory is updated.
+    */
+
+    pub fn record_allocation(&mut self, succeeded: bool) {
+        if succeeded {
+            self.allocations += 1;
+            let free = self.free_bytes();
+            if free < self.min_free {
+                self.min_free = free;
+            }
+        } else {
+            self.failed_allocations += 1;
+        }
+    }
+
+    pub fn record_free(&mut self) {
+        self.frees += 1;
+    }
+
+    /*
+    The header is written at the start of a newly allocated block, and
+    linked at the head of the list of outstanding allocations.
+    */
+
+    unsafe fn track_allocation(&mut self, block: *mut u8, owner: usize, size: usize) {
+        let header = block as *mut AllocationHeader;
+        header.write(AllocationHeader {
+            owner,
+            size,
+            prev: ptr::null_mut(),
+            next: self.allocated,
+        });
+        if !self.allocated.is_null() {
+            (*self.allocated).prev = header;
+        }
+        self.allocated = header;
+    }
+
+    /* The allocation is removed from the list of outstanding allocations */
+
+    unsafe fn untrack_allocation(&mut self, header: *mut AllocationHeader) {
+        let header = &mut *header;
+        if header.prev.is_null() {
+            self.allocated = header.next;
+        } else {
+            (*header.prev).next = header.next;
+        }
+        if !header.next.is_null() {
+            (*header.next).prev = header.prev;
+        }
+    }
+
+    /* Returns the number of outstanding allocations owned by the task, and their size */
+
+    pub fn task_allocations(&self, owner: usize) -> (usize, usize) {
+        let mut count = 0;
+        let mut bytes = 0;
+        let mut cursor = self.allocated;
+        while let Some(header) = unsafe{ cursor.as_ref() } {
+            if header.owner == owner {
+                count += 1;
+                bytes += header.size;
+            }
+            cursor = header.next;
+        }
+        (count, bytes)
+    }
+
+    pub fn stats(&self) -> HeapStats {
+        let total_size = self.iter().map(|region| region.size()).sum();
+        let free = self.free_bytes();
+        let largest_free_block = self.iter()
+            .map(|region| region.heap.largest_free_segment())
+            .max()
+            .unwrap_or(0);
+        let fragmentation = if free == 0 {
+            0
+        } else {
+            (100 - largest_free_block * 100 / free) as u32
+        };
+
+        HeapStats {
+            total_size,
+            used: total_size - free,
+            free,
+            largest_free_block,
+            min_free: self.min_free,
+            allocations: self.allocations,
+            frees: self.frees,
+            failed_allocations: self.failed_allocations,
+            fragmentation,
+        }
+    }
+
+    pub fn check_integrity(&self) -> Result<(), HeapError> {
+        for region in self.iter() {
+            region.heap.check_integrity()?;
+        }
+        Ok(())
+    }
+}
+
+impl HeapRegion {
+    pub fn tag(&self) -> HeapRegionTag {
+        self.tag
+    }
+
+    pub fn start_address(&self) -> usize {
+        self.heap.start
+    }
+
+    pub fn end_address(&self) -> usize {
+        self.heap.end
+    }
+
+    pub fn size(&self) -> usize {
+        self.heap.end - self.heap.start
+    }
+
+    pub fn heap(&self) -> &Heap {
+        &self.heap
+    }
+
+    pub fn contains(&self, address: usize) -> bool {
+        address >= self.heap.start && address < self.heap.end
+    }
 }
 
 impl Heap {
 
     pub const fn new() -> Self {
-        Self { head: None }
+        Self { head: None, start: 0, end: 0, free: 0 }
     }
 
     /* Initializes the heap as a single empty memory block */
 
     pub fn init(&mut self, start_address: usize, size: usize) {
+        self.start = start_address;
+        self.end = start_address + size;
+
+        // In debug mode the whole heap is poisoned, so that reads from
+        // uninitialized memory are easier to spot
+        #[cfg(feature = "heap-debug")]
+        unsafe{ ptr::write_bytes(start_address as *mut u8, HEAP_POISON, size) };
+
         self.add_free_segment(start_address, size);
     }
 
@@ -104,6 +685,7 @@ impl Heap {
             // allocated
             Self::trim_segment(old_head, actual_size);
             self.head = old_head.next.take();
+            self.free -= old_head.size;
             return Some(old_head.start_address() as *mut u8);
         }
 
@@ -132,6 +714,7 @@ impl Heap {
         let next = cursor.next.take().unwrap();
         Self::trim_segment(next, actual_size);
         cursor.next = next.next.take();
+        self.free -= next.size;
         
         Some(next.start_address() as *mut u8)
     }
@@ -157,6 +740,7 @@ impl Heap {
         // The heap should never allocate segments of size less than
         // HEAP_SEG_HEADER_SIZE
         assert!(size > HEAP_SEG_HEADER_SIZE);
+        self.free += size;
         
         let mut new_seg = unsafe{Self::init_segment(HeapSegment::new(size), address)};
         if self.head.is_none() || self.head.as_ref().unwrap().start_address() > address {
@@ -242,6 +826,18 @@ impl Heap {
         total
     }
 
+    /* Total size of the free segments, kept up to date on every operation */
+
+    pub fn free_bytes(&self) -> usize {
+        self.free
+    }
+
+    /* Utility function that returns the size of the largest free segment */
+
+    pub fn largest_free_segment(&self) -> usize {
+        self.iter().map(|seg| seg.size).max().unwrap_or(0)
+    }
+
     /* Utility function that returns the number of free segments in the heap */
 
     pub fn count_segments(&self) -> usize {
@@ -252,6 +848,53 @@ impl Heap {
         total
     }
 
+    /*
+    The function walks the list of free segments, checking that each header
+    is sane and lies inside the heap, and that segments are sorted by
+    address and do not overlap. The `next` pointer of a segment is only
+    followed once it is known to point inside the heap, therefore a
+    corrupted list is reported instead of being blindly traversed.
+    */
+
+    pub fn check_integrity(&self) -> Result<(), HeapError> {
+        let bounded = self.end > self.start;
+        let mut previous: Option<(usize, usize)> = None;
+        let mut cursor = self.head.as_deref().map(|seg| seg as *const HeapSegment);
+
+        while let Some(seg_ptr) = cursor {
+            let start = seg_ptr as usize;
+
+            if bounded && (start < self.start || start + HEAP_SEG_HEADER_SIZE > self.end) {
+                return Err(HeapError::OutOfBounds(start));
+            }
+            if start % mem::align_of::<HeapSegment>() != 0 {
+                return Err(HeapError::BadHeader(start));
+            }
+
+            let seg = unsafe{ &*seg_ptr };
+            let end = match start.checked_add(seg.size) {
+                Some(end) if seg.size >= HEAP_SEG_HEADER_SIZE => end,
+                _ => return Err(HeapError::BadHeader(start)),
+            };
+            if bounded && end > self.end {
+                return Err(HeapError::OutOfBounds(start));
+            }
+
+            if let Some((previous_start, previous_end)) = previous {
+                if start <= previous_start {
+                    return Err(HeapError::Unordered(start));
+                }
+                if start < previous_end {
+                    return Err(HeapError::Overlapping(start));
+                }
+            }
+
+            previous = Some((start, end));
+            cursor = seg.next.as_deref().map(|next| next as *const HeapSegment);
+        }
+        Ok(())
+    }
+
     /*
     This function copies an `HeapSegment` struct at the desired address, while
     returning a mutable reference to it.
@@ -326,21 +969,200 @@ use core::ptr;
 
 unsafe impl GlobalAlloc for LockedHeap {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        let mut heap = self.lock();
-        match heap.allocate_segment(layout.size()) {
-            None => ptr::null_mut(),
-            Some(ptr) => ptr
+        if !self.check_align(&layout) {
+            return ptr::null_mut();
         }
+        handle_oom(layout, || self.heap_alloc(None, layout))
     }
 
     unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {
-        let mut heap = self.lock();
-        heap.add_free_segment(_ptr as usize, _layout.size());
+        if let Some(pool) = self.pool_containing(_ptr) {
+            if pool.free(_ptr).is_ok() {
+                self.lock().record_free();
+            }
+            return;
+        }
+
+        self.heap_dealloc(_ptr, _layout);
+    }
+}
+
+/*
+Heap debug mode, enabled by the `heap-debug` cargo feature.
+
+Every allocation served by the heap is surrounded by two guard words holding
+HEAP_CANARY, placed so that the allocation keeps its alignment. The guards are checked when the memory is freed, so that buffer
+overruns are caught before they can corrupt the header of the following
+segment. Freed memory is filled with HEAP_POISON, which makes use-after-free
+bugs easier to spot.
+
+Allocations served by the fixed-size pools are not guarded, nor charged to
+any task: pools only hold the objects the kernel allocates for itself, see
+`alloc_pooled`.
+*/
+
+#[cfg(feature = "heap-debug")]
+pub const HEAP_GUARD_SIZE: usize = mem::size_of::<usize>();
+#[cfg(not(feature = "heap-debug"))]
+pub const HEAP_GUARD_SIZE: usize = 0;
+
+pub const HEAP_CANARY: usize = 0xC0FF_EE42;
+pub const HEAP_POISON: u8 = 0xA5;
+
+// Largest alignment the heap honours, the one of u64 and f64
+pub const HEAP_MAX_ALIGN: usize = 8;
+
+const fn align_up(value: usize, align: usize) -> usize {
+    (value + align - 1) & !(align - 1)
+}
+
+// Bytes between the start of a heap block and the memory handed out: the
+// allocation header and the first guard word, rounded up to HEAP_MAX_ALIGN
+const ALLOC_PREFIX_SIZE: usize = align_up(ALLOC_HEADER_SIZE + HEAP_GUARD_SIZE, HEAP_MAX_ALIGN);
+
+// Bytes added by the kernel to each heap allocation whose size is a
+// multiple of HEAP_MAX_ALIGN
+pub const HEAP_ALLOC_OVERHEAD: usize = ALLOC_PREFIX_SIZE + align_up(HEAP_GUARD_SIZE, HEAP_MAX_ALIGN);
+
+/*
+Size of a heap allocation, including its header and guard words. It is a
+multiple of HEAP_MAX_ALIGN, so that the next block is aligned as well.
+*/
+
+const fn guarded_size(size: usize) -> usize {
+    align_up(ALLOC_PREFIX_SIZE + size + HEAP_GUARD_SIZE, HEAP_MAX_ALIGN)
+}
+
+/*
+The guard words are written right before and right after the memory handed
+out, and its address is returned to the caller.
+*/
+
+#[cfg(feature = "heap-debug")]
+unsafe fn put_guards(block: *mut u8, size: usize) -> *mut u8 {
+    let ptr = block.add(ALLOC_PREFIX_SIZE);
+    (ptr.sub(HEAP_GUARD_SIZE) as *mut usize).write_unaligned(HEAP_CANARY);
+    (ptr.add(size) as *mut usize).write_unaligned(HEAP_CANARY);
+    ptr
+}
+
+#[cfg(not(feature = "heap-debug"))]
+unsafe fn put_guards(block: *mut u8, _size: usize) -> *mut u8 {
+    block.add(ALLOC_PREFIX_SIZE)
+}
+
+/*
+The guard words of the allocation are checked, and the start address of
+the whole block is returned.
+*/
+
+#[cfg(feature = "heap-debug")]
+unsafe fn check_guards(ptr: *mut u8, size: usize) -> *mut u8 {
+    if (ptr.sub(HEAP_GUARD_SIZE) as *const usize).read_unaligned() != HEAP_CANARY {
+        panic!("heap corruption detected: guard word before {:#x} overwritten", ptr as usize);
+    }
+    if (ptr.add(size) as *const usize).read_unaligned() != HEAP_CANARY {
+        panic!("heap corruption detected: buffer at {:#x} overrun", ptr as usize);
+    }
+    ptr.sub(ALLOC_PREFIX_SIZE)
+}
+
+#[cfg(not(feature = "heap-debug"))]
+unsafe fn check_guards(ptr: *mut u8, _size: usize) -> *mut u8 {
+    ptr.sub(ALLOC_PREFIX_SIZE)
+}
+
+#[cfg(feature = "heap-debug")]
+unsafe fn poison(block: *mut u8, size: usize) {
+    ptr::write_bytes(block, HEAP_POISON, size);
+}
+
+#[cfg(not(feature = "heap-debug"))]
+unsafe fn poison(_block: *mut u8, _size: usize) {}
+
+/*
+Out of memory policy.
+
+When an allocation can't be satisfied, either because the heap is exhausted
+or because the running task reached its quota, the kernel calls the OOM
+hook registered by the application, if any. The hook receives the size and
+alignment of the failing request, and the id of the offending task (0 if
+the memory was requested by the kernel), and decides what to do:
+
+    - Fail:     the allocation fails. Fallible allocations, like the ones
+                performed by system calls, report the error to the caller.
+    - Retry:    the hook released some memory (e.g. by dropping caches),
+                and the allocation is attempted again.
+    - KillTask: the allocation fails, and the offending task is terminated.
+                The allocator may be called with locks held, therefore the
+                task is only switched out, and its memory reclaimed, once
+                the kernel's interrupts are unmasked again.
+    - Reset:    the system is reset.
+*/
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OomAction {
+    Fail = 0,
+    Retry = 1,
+    KillTask = 2,
+    Reset = 3,
+}
+
+pub type OomHook = extern "C" fn(size: usize, align: usize, task_id: usize) -> OomAction;
+
+// Maximum number of times an allocation is retried on behalf of the hook
+pub const OOM_MAX_RETRIES: usize = 3;
+
+static mut OOM_HOOK: Option<OomHook> = None;
+
+pub fn set_oom_hook(hook: Option<OomHook>) {
+    unsafe{ OOM_HOOK = hook };
+}
+
+/*
+Runs the allocation, applying the OOM policy when it fails. The hook is
+invoked with no lock held, so that it can free memory itself.
+*/
+
+fn handle_oom<F: FnMut() -> *mut u8>(layout: Layout, mut allocate: F) -> *mut u8 {
+    let mut retries = 0;
+    loop {
+        let ptr = allocate();
+        if !ptr.is_null() {
+            return ptr;
+        }
+
+        let hook = match unsafe{ OOM_HOOK } {
+            Some(hook) => hook,
+            None => return ptr::null_mut(),
+        };
+        let task_id = task::current_task_id();
+
+        match hook(layout.size(), layout.align(), task_id) {
+            OomAction::Fail => return ptr::null_mut(),
+            OomAction::Retry => {
+                retries += 1;
+                if retries > OOM_MAX_RETRIES {
+                    return ptr::null_mut();
+                }
+            }
+            OomAction::KillTask => {
+                // The kernel itself can't be killed, the allocation fails
+                // either way
+                if task_id != KERNEL_ID {
+                    task::kill_running();
+                }
+                return ptr::null_mut();
+            }
+            OomAction::Reset => cortex_m::peripheral::SCB::sys_reset(),
+        }
     }
 }
 
 /* The allocation error handler, needed by the `alloc` crate */
 
+#[cfg(not(test))]
 #[alloc_error_handler]
 fn alloc_error_handler(layout: alloc::alloc::Layout) -> ! {
     panic!("allocation error: {:?}", layout);
@@ -0,0 +1,80 @@
+use crate::error::KernelError;
+use crate::mutex::{CriticalSection, Mutex};
+use crate::task::Queue;
+use crate::wait::{self, WAIT_FOREVER};
+use core::marker::Sync;
+
+/*
+A Barrier makes a group of `parties` tasks wait for each other: every task
+that calls `wait` is blocked until the last one of the group arrives, then
+they are all released together and the barrier can be used again by the
+next round, e.g. for the next phase of a pipeline.
+
+A task may give up waiting when its timeout expires: it is then no longer
+counted among the tasks that have arrived, so the others keep waiting for
+a full group.
+*/
+pub struct Barrier {
+    inner: Mutex<BarrierState>,
+}
+
+struct BarrierState {
+    parties: usize, //number of tasks that must arrive to release the group
+    arrived: usize, //number of tasks waiting for the current round
+    waiters: Queue,
+}
+
+impl Barrier {
+    pub const fn new(parties: usize) -> Self {
+        Self {
+            inner: Mutex::new(BarrierState {
+                parties,
+                arrived: 0,
+                waiters: Queue::new(),
+            }),
+        }
+    }
+
+    /* Waits for as long as needed for the rest of the group to arrive */
+    pub fn wait(&self) -> Result<(), KernelError> {
+        self.wait_timeout(WAIT_FOREVER)
+    }
+
+    /*
+    Waits at most `timeout` ticks for the rest of the group to arrive. The
+    last task to arrive does not wait, it releases the others instead.
+    */
+    pub fn wait_timeout(&self, timeout: u32) -> Result<(), KernelError> {
+        {
+            let _section = CriticalSection::enter();
+            let mut state = self.inner.lock();
+
+            if state.arrived + 1 >= state.parties {
+                state.arrived = 0;
+                while wait::wake_first(&mut state.waiters, Ok(())).is_some() {}
+                return Ok(());
+            }
+            let object = self as *const Self as *const ();
+            wait::block_running_with_hook(&mut state.waiters, timeout, object, Self::task_left)?;
+            state.arrived += 1;
+        }
+        wait::wait_for_wakeup()
+    }
+
+    /* Returns the number of tasks waiting for the current round */
+    pub fn waiting_tasks(&self) -> usize {
+        self.inner.lock().arrived
+    }
+
+    pub fn parties(&self) -> usize {
+        self.inner.lock().parties
+    }
+
+    // Called when a waiting task gives up, because of its timeout
+    unsafe fn task_left(barrier: *const ()) {
+        let barrier = &*(barrier as *const Self);
+        barrier.inner.lock().arrived -= 1;
+    }
+}
+
+unsafe impl Sync for Barrier {}
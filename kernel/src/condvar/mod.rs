@@ -0,0 +1,78 @@
+use crate::error::KernelError;
+use crate::mutex::{CriticalSection, Mutex};
+use crate::task::{self, CallerContext, Queue};
+use crate::task_mutex::TaskMutex;
+use crate::wait::{self, WAIT_FOREVER};
+use core::marker::Sync;
+
+/*
+A condition variable lets a task wait, while holding a TaskMutex, until
+another task signals that the state protected by the mutex has changed.
+
+`wait` releases the mutex and blocks the task in a single step, with the
+kernel interrupts masked, so that a notification sent right after the
+mutex is released cannot get lost. When the task is woken up it locks the
+mutex again before returning, even if the wait timed out. As usual with
+condition variables, the waiting task should check its condition again
+once `wait` returns.
+
+The mutex must be locked exactly once by the caller: a recursive mutex that
+was locked more than once would not be released while waiting.
+*/
+pub struct Condvar {
+    waiters: Mutex<Queue>, //tasks waiting for a notification, sorted by priority
+}
+
+impl Condvar {
+    pub const fn new() -> Self {
+        Self { waiters: Mutex::new(Queue::new()) }
+    }
+
+    /* Waits for a notification for as long as needed */
+    pub fn wait(&self, mutex: &TaskMutex) -> Result<(), KernelError> {
+        self.wait_timeout(mutex, WAIT_FOREVER)
+    }
+
+    /*
+    Waits at most `timeout` ticks for a notification. Returns Timeout if no
+    notification came, NotOwner if the caller does not own the mutex, or
+    WouldBlock if the caller is not a task running its own code, which
+    could not lock the mutex again. Unless NotOwner or WouldBlock is
+    returned, the mutex is locked again on return.
+    */
+    pub fn wait_timeout(&self, mutex: &TaskMutex, timeout: u32) -> Result<(), KernelError> {
+        if task::caller_context() != CallerContext::Task {
+            return Err(KernelError::WouldBlock);
+        }
+        let blocked = {
+            let _section = CriticalSection::enter();
+            mutex.unlock()?;
+            wait::block_running(&mut self.waiters.lock(), timeout)
+        };
+        let result = blocked.and_then(|_| wait::wait_for_wakeup());
+
+        // The caller is a task, so the mutex is eventually locked again
+        while mutex.lock().is_err() {}
+        result
+    }
+
+    /* Wakes up the waiting task with the highest priority, if any */
+    pub fn notify_one(&self) {
+        let _section = CriticalSection::enter();
+        wait::wake_first(&mut self.waiters.lock(), Ok(()));
+    }
+
+    /* Wakes up all the waiting tasks */
+    pub fn notify_all(&self) {
+        let _section = CriticalSection::enter();
+        let mut waiters = self.waiters.lock();
+        while wait::wake_first(&mut waiters, Ok(())).is_some() {}
+    }
+
+    /* Returns the number of tasks waiting for a notification */
+    pub fn waiting_tasks(&self) -> usize {
+        self.waiters.lock().count_tasks()
+    }
+}
+
+unsafe impl Sync for Condvar {}
@@ -0,0 +1,18 @@
+/*
+The kernel's configuration. Applications that need different values edit
+them here, and rebuild the kernel.
+*/
+
+// Frequency of the kernel tick, i.e. of the SysTick interrupt
+pub const TICK_RATE_HZ: u32 = 100;
+
+// Time slice given to a task before the next task with the same priority
+// runs, unless the task is created with a quantum of its own
+pub const DEFAULT_QUANTUM_MS: u32 = 100;
+
+// Quantum that turns round robin off for a task: it runs until it blocks,
+// or a task with a higher priority is ready
+pub const NO_TIME_SLICE: u32 = 0;
+
+// Relative deadline of the tasks that have none, see the `deadline` module
+pub const NO_DEADLINE: u32 = 0;
@@ -0,0 +1,210 @@
+use crate::error::KernelError;
+use crate::mutex::{CriticalSection, Mutex};
+use crate::task::{Queue, TaskTCB, MAX_TASKS, RUNNING, TASK_TABLE};
+use crate::time;
+use crate::wait;
+
+/*
+Deadlines of the tasks. A task created with a relative deadline runs
+jobs: its first job is released when the task is created, and it completes
+a job by calling `complete_job`. The next job is released the next time
+the task becomes ready after a wait. The waits in the middle of a job, e.g.
+for a mutex or a queue, do not complete it: the job must complete within
+`relative_deadline` ticks from its release, whatever it waits for. A task
+with no deadline of its own is never late.
+
+Periodic tasks release a job every `period` ticks instead, whatever they
+block for in the meantime: a job completes when the task calls
+`wait_next_period`, which puts it to sleep until the next release. The
+deadline of each job is relative to its release.
+
+A job that has not completed once its absolute deadline is reached has
+missed it. Deadlines are checked on every tick, when the task blocks, and
+when a job completes:
+each miss is counted in the statistics of the task, and reported to the
+deadline miss hook, if one is set.
+
+Jobs are released by the hooks of the scheduler. With the
+`edf` cargo feature, the tasks with a pending job are scheduled by Earliest
+Deadline First, ahead of all the others, see `scheduler::Edf`.
+*/
+
+/*
+Called with the id of a task whose job has missed its deadline. The hook
+runs inside the kernel, possibly with its interrupts masked: it must be
+short, and it must not block nor invoke system calls.
+*/
+pub type DeadlineMissHook = extern "C" fn(usize);
+
+static mut DEADLINE_MISS_HOOK: Option<DeadlineMissHook> = None;
+
+pub fn set_deadline_miss_hook(hook: Option<DeadlineMissHook>) {
+    unsafe{ DEADLINE_MISS_HOOK = hook };
+}
+
+// The periodic tasks sleeping until their next release
+static mut period_waiters: Mutex<Queue> = Mutex::new(Queue::new());
+static PERIOD_WAITERS: &Mutex<Queue> = unsafe{&period_waiters};
+
+/*
+Starts a new job of the task at tick `now`, which its deadline is relative
+to, unless its current job has not completed yet. The jobs of periodic
+tasks are released by `wait_next_period` instead.
+*/
+pub fn release(tcb: &mut TaskTCB, now: u32) {
+    if tcb.relative_deadline == 0 || tcb.next_release.is_some() || tcb.deadline.is_some() {
+        return;
+    }
+    tcb.deadline = Some(now.wrapping_add(tcb.relative_deadline));
+    tcb.deadline_missed = false;
+}
+
+/*
+Completes the current job of the task at tick `now`, unless the task is
+periodic: its next job is released when it becomes ready after a wait.
+*/
+pub fn complete(tcb: &mut TaskTCB, now: u32) {
+    check(tcb, now);
+    if tcb.next_release.is_none() {
+        tcb.deadline = None;
+    }
+}
+
+/*
+Called by a task with a deadline once its job is done. Periodic tasks call
+`wait_next_period` instead: they and the tasks with no deadline get
+InvalidArgument.
+*/
+pub fn complete_job() -> Result<(), KernelError> {
+    let _section = CriticalSection::enter();
+    let tcb = unsafe{ RUNNING.as_mut() }.ok_or(KernelError::InvalidArgument)?;
+    if tcb.relative_deadline == 0 || tcb.next_release.is_some() {
+        return Err(KernelError::InvalidArgument);
+    }
+    complete(tcb, time::ticks());
+    Ok(())
+}
+
+/*
+Counts a miss if the current job of the task has reached its deadline, and
+calls the deadline miss hook. Each job is counted at most once. Returns
+true if a miss was counted.
+*/
+pub fn check(tcb: &mut TaskTCB, now: u32) -> bool {
+    if !count_miss(tcb, now) {
+        return false;
+    }
+    report_miss(tcb.id);
+    true
+}
+
+// Same as `check`, without calling the hook
+fn count_miss(tcb: &mut TaskTCB, now: u32) -> bool {
+    let deadline = match tcb.deadline {
+        Some(deadline) => deadline,
+        None => return false,
+    };
+    if tcb.deadline_missed || !time::deadline_reached(deadline, now) {
+        return false;
+    }
+    tcb.deadline_missed = true;
+    tcb.deadline_misses = tcb.deadline_misses.wrapping_add(1);
+    true
+}
+
+fn report_miss(id: usize) {
+    if let Some(hook) = unsafe{ DEADLINE_MISS_HOOK } {
+        hook(id);
+    }
+}
+
+/*
+Checks the deadlines of all the tasks, whether they are running, ready or
+blocked. Called by the kernel on every tick. The misses are reported once
+the tasks table is unlocked.
+*/
+pub fn check_tasks() {
+    let mut missed = [0; MAX_TASKS];
+    let mut count = 0;
+    {
+        let _section = CriticalSection::enter();
+        let now = time::ticks();
+        let mut table = TASK_TABLE.lock();
+        for id in 1..=MAX_TASKS {
+            if let Some(tcb) = table.get(id) {
+                if count_miss(tcb, now) {
+                    missed[count] = id;
+                    count += 1;
+                }
+            }
+        }
+    }
+    for &id in &missed[..count] {
+        report_miss(id);
+    }
+}
+
+/*
+Makes the task periodic: its first job is released at tick `now`, and the
+next ones every `period` ticks. Each job must complete within
+`relative_deadline` ticks from its release.
+*/
+pub fn start_periodic(tcb: &mut TaskTCB, period: u32, relative_deadline: u32, now: u32) {
+    tcb.period = period;
+    tcb.relative_deadline = relative_deadline;
+    tcb.deadline = Some(now.wrapping_add(relative_deadline));
+    tcb.deadline_missed = false;
+    tcb.next_release = Some(now.wrapping_add(period));
+}
+
+/*
+Completes the current job of a periodic task at tick `now`, and releases
+the next one. Returns the number of ticks until its release, or None if it
+is already due: a job that overran its period is followed right away by
+the next one.
+*/
+pub fn next_period(tcb: &mut TaskTCB, now: u32) -> Result<Option<u32>, KernelError> {
+    let release = tcb.next_release.ok_or(KernelError::InvalidArgument)?;
+    check(tcb, now);
+
+    tcb.deadline = Some(release.wrapping_add(tcb.relative_deadline));
+    tcb.deadline_missed = false;
+    tcb.next_release = Some(release.wrapping_add(tcb.period));
+    if time::deadline_reached(release, now) {
+        Ok(None)
+    } else {
+        Ok(Some(release.wrapping_sub(now)))
+    }
+}
+
+/*
+Called by a periodic task once its job is done: the task sleeps until its
+next job is released. Tasks that are not periodic get InvalidArgument.
+*/
+pub fn wait_next_period() -> Result<(), KernelError> {
+    {
+        let _section = CriticalSection::enter();
+        let tcb = unsafe{ RUNNING.as_mut() }.ok_or(KernelError::WouldBlock)?;
+        match next_period(tcb, time::ticks())? {
+            None => return Ok(()),
+            Some(ticks) => wait::block_running(&mut PERIOD_WAITERS.lock(), ticks)?,
+        }
+    }
+    // The wait always ends with the timeout, at the release of the job
+    match wait::wait_for_wakeup() {
+        Err(KernelError::Timeout) => Ok(()),
+        result => result,
+    }
+}
+
+/*
+Returns true if deadline `a` comes strictly before deadline `b`. No
+deadline comes after any deadline.
+*/
+pub fn earlier(a: Option<u32>, b: Option<u32>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => (a.wrapping_sub(b) as i32) < 0,
+        (Some(_), None) => true,
+        (None, _) => false,
+    }
+}
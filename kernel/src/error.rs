@@ -0,0 +1,39 @@
+/*
+The errors reported by the kernel to the application.
+
+System calls return 0 on success, otherwise the numeric code of one of
+these errors, so that they can be checked from C as well.
+*/
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KernelError {
+    OutOfMemory = 1,
+    TooManyTasks = 2,
+    InvalidArgument = 3,
+    // The timeout expired before the wait was satisfied
+    Timeout = 4,
+    // The call would have blocked, and no waiting was allowed
+    WouldBlock = 5,
+    // The calling task does not own the object
+    NotOwner = 6,
+    // The call would make the calling task wait for itself forever
+    Deadlock = 7,
+    // The object cannot take any more items, e.g. a semaphore at its max count
+    Full = 8,
+    // The kernel object registry is full
+    TooManyObjects = 9,
+    // Internal: the calling task was blocked by a system call, and the
+    // outcome of the wait is only known once the task is resumed. It is
+    // never returned to the application.
+    Pending = 255,
+}
+
+pub const SUCCESS: usize = 0;
+
+/* Converts the result of a kernel service into the status returned to the caller */
+pub fn status(result: Result<(), KernelError>) -> usize {
+    match result {
+        Ok(()) => SUCCESS,
+        Err(error) => error as usize,
+    }
+}
@@ -0,0 +1,149 @@
+use crate::error::KernelError;
+use crate::mutex::{CriticalSection, Mutex};
+use crate::task::{Queue, MAX_TASKS, RUNNING};
+use crate::wait;
+use core::marker::Sync;
+
+// Wait options: wait for all the requested bits, rather than any of them
+pub const EVENT_WAIT_ALL: u32 = 1 << 0;
+// Wait options: clear the requested bits once the wait is satisfied
+pub const EVENT_CLEAR_ON_EXIT: u32 = 1 << 1;
+
+/*
+An EventGroup holds 32 event flags. Tasks wait for any, or all, of a set of
+flags to be set, and are woken up as soon as their condition is met. This
+way a task can wait for combinations of events signalled by several other
+tasks, or interrupt handlers.
+
+When the flags are set, the condition of every waiting task is checked
+against the same value: all the tasks whose condition is met are woken up,
+and only then the flags they asked to clear on exit are cleared. A woken
+task receives the value of the flags that satisfied its wait.
+
+The condition of a waiting task is stored in its TCB: `wait_value` holds
+the flags it waits for, `wait_options` the wait options, and `wait_buffer`
+points to where the value of the flags is written when it's woken up.
+*/
+pub struct EventGroup {
+    inner: Mutex<EventGroupState>,
+}
+
+struct EventGroupState {
+    bits: u32,
+    waiters: Queue, //tasks waiting for some flags, sorted by priority
+}
+
+impl EventGroup {
+    pub const fn new() -> Self {
+        Self {
+            inner: Mutex::new(EventGroupState { bits: 0, waiters: Queue::new() }),
+        }
+    }
+
+    /* Returns the current value of the flags */
+    pub fn get(&self) -> u32 {
+        self.inner.lock().bits
+    }
+
+    /*
+    Sets the given flags and wakes up the tasks whose condition is now met.
+    Returns the value of the flags after the tasks have been woken up.
+    */
+    pub fn set(&self, bits: u32) -> u32 {
+        let _section = CriticalSection::enter();
+        let mut state = self.inner.lock();
+        state.bits |= bits;
+        let value = state.bits;
+
+        // The satisfied waiters are collected first, as they can't be
+        // removed from the queue while iterating over it
+        let mut woken = [0; MAX_TASKS];
+        let mut count = 0;
+        let mut to_clear = 0;
+        for tcb in state.waiters.iter() {
+            if is_satisfied(value, tcb.wait_value, tcb.wait_options) {
+                woken[count] = tcb.id;
+                count += 1;
+                if tcb.wait_options & EVENT_CLEAR_ON_EXIT != 0 {
+                    to_clear |= tcb.wait_value;
+                }
+            }
+        }
+
+        for id in &woken[..count] {
+            if let Some(tcb) = state.waiters.remove(*id) {
+                unsafe{ (tcb.wait_buffer as *mut u32).write(value) };
+                wait::make_ready(tcb, Ok(()));
+            }
+        }
+
+        state.bits &= !to_clear;
+        state.bits
+    }
+
+    /* Same as `set`, for interrupt handlers (see the `wait` module) */
+    pub fn set_from_isr(&self, bits: u32) -> u32 {
+        self.set(bits)
+    }
+
+    /* Clears the given flags, and returns their value before clearing them */
+    pub fn clear(&self, bits: u32) -> u32 {
+        let mut state = self.inner.lock();
+        let previous = state.bits;
+        state.bits &= !bits;
+        previous
+    }
+
+    /*
+    Waits at most `timeout` ticks for any of the given flags to be set, or
+    for all of them if EVENT_WAIT_ALL is among the options. Returns the value
+    of the flags that satisfied the wait, or Timeout.
+    */
+    pub fn wait(&self, bits: u32, options: u32, timeout: u32) -> Result<u32, KernelError> {
+        let mut value: u32 = 0;
+        self.wait_into(bits, options, timeout, &mut value)?;
+        Ok(value)
+    }
+
+    /*
+    Same as `wait`, but the value of the flags is written to `value`, which
+    must stay valid until the wait is over. Used by the syscall layer.
+    */
+    pub fn wait_into(&self, bits: u32, options: u32, timeout: u32, value: *mut u32) -> Result<(), KernelError> {
+        if bits == 0 {
+            return Err(KernelError::InvalidArgument);
+        }
+        {
+            let _section = CriticalSection::enter();
+            let mut state = self.inner.lock();
+
+            if is_satisfied(state.bits, bits, options) {
+                unsafe{ value.write(state.bits) };
+                if options & EVENT_CLEAR_ON_EXIT != 0 {
+                    state.bits &= !bits;
+                }
+                return Ok(());
+            }
+
+            unsafe {
+                if let Some(tcb) = RUNNING.as_mut() {
+                    tcb.wait_value = bits;
+                    tcb.wait_options = options;
+                }
+            }
+            wait::block_running_with_buffer(&mut state.waiters, timeout, value as *mut u8)?;
+        }
+        // When the task is woken up, the value of the flags has been written
+        wait::wait_for_wakeup()
+    }
+}
+
+unsafe impl Sync for EventGroup {}
+
+fn is_satisfied(value: u32, bits: u32, options: u32) -> bool {
+    if options & EVENT_WAIT_ALL != 0 {
+        value & bits == bits
+    } else {
+        value & bits != 0
+    }
+}
@@ -0,0 +1,212 @@
+use crate::error::KernelError;
+use crate::mutex::Mutex;
+use crate::syscalls;
+use crate::task::{TaskTCB, KERNEL_ID, RUNNING};
+use alloc::boxed::Box;
+#[cfg(feature = "tickless-idle")]
+use crate::{time, timer::TIMER_LIST, wait::{self, TIMEOUT_LIST}, WAITING_QUEUE};
+#[cfg(feature = "tickless-idle")]
+use cortex_m::peripheral::{SCB, SYST};
+
+// Priority of the idle task, the lowest one
+pub const IDLE_PRIORITY: usize = 0;
+
+// Number of ticks over which the CPU load is measured
+pub const CPU_LOAD_WINDOW: u32 = 1000;
+
+// Number of idle ticks below which stopping SysTick is not worth it
+#[cfg(feature = "tickless-idle")]
+pub const TICKLESS_MIN_IDLE_TICKS: u32 = 2;
+
+// Largest value the 24-bit SysTick counter can be reloaded with
+#[cfg(feature = "tickless-idle")]
+const SYST_MAX_RELOAD: u32 = 0x00FF_FFFF;
+
+/*
+The idle task runs whenever no other task is ready, so that the scheduler
+always has a task to switch to. It is created by `kernel_init`.
+
+It is never in the ready queues: while it is not running, its TCB is parked
+here, and the scheduler picks it only when the ready queues are empty. On
+every iteration it runs the idle hook registered by the application, if
+any, then it puts the CPU to sleep until the next interrupt.
+
+The ticks during which the idle task was running are counted, so that the
+CPU load can be reported: it is the share of the last CPU_LOAD_WINDOW ticks
+during which some other task was running.
+
+With the `tickless-idle` feature, SysTick is also stopped while sleeping:
+the idle task looks up the next deadline among the blocked tasks and the
+software timers, programs SysTick to fire only then, and once woken up
+advances the kernel's time by the ticks that were skipped.
+*/
+static mut idle_task: Option<Box<TaskTCB>> = None;
+
+// Id of the idle task, KERNEL_ID until it is created
+static mut idle_id: usize = KERNEL_ID;
+
+/*
+The function run by the idle task on every iteration, e.g. to kick a
+watchdog or to do low-priority housekeeping. It must return quickly, and
+it must never block, as the idle task has to be ready at all times.
+*/
+pub type IdleHook = extern "C" fn();
+
+static mut IDLE_HOOK: Option<IdleHook> = None;
+
+pub fn set_idle_hook(hook: Option<IdleHook>) {
+    unsafe{ IDLE_HOOK = hook };
+}
+
+struct CpuUsage {
+    window_ticks: u32, //ticks elapsed in the current window
+    idle_ticks: u32,   //ticks of the current window spent in the idle task
+    load: u32,         //CPU load of the last complete window, in percent
+}
+
+static mut cpu_usage: Mutex<CpuUsage> = Mutex::new(CpuUsage { window_ticks: 0, idle_ticks: 0, load: 0 });
+static CPU_USAGE: &Mutex<CpuUsage> = unsafe{&cpu_usage};
+
+/*
+Charges `count` ticks to the task that is running, either the idle task or
+any other one. Called by the kernel whenever its time advances.
+*/
+pub fn account_ticks(count: u32) {
+    let mut usage = CPU_USAGE.lock();
+    usage.window_ticks += count;
+    if is_running() {
+        usage.idle_ticks += count;
+    }
+    if usage.window_ticks >= CPU_LOAD_WINDOW {
+        let idle = (usage.idle_ticks as u64 * 100 / usage.window_ticks as u64) as u32;
+        usage.load = 100 - idle;
+        usage.window_ticks = 0;
+        usage.idle_ticks = 0;
+    }
+}
+
+/* Returns the CPU load over the last complete window, in percent */
+pub fn cpu_load() -> u32 {
+    CPU_USAGE.lock().load
+}
+
+/* Creates the idle task */
+pub fn init() -> Result<(), KernelError> {
+    let tcb = syscalls::new_task(idle_main, core::ptr::null_mut(), IDLE_PRIORITY, 0)?;
+    unsafe {
+        idle_id = tcb.id;
+        idle_task = Some(tcb);
+    }
+    Ok(())
+}
+
+/* Returns true if `id` is the id of the idle task */
+pub fn is_idle(id: usize) -> bool {
+    id != KERNEL_ID && id == unsafe{ idle_id }
+}
+
+/* Returns true if the idle task is the RUNNING task */
+pub fn is_running() -> bool {
+    unsafe{ RUNNING.as_ref() }.map_or(false, |tcb| is_idle(tcb.id))
+}
+
+/* Called by the scheduler to switch the idle task in, if nothing else is ready */
+pub(crate) fn take() -> Option<Box<TaskTCB>> {
+    unsafe{ idle_task.take() }
+}
+
+/* Called by the scheduler when the idle task is switched out */
+pub(crate) fn park(tcb: Box<TaskTCB>) {
+    unsafe{ idle_task = Some(tcb) };
+}
+
+fn idle_main(_args: *mut u8) {
+    loop {
+        if let Some(hook) = unsafe{ IDLE_HOOK } {
+            hook();
+        }
+        #[cfg(feature = "tickless-idle")]
+        sleep_tickless();
+        #[cfg(not(feature = "tickless-idle"))]
+        cortex_m::asm::wfi();
+    }
+}
+
+/*
+Returns the number of ticks until the first blocked task times out, or the
+first software timer expires, or None if there is no such deadline.
+*/
+#[cfg(feature = "tickless-idle")]
+fn ticks_to_next_wakeup() -> Option<u32> {
+    let now = time::ticks();
+    let deadlines = [TIMEOUT_LIST.lock().next_deadline(), TIMER_LIST.lock().next_expiry()];
+    deadlines.iter().flatten()
+        .map(|deadline| (deadline.wrapping_sub(now) as i32).max(0) as u32)
+        .min()
+}
+
+/*
+Stops SysTick until the next deadline, and sleeps. Interrupts are disabled
+through PRIMASK rather than BASEPRI, as WFI must still be woken up by the
+interrupts it masks: they are only served once it is enabled again, after
+the tick count has been corrected.
+*/
+#[cfg(feature = "tickless-idle")]
+fn sleep_tickless() {
+    let tick_cycles = time::tick_reload() + 1;
+    cortex_m::interrupt::disable();
+
+    // A task may have been woken up, or a tick may be due, since the idle
+    // task was switched in
+    if !WAITING_QUEUE.empty() || SCB::is_pendst_pending() {
+        wait::request_switch();
+        unsafe{ cortex_m::interrupt::enable() };
+        return;
+    }
+    let max_ticks = SYST_MAX_RELOAD / tick_cycles;
+    let idle_ticks = ticks_to_next_wakeup().unwrap_or(max_ticks).min(max_ticks);
+    if idle_ticks < TICKLESS_MIN_IDLE_TICKS {
+        unsafe{ cortex_m::interrupt::enable() };
+        cortex_m::asm::wfi();
+        return;
+    }
+
+    // SysTick first completes the current tick, then counts the idle ones
+    let mut syst = unsafe{ cortex_m::Peripherals::steal().SYST };
+    syst.disable_counter();
+    let remaining = SYST::get_current();
+    let reload = remaining + tick_cycles * (idle_ticks - 1);
+    syst.set_reload(reload);
+    syst.clear_current();
+    syst.enable_counter();
+
+    cortex_m::asm::dsb();
+    cortex_m::asm::wfi();
+    cortex_m::asm::isb();
+
+    syst.disable_counter();
+    let skipped = if syst.has_wrapped() {
+        // The whole interval has elapsed: the pending SysTick interrupt
+        // counts the last tick
+        syst.set_reload(tick_cycles - 1);
+        syst.clear_current();
+        syst.enable_counter();
+        idle_ticks - 1
+    } else {
+        // Another interrupt woke the CPU up earlier. The ticks that fully
+        // elapsed are counted, and SysTick fires at the end of the current
+        // one, after which it reloads the value of a whole tick
+        let elapsed = reload - SYST::get_current();
+        let (skipped, into_tick) = match elapsed.checked_sub(remaining) {
+            None => (0, tick_cycles - remaining + elapsed),
+            Some(after) => (1 + after / tick_cycles, after % tick_cycles),
+        };
+        syst.set_reload((tick_cycles - into_tick).max(2) - 1);
+        syst.clear_current();
+        syst.enable_counter();
+        syst.set_reload(tick_cycles - 1);
+        skipped
+    };
+    time::step_ticks(skipped);
+    unsafe{ cortex_m::interrupt::enable() };
+}
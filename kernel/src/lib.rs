@@ -1,18 +1,41 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 #![feature(const_mut_refs)]
 #![feature(alloc_error_handler)]
 #![feature(asm_const)]
 #![feature(naked_functions)]
+#![feature(allocator_api)]
 
 extern crate alloc;
 pub mod allocator;
+pub mod barrier;
+pub mod condvar;
+pub mod config;
+pub mod deadline;
+pub mod error;
+pub mod event_group;
+pub mod idle;
+pub mod message_queue;
 pub mod mutex;
+pub mod notification;
+pub mod pool;
+pub mod registry;
+pub mod scheduler;
+pub mod rwlock;
+pub mod semaphore;
+pub mod stats;
+pub mod stream_buffer;
 pub mod task;
+pub mod task_mutex;
 pub mod syscalls;
+pub mod time;
+pub mod timer;
 pub mod utility;
+pub mod wait;
 use core::arch::asm;
-use allocator::LockedHeap;
+use allocator::{LockedHeap, HeapRegionTag, HeapStats, OomHook};
 use task::LockedQueue;
+use idle::IdleHook;
+use deadline::DeadlineMissHook;
 
 
 use cortex_m_rt::exception;
@@ -24,28 +47,68 @@ use cortex_m_semihosting::{hprint, hprintln};
 // The `mut` keyword prevents the linker from placing those variables in FLASH
 // memory, as it would assume them to be read-only. If that was the case,
 // mutating those objects would silently fail.
-#[global_allocator]
+#[cfg_attr(not(test), global_allocator)]
 static mut heap: LockedHeap = LockedHeap::new();
 pub static HEAP: &LockedHeap = unsafe{&heap};
 static mut waiting_queue: LockedQueue = LockedQueue::new();
 pub static WAITING_QUEUE: &LockedQueue = unsafe{&waiting_queue};
 
 use cortex_m::peripheral::syst::SystClkSource;
+use cortex_m::peripheral::scb::SystemHandler;
+use mutex::KERNEL_INTERRUPT_PRIORITY;
+
+// Largest value of the 24-bit SysTick reload register
+const SYST_MAX_RELOAD: u32 = 0x00FF_FFFF;
 
 // The kernel initialization routine, for the time being it just 
-// initializes the heap and the systick peripheral
+// initializes the heap and the systick peripheral. The tick rate is set by
+// `config::TICK_RATE_HZ`, given the frequency of the CPU clock.
 #[no_mangle]
-pub extern "C" fn kernel_init(heap_start : usize, heap_size : usize,  reload_value : u32) {
+pub extern "C" fn kernel_init(heap_start : usize, heap_size : usize,  cpu_clock_hz : u32) {
     unsafe{
         HEAP.init(heap_start, heap_size);
     }
 
+    // The pools for kernel objects are reserved
+    pool::init_kernel_pools(HEAP);
+
+    // The idle task is created, so that the scheduler always has a task to
+    // run: the kernel cannot work without it
+    if idle::init().is_err() {
+        panic!("not enough memory for the idle task");
+    }
+
+    let mut p = cortex_m::Peripherals::take().unwrap();
+
+    // The CPU time of the tasks is measured with the cycle counter, if any
+    stats::init(&mut p.DCB, &mut p.DWT);
+
+    // The exceptions that call into the kernel are given a priority that is
+    // masked by the kernel's critical sections (see the `mutex` module).
+    // SysTick and PendSV have the lowest priority, so that they never
+    // preempt other interrupt handlers: context switches requested by the
+    // kernel only happen once all the handlers have returned.
+    unsafe {
+        p.SCB.set_priority(SystemHandler::SVCall, KERNEL_INTERRUPT_PRIORITY);
+        p.SCB.set_priority(SystemHandler::SysTick, 0xFF);
+        p.SCB.set_priority(SystemHandler::PendSV, 0xFF);
+    }
+
     //systick init
-    let p = cortex_m::Peripherals::take().unwrap();
     let mut syst = p.SYST;
     syst.set_clock_source(SystClkSource::Core);
-    // this is configured for the LM3S6965 which has a default CPU clock of 12 MHz
+    // The SysTick counter is 24 bits wide: a tick must last at least one
+    // cycle, and at most 2^24 of them
+    let cycles_per_tick = cpu_clock_hz / config::TICK_RATE_HZ;
+    if cycles_per_tick == 0 || cycles_per_tick > SYST_MAX_RELOAD + 1 {
+        panic!(
+            "a {} Hz CPU clock cannot generate {} ticks per second with SysTick",
+            cpu_clock_hz, config::TICK_RATE_HZ
+        );
+    }
+    let reload_value = cycles_per_tick - 1;
     syst.set_reload(reload_value);
+    time::set_tick_reload(reload_value);
 
     // // questi tre da fare una volta che il kernel è inizializzato
     // syst.clear_current();
@@ -53,25 +116,292 @@ pub extern "C" fn kernel_init(heap_start : usize, heap_size : usize,  reload_val
     // syst.enable_interrupt();
 }
 
+/*
+Adds a further RAM bank to the kernel's heap, with one of the HeapRegionTag
+values as `tag`. Boards with more than one bank should call this function
+right after `kernel_init`. It returns false if the tag is not valid, or if
+the region could not be added.
+*/
+#[no_mangle]
+pub extern "C" fn kernel_add_heap_region(tag: u32, start: usize, size: usize) -> bool {
+    match HeapRegionTag::from_raw(tag) {
+        Some(tag) => HEAP.add_region(tag, start, size),
+        None => false,
+    }
+}
+
+/*
+Fills `stats` with a snapshot of the heap's statistics, so that they can be
+reported by the application.
+*/
+#[no_mangle]
+pub extern "C" fn get_heap_stats(stats: *mut HeapStats) {
+    if !stats.is_null() {
+        unsafe{ stats.write(HEAP.stats()) };
+    }
+}
+
+/*
+Registers the hook invoked by the kernel when an allocation fails, see the
+`allocator` module for the actions it can take. A null hook restores the
+default behaviour, where the allocation simply fails.
+*/
+#[no_mangle]
+pub extern "C" fn kernel_set_oom_hook(hook: Option<OomHook>) {
+    allocator::set_oom_hook(hook);
+}
+
+/*
+Registers the hook run by the idle task, see the `idle` module. A null hook
+removes it.
+*/
+#[no_mangle]
+pub extern "C" fn kernel_set_idle_hook(hook: Option<IdleHook>) {
+    idle::set_idle_hook(hook);
+}
+
+/*
+Registers the hook called when a job misses its deadline, see the
+`deadline` module. A null hook removes it.
+*/
+#[no_mangle]
+pub extern "C" fn kernel_set_deadline_miss_hook(hook: Option<DeadlineMissHook>) {
+    deadline::set_deadline_miss_hook(hook);
+}
+
+/* Returns the CPU load, in percent, measured over the last CPU_LOAD_WINDOW ticks */
+#[no_mangle]
+pub extern "C" fn get_cpu_load() -> u32 {
+    idle::cpu_load()
+}
+
 #[exception]
+#[cfg(target_arch = "arm")]
 fn SVCall(){
     unsafe{
         asm!(
             "ldr r4, [r7, #40]",
             "ldrb r4, [r4, #-2]",
-            "cmp r4, #01",
+            "cmp r4, #1",
             "itt eq",
             "ldreq r5, =kcreate_task",
             "beq 2f",
+            "cmp r4, #2",
+            "itt eq",
+            "ldreq r5, =kcreate_pool",
+            "beq 2f",
+            "cmp r4, #3",
+            "itt eq",
+            "ldreq r5, =kpool_alloc",
+            "beq 2f",
+            "cmp r4, #4",
+            "itt eq",
+            "ldreq r5, =kpool_free",
+            "beq 2f",
+            "cmp r4, #5",
+            "itt eq",
+            "ldreq r5, =kexit_task",
+            "beq 2f",
+            "cmp r4, #6",
+            "itt eq",
+            "ldreq r5, =kcreate_task_with_quota",
+            "beq 2f",
+            "cmp r4, #7",
+            "itt eq",
+            "ldreq r5, =kwait_result",
+            "beq 2f",
+            "cmp r4, #8",
+            "itt eq",
+            "ldreq r5, =kcreate_task_mutex",
+            "beq 2f",
+            "cmp r4, #9",
+            "itt eq",
+            "ldreq r5, =ktask_mutex_lock",
+            "beq 2f",
+            "cmp r4, #10",
+            "itt eq",
+            "ldreq r5, =ktask_mutex_unlock",
+            "beq 2f",
+            "cmp r4, #11",
+            "itt eq",
+            "ldreq r5, =kcreate_semaphore",
+            "beq 2f",
+            "cmp r4, #12",
+            "itt eq",
+            "ldreq r5, =ksemaphore_take",
+            "beq 2f",
+            "cmp r4, #13",
+            "itt eq",
+            "ldreq r5, =ksemaphore_give",
+            "beq 2f",
+            "cmp r4, #14",
+            "itt eq",
+            "ldreq r5, =kcreate_message_queue",
+            "beq 2f",
+            "cmp r4, #15",
+            "itt eq",
+            "ldreq r5, =kmessage_queue_send",
+            "beq 2f",
+            "cmp r4, #16",
+            "itt eq",
+            "ldreq r5, =kmessage_queue_receive",
+            "beq 2f",
+            "cmp r4, #17",
+            "itt eq",
+            "ldreq r5, =kmessage_queue_peek",
+            "beq 2f",
+            "cmp r4, #18",
+            "itt eq",
+            "ldreq r5, =kcreate_event_group",
+            "beq 2f",
+            "cmp r4, #19",
+            "itt eq",
+            "ldreq r5, =kevent_group_set",
+            "beq 2f",
+            "cmp r4, #20",
+            "itt eq",
+            "ldreq r5, =kevent_group_clear",
+            "beq 2f",
+            "cmp r4, #21",
+            "itt eq",
+            "ldreq r5, =kevent_group_wait",
+            "beq 2f",
+            "cmp r4, #22",
+            "itt eq",
+            "ldreq r5, =knotify",
+            "beq 2f",
+            "cmp r4, #23",
+            "itt eq",
+            "ldreq r5, =knotify_wait",
+            "beq 2f",
+            "cmp r4, #24",
+            "itt eq",
+            "ldreq r5, =kget_task_id",
+            "beq 2f",
+            "cmp r4, #25",
+            "itt eq",
+            "ldreq r5, =kcreate_stream_buffer",
+            "beq 2f",
+            "cmp r4, #26",
+            "itt eq",
+            "ldreq r5, =kstream_buffer_write",
+            "beq 2f",
+            "cmp r4, #27",
+            "itt eq",
+            "ldreq r5, =kstream_buffer_read",
+            "beq 2f",
+            "cmp r4, #28",
+            "itt eq",
+            "ldreq r5, =kstream_buffer_wait",
+            "beq 2f",
+            "cmp r4, #29",
+            "itt eq",
+            "ldreq r5, =kcreate_barrier",
+            "beq 2f",
+            "cmp r4, #30",
+            "itt eq",
+            "ldreq r5, =kbarrier_wait",
+            "beq 2f",
+            "cmp r4, #31",
+            "itt eq",
+            "ldreq r5, =kcreate_timer",
+            "beq 2f",
+            "cmp r4, #32",
+            "itt eq",
+            "ldreq r5, =ktimer_start",
+            "beq 2f",
+            "cmp r4, #33",
+            "itt eq",
+            "ldreq r5, =ktimer_stop",
+            "beq 2f",
+            "cmp r4, #34",
+            "itt eq",
+            "ldreq r5, =ktimer_reset",
+            "beq 2f",
+            "cmp r4, #35",
+            "itt eq",
+            "ldreq r5, =ktimer_change_period",
+            "beq 2f",
+            "cmp r4, #36",
+            "itt eq",
+            "ldreq r5, =kcreate_task_with_quantum",
+            "beq 2f",
+            "cmp r4, #37",
+            "itt eq",
+            "ldreq r5, =kset_priority",
+            "beq 2f",
+            "cmp r4, #38",
+            "itt eq",
+            "ldreq r5, =kget_priority",
+            "beq 2f",
+            "cmp r4, #39",
+            "itt eq",
+            "ldreq r5, =ksuspend_task",
+            "beq 2f",
+            "cmp r4, #40",
+            "itt eq",
+            "ldreq r5, =kresume_task",
+            "beq 2f",
+            "cmp r4, #41",
+            "itt eq",
+            "ldreq r5, =kcreate_task_with_deadline",
+            "beq 2f",
+            "cmp r4, #42",
+            "itt eq",
+            "ldreq r5, =kcreate_periodic_task",
+            "beq 2f",
+            "cmp r4, #43",
+            "itt eq",
+            "ldreq r5, =kwait_next_period",
+            "beq 2f",
+            "cmp r4, #44",
+            "itt eq",
+            "ldreq r5, =knotify_take",
+            "beq 2f",
+            "cmp r4, #45",
+            "itt eq",
+            "ldreq r5, =kcomplete_job",
+            "beq 2f",
             "ldr r5, =unknownService",
             "2:",
             "str lr, [sp, #-4]!",
             "blx r5",
+            // The value returned by the service is written over the stacked
+            // r0, so that the caller finds it in r0 once the handler returns
+            "str r0, [r7, #16]",
             "ldr pc, [sp], #4",
         );
     }
 }
 
+/*
+SysTick advances the kernel's time, charges the running task for its CPU
+time, checks the deadlines of the tasks, and ends the time slice of the
+running task when its quantum is over. The first tick starts the scheduler.
+*/
+#[exception]
+fn SysTick(){
+    time::kernel_tick();
+    stats::tick();
+    deadline::check_tasks();
+    if unsafe{ task::CURRENT.is_null() } || task::time_slice_tick() {
+        cortex_m::peripheral::SCB::set_pendsv();
+    }
+}
+
+/*
+PendSV carries out the context switches requested by the kernel, e.g. when
+a task blocks or when a task with a higher priority is woken up.
+*/
+#[exception]
+#[cfg(target_arch = "arm")]
+fn PendSV(){
+    unsafe{
+        syscalls::task_switch();
+        asm!("POP {{pc}}");
+    }
+}
+
 /* Add this code block when implementing a new service
 
     "cmp r0, #numeric_code",
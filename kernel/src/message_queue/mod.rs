@@ -0,0 +1,253 @@
+use crate::error::KernelError;
+use crate::mutex::{CriticalSection, Mutex};
+use crate::task::Queue;
+use crate::utility::memcpy;
+use crate::wait::{self, NO_WAIT};
+use alloc::alloc::{alloc, dealloc, Layout};
+use core::marker::{PhantomData, Sync};
+use core::mem::{self, MaybeUninit};
+
+// Alignment of the buffer of a message queue, enough for any item type
+const MESSAGE_QUEUE_ALIGN: usize = mem::align_of::<u64>();
+
+/*
+A MessageQueue passes items of a fixed size between tasks, by copy. The
+items are stored in a ring buffer, allocated from the heap when the queue
+is created, and they are received in the order they were sent.
+
+- A task sending to a full queue waits until there is room, and a task
+  receiving from an empty queue waits until an item is sent, in both cases
+  for at most the given timeout. A timeout of NO_WAIT makes the call fail
+  with WouldBlock instead, which is how the non-blocking variants work.
+- Waiting tasks are kept sorted by priority. The item is handed over
+  directly to, or taken directly from, the buffer of the waiting task with
+  the highest priority, so that no other task can get in the way.
+
+MsgQueue<T> is the typed front-end to a MessageQueue.
+*/
+pub struct MessageQueue {
+    inner: Mutex<MessageQueueState>,
+}
+
+struct MessageQueueState {
+    buffer: *mut u8,  //ring buffer, `capacity` items long
+    item_size: usize,
+    capacity: usize,
+    head: usize,      //index of the oldest item
+    len: usize,       //number of items in the buffer
+    senders: Queue,   //tasks waiting for room, sorted by priority
+    receivers: Queue, //tasks waiting for an item, sorted by priority
+}
+
+impl MessageQueue {
+    /*
+    Creates a queue of `capacity` items, each `item_size` bytes large.
+    Fails with OutOfMemory if the buffer cannot be allocated.
+    */
+    pub fn new(item_size: usize, capacity: usize) -> Result<Self, KernelError> {
+        if item_size == 0 || capacity == 0 {
+            return Err(KernelError::InvalidArgument);
+        }
+        let layout = Self::layout(item_size, capacity)?;
+        let buffer = unsafe{ alloc(layout) };
+        if buffer.is_null() {
+            return Err(KernelError::OutOfMemory);
+        }
+
+        Ok(Self {
+            inner: Mutex::new(MessageQueueState {
+                buffer,
+                item_size,
+                capacity,
+                head: 0,
+                len: 0,
+                senders: Queue::new(),
+                receivers: Queue::new(),
+            }),
+        })
+    }
+
+    fn layout(item_size: usize, capacity: usize) -> Result<Layout, KernelError> {
+        let size = item_size.checked_mul(capacity).ok_or(KernelError::InvalidArgument)?;
+        Layout::from_size_align(size, MESSAGE_QUEUE_ALIGN).map_err(|_| KernelError::InvalidArgument)
+    }
+
+    /*
+    Sends the item pointed to by `item`, waiting at most `timeout` ticks
+    for room in the queue. Returns Timeout if the queue stayed full.
+    */
+    pub fn send(&self, item: *const u8, timeout: u32) -> Result<(), KernelError> {
+        {
+            let _section = CriticalSection::enter();
+            let mut state = self.inner.lock();
+
+            // A task waiting for an item gets it right away
+            if let Some(receiver) = state.receivers.dequeue() {
+                unsafe{ memcpy(item, receiver.wait_buffer, state.item_size) };
+                wait::make_ready(receiver, Ok(()));
+                return Ok(());
+            }
+            if state.len < state.capacity {
+                state.push(item);
+                return Ok(());
+            }
+            wait::block_running_with_buffer(&mut state.senders, timeout, item as *mut u8)?;
+        }
+        // When the task is woken up by a receiver, its item has been queued
+        wait::wait_for_wakeup()
+    }
+
+    /* Sends an item only if that does not require waiting */
+    pub fn try_send(&self, item: *const u8) -> Result<(), KernelError> {
+        self.send(item, NO_WAIT)
+    }
+
+    /* Same as `try_send`, for interrupt handlers (see the `wait` module) */
+    pub fn send_from_isr(&self, item: *const u8) -> Result<(), KernelError> {
+        self.try_send(item)
+    }
+
+    /*
+    Receives the oldest item into `buffer`, waiting at most `timeout` ticks
+    for one to be sent. Returns Timeout if the queue stayed empty.
+    */
+    pub fn receive(&self, buffer: *mut u8, timeout: u32) -> Result<(), KernelError> {
+        {
+            let _section = CriticalSection::enter();
+            let mut state = self.inner.lock();
+
+            if state.len > 0 {
+                state.pop(buffer);
+
+                // The room left is taken by the first task waiting for it
+                if let Some(sender) = state.senders.dequeue() {
+                    state.push(sender.wait_buffer);
+                    wait::make_ready(sender, Ok(()));
+                }
+                return Ok(());
+            }
+            wait::block_running_with_buffer(&mut state.receivers, timeout, buffer)?;
+        }
+        // When the task is woken up by a sender, the item is in its buffer
+        wait::wait_for_wakeup()
+    }
+
+    /* Receives an item only if that does not require waiting */
+    pub fn try_receive(&self, buffer: *mut u8) -> Result<(), KernelError> {
+        self.receive(buffer, NO_WAIT)
+    }
+
+    /*
+    Copies the oldest item into `buffer` without removing it from the
+    queue. Returns WouldBlock if the queue is empty.
+    */
+    pub fn peek(&self, buffer: *mut u8) -> Result<(), KernelError> {
+        let state = self.inner.lock();
+        if state.len == 0 {
+            return Err(KernelError::WouldBlock);
+        }
+        unsafe{ memcpy(state.slot(state.head), buffer, state.item_size) };
+        Ok(())
+    }
+
+    /* Returns the number of items in the queue */
+    pub fn len(&self) -> usize {
+        self.inner.lock().len
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.inner.lock().capacity
+    }
+
+    pub fn item_size(&self) -> usize {
+        self.inner.lock().item_size
+    }
+}
+
+impl MessageQueueState {
+    fn slot(&self, index: usize) -> *mut u8 {
+        unsafe{ self.buffer.add(index * self.item_size) }
+    }
+
+    // the item is copied at the end of the ring buffer, which must not be full
+    fn push(&mut self, item: *const u8) {
+        let tail = (self.head + self.len) % self.capacity;
+        unsafe{ memcpy(item, self.slot(tail), self.item_size) };
+        self.len += 1;
+    }
+
+    // the oldest item is moved out of the ring buffer, which must not be empty
+    fn pop(&mut self, buffer: *mut u8) {
+        unsafe{ memcpy(self.slot(self.head), buffer, self.item_size) };
+        self.head = (self.head + 1) % self.capacity;
+        self.len -= 1;
+    }
+}
+
+impl Drop for MessageQueue {
+    fn drop(&mut self) {
+        let state = self.inner.lock();
+        if let Ok(layout) = Self::layout(state.item_size, state.capacity) {
+            unsafe{ dealloc(state.buffer, layout) };
+        }
+    }
+}
+
+unsafe impl Sync for MessageQueue {}
+
+/*
+The typed front-end to a MessageQueue: items of type T are sent and
+received by value.
+*/
+pub struct MsgQueue<T: Copy> {
+    queue: MessageQueue,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Copy> MsgQueue<T> {
+    pub fn new(capacity: usize) -> Result<Self, KernelError> {
+        if mem::align_of::<T>() > MESSAGE_QUEUE_ALIGN {
+            return Err(KernelError::InvalidArgument);
+        }
+        Ok(Self {
+            queue: MessageQueue::new(mem::size_of::<T>(), capacity)?,
+            _marker: PhantomData,
+        })
+    }
+
+    pub fn send(&self, item: T, timeout: u32) -> Result<(), KernelError> {
+        self.queue.send(&item as *const T as *const u8, timeout)
+    }
+
+    pub fn try_send(&self, item: T) -> Result<(), KernelError> {
+        self.queue.try_send(&item as *const T as *const u8)
+    }
+
+    pub fn send_from_isr(&self, item: T) -> Result<(), KernelError> {
+        self.queue.send_from_isr(&item as *const T as *const u8)
+    }
+
+    pub fn receive(&self, timeout: u32) -> Result<T, KernelError> {
+        let mut item = MaybeUninit::<T>::uninit();
+        self.queue.receive(item.as_mut_ptr() as *mut u8, timeout)?;
+        Ok(unsafe{ item.assume_init() })
+    }
+
+    pub fn try_receive(&self) -> Result<T, KernelError> {
+        self.receive(NO_WAIT)
+    }
+
+    pub fn peek(&self) -> Result<T, KernelError> {
+        let mut item = MaybeUninit::<T>::uninit();
+        self.queue.peek(item.as_mut_ptr() as *mut u8)?;
+        Ok(unsafe{ item.assume_init() })
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+}
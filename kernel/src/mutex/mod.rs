@@ -1,31 +1,121 @@
+use core::cell::UnsafeCell;
 use core::ops::{Deref, DerefMut};
-use cortex_m::interrupt::{enable, disable};
 use core::marker::Sync;
 
+/*
+Interrupts whose priority is numerically lower than this value are never
+masked by the kernel's critical sections: they keep running with minimal
+latency, but they must never call into the kernel. All the interrupts that
+do call the kernel (SysTick and PendSV included) must have a priority equal
+to or greater than KERNEL_INTERRUPT_PRIORITY.
+
+The value is given in the 8-bit format used by the NVIC. Only the most
+significant bits are implemented (three on the LM3S6965).
+*/
+pub const KERNEL_INTERRUPT_PRIORITY: u8 = 0x40;
+
+/*
+A critical section masks all the interrupts that may call into the kernel,
+and restores the previous masking state when it's dropped. Critical
+sections can therefore be nested freely: leaving the inner one does not
+re-enable interrupts that the outer one had masked.
+
+By default the kernel raises BASEPRI to KERNEL_INTERRUPT_PRIORITY. With the
+`primask-critical-section` feature (e.g. on ARMv6-M cores, which have no
+BASEPRI register) all the interrupts are masked through PRIMASK instead.
+
+System calls must never be invoked inside a critical section, nor while a
+`Mutex` is locked: SVCall runs at KERNEL_INTERRUPT_PRIORITY, so the
+masking level that protects the kernel also masks the `svc` instruction,
+which escalates to a HardFault. See `kernel_interrupts_masked`.
+*/
+pub struct CriticalSection {
+    previous: u8,
+}
+
+impl CriticalSection {
+    #[cfg(not(feature = "primask-critical-section"))]
+    pub fn enter() -> Self {
+        use cortex_m::register::{basepri, basepri_max};
+
+        let previous = basepri::read();
+        // BASEPRI_MAX only raises the masking level, so a critical
+        // section entered from a more restrictive context leaves it as is
+        basepri_max::write(KERNEL_INTERRUPT_PRIORITY);
+        Self { previous }
+    }
+
+    #[cfg(feature = "primask-critical-section")]
+    pub fn enter() -> Self {
+        use cortex_m::register::primask;
+
+        let previous = primask::read().is_inactive() as u8;
+        cortex_m::interrupt::disable();
+        Self { previous }
+    }
+}
+
+impl Drop for CriticalSection {
+    #[cfg(not(feature = "primask-critical-section"))]
+    fn drop(&mut self) {
+        unsafe{ cortex_m::register::basepri::write(self.previous) };
+    }
+
+    #[cfg(feature = "primask-critical-section")]
+    fn drop(&mut self) {
+        // Interrupts are enabled again only if they were enabled when the
+        // critical section was entered
+        if self.previous == 0 {
+            unsafe{ cortex_m::interrupt::enable() };
+        }
+    }
+}
+
+/* Returns true if the interrupts that call into the kernel are currently masked */
+pub fn kernel_interrupts_masked() -> bool {
+    if cortex_m::register::primask::read().is_inactive() {
+        return true;
+    }
+
+    #[cfg(not(feature = "primask-critical-section"))]
+    {
+        let basepri = cortex_m::register::basepri::read();
+        if basepri != 0 && basepri <= KERNEL_INTERRUPT_PRIORITY {
+            return true;
+        }
+    }
+    false
+}
+
+// A critical section that lives as long as the lock, see `CriticalSection`
+// for what must not be done while holding it
 pub struct Mutex<T> {
-    inner: T,
+    inner: UnsafeCell<T>,
 }
 
+// When the guard is dropped, its critical section is dropped as well, and
+// the previous interrupt masking state is restored
 pub struct MutexGuard<'a, T> {
     mutex: &'a Mutex<T>,
+    _section: CriticalSection,
 }
 
 impl<T> Mutex<T> {
     pub const fn new(inner: T) -> Self {
-        Self {inner: inner}
+        Self {inner: UnsafeCell::new(inner)}
     }
 
     pub fn lock(&self) -> MutexGuard<T> {
-        disable(); // Disable interrupts
-        MutexGuard::new(self) 
+        let section = CriticalSection::enter(); // Mask interrupts
+        MutexGuard::new(self, section)
     }
 }
 
 unsafe impl<T> Sync for Mutex<T> {}
 
 impl<'a, T> MutexGuard<'a, T> {
-    pub fn new(mutex: &'a Mutex<T>) -> Self {
-        Self {mutex: mutex}
+    pub fn new(mutex: &'a Mutex<T>, section: CriticalSection) -> Self {
+        Self {mutex: mutex, _section: section}
     }
 }
 
@@ -33,18 +123,12 @@ impl<T> Deref for MutexGuard<'_, T> {
     type Target = T;
 
     fn deref(&self) -> &T {
-        unsafe { & *(&self.mutex.inner as *const T) }
+        unsafe { &*self.mutex.inner.get() }
     }
 }
 
 impl<T> DerefMut for MutexGuard<'_, T> {
     fn deref_mut(&mut self) -> &mut T {
-        unsafe { &mut *(&self.mutex.inner as *const T as *mut T) }
-    }
-}
-
-impl<T> Drop for MutexGuard<'_, T> {
-    fn drop(&mut self) {
-        unsafe{ enable() }; // Enable interrupts
+        unsafe { &mut *self.mutex.inner.get() }
     }
 }
@@ -0,0 +1,152 @@
+use crate::error::KernelError;
+use crate::mutex::{CriticalSection, Mutex};
+use crate::task::{Queue, TaskState, TaskTCB, RUNNING, TASK_TABLE};
+use crate::wait;
+
+/*
+Direct-to-task notifications. Every task has a 32-bit notification word in
+its TCB: other tasks, and interrupt handlers, can update it and wake the
+task up, without creating a separate kernel object. They are the cheapest
+way for one party to signal a specific task, e.g. a driver's interrupt
+handler waking up the task that serves it.
+
+A task is identified by its id. `notify_wait` returns the notification
+word, and resets it to 0. When the word is used as a counter, through the
+`Increment` action, `notify_take` takes one notification at a time
+instead: it decrements the word, so that no notification is lost.
+*/
+
+/* How a notification updates the notification word of the task */
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotifyAction {
+    // The bits of the value are set in the word, like in an event group
+    SetBits = 0,
+    // The word is incremented, like a counting semaphore. The value is ignored
+    Increment = 1,
+    // The word is replaced with the value, like a mailbox
+    Overwrite = 2,
+}
+
+/*
+The tasks waiting for a notification. Each of them waits for its own
+notification, so the order of the queue does not matter.
+*/
+static mut notification_waiters: Mutex<Queue> = Mutex::new(Queue::new());
+static NOTIFICATION_WAITERS: &Mutex<Queue> = unsafe{&notification_waiters};
+
+// Set in the `wait_options` of the tasks waiting in `notify_take`
+const TAKE_ONE: u32 = 1;
+
+/*
+Notifies the task with the given id, updating its notification word. If the
+task is waiting for a notification it is woken up. Returns InvalidArgument
+if there is no such task.
+*/
+pub fn notify(id: usize, value: u32, action: NotifyAction) -> Result<(), KernelError> {
+    let _section = CriticalSection::enter();
+    let tcb = match TASK_TABLE.lock().get(id) {
+        Some(tcb) => tcb as *mut TaskTCB,
+        None => return Err(KernelError::InvalidArgument),
+    };
+
+    unsafe {
+        let tcb = &mut *tcb;
+        tcb.notification_value = match action {
+            NotifyAction::SetBits => tcb.notification_value | value,
+            NotifyAction::Increment => tcb.notification_value.wrapping_add(1),
+            NotifyAction::Overwrite => value,
+        };
+        tcb.notification_pending = true;
+
+        let mut waiters = NOTIFICATION_WAITERS.lock();
+        if tcb.state == TaskState::Blocked && tcb.waiting_on == &mut *waiters as *mut Queue {
+            if tcb.wait_options == TAKE_ONE && tcb.notification_value == 0 {
+                return Ok(());
+            }
+            if let Some(mut block) = waiters.remove(id) {
+                let value = take_notification(&mut block);
+                (block.wait_buffer as *mut u32).write(value);
+                wait::make_ready(block, Ok(()));
+            }
+        }
+    }
+    Ok(())
+}
+
+/* Same as `notify`, for interrupt handlers (see the `wait` module) */
+pub fn notify_from_isr(id: usize, value: u32, action: NotifyAction) -> Result<(), KernelError> {
+    notify(id, value, action)
+}
+
+/*
+Waits at most `timeout` ticks for the running task to be notified, and
+returns its notification word. If the task was notified since its last
+wait, it returns right away.
+*/
+pub fn notify_wait(timeout: u32) -> Result<u32, KernelError> {
+    let mut value: u32 = 0;
+    notify_wait_into(timeout, &mut value)?;
+    Ok(value)
+}
+
+/*
+Same as `notify_wait`, but the notification word is written to `value`,
+which must stay valid until the wait is over. Used by the syscall layer.
+*/
+pub fn notify_wait_into(timeout: u32, value: *mut u32) -> Result<(), KernelError> {
+    wait_notification(timeout, value, 0)
+}
+
+/*
+Waits at most `timeout` ticks for the notification word of the running task
+to be non-zero, then decrements it and returns its previous value: each
+call takes a single notification sent with `Increment`, like a counting
+semaphore.
+*/
+pub fn notify_take(timeout: u32) -> Result<u32, KernelError> {
+    let mut value: u32 = 0;
+    notify_take_into(timeout, &mut value)?;
+    Ok(value)
+}
+
+/* Same as `notify_take`, but the previous value is written to `value` */
+pub fn notify_take_into(timeout: u32, value: *mut u32) -> Result<(), KernelError> {
+    wait_notification(timeout, value, TAKE_ONE)
+}
+
+fn wait_notification(timeout: u32, value: *mut u32, options: u32) -> Result<(), KernelError> {
+    {
+        let _section = CriticalSection::enter();
+        let tcb = unsafe{ RUNNING.as_mut() }.ok_or(KernelError::WouldBlock)?;
+
+        tcb.wait_options = options;
+        let notified = match options {
+            TAKE_ONE => tcb.notification_value != 0,
+            _ => tcb.notification_pending,
+        };
+        if notified {
+            unsafe{ value.write(take_notification(tcb)) };
+            return Ok(());
+        }
+        wait::block_running_with_buffer(&mut NOTIFICATION_WAITERS.lock(), timeout, value as *mut u8)?;
+    }
+    // When the task is woken up, its notification word has been written
+    wait::wait_for_wakeup()
+}
+
+/*
+Returns the notification word of the task, which is reset to 0, or only
+decremented if the task takes one notification at a time.
+*/
+fn take_notification(tcb: &mut TaskTCB) -> u32 {
+    let value = tcb.notification_value;
+    if tcb.wait_options == TAKE_ONE {
+        tcb.notification_value = value.saturating_sub(1);
+        tcb.notification_pending = tcb.notification_value != 0;
+        return value;
+    }
+    tcb.notification_value = 0;
+    tcb.notification_pending = false;
+    value
+}
@@ -0,0 +1,558 @@
+use core::mem;
+use core::cell::UnsafeCell;
+use core::marker::PhantomData;
+use core::mem::{ManuallyDrop, MaybeUninit};
+use core::ops::{Deref, DerefMut};
+use core::ptr;
+use alloc::alloc::Layout;
+use alloc::boxed::Box;
+use crate::mutex::{Mutex, MutexGuard};
+use crate::allocator::{LockedHeap, HEAP_MAX_ALIGN};
+use crate::error::KernelError;
+use crate::task::TaskTCB;
+use crate::barrier::Barrier;
+use crate::event_group::EventGroup;
+use crate::message_queue::MessageQueue;
+use crate::semaphore::Semaphore;
+use crate::stream_buffer::StreamBuffer;
+use crate::task_mutex::TaskMutex;
+use crate::timer::Timer;
+
+pub const POOL_BLOCK_HEADER_SIZE: usize = mem::size_of::<PoolBlock>();
+
+// Number of task control blocks reserved for the kernel at boot
+pub const TCB_POOL_BLOCKS: usize = 4;
+
+// Number of task mutexes reserved for the kernel at boot
+pub const TASK_MUTEX_POOL_BLOCKS: usize = 8;
+
+// Number of semaphores reserved for the kernel at boot
+pub const SEMAPHORE_POOL_BLOCKS: usize = 8;
+
+// Number of message queues reserved for the kernel at boot
+pub const MESSAGE_QUEUE_POOL_BLOCKS: usize = 4;
+
+// Number of event groups reserved for the kernel at boot
+pub const EVENT_GROUP_POOL_BLOCKS: usize = 4;
+
+// Number of stream buffers reserved for the kernel at boot
+pub const STREAM_BUFFER_POOL_BLOCKS: usize = 4;
+
+// Number of barriers reserved for the kernel at boot
+pub const BARRIER_POOL_BLOCKS: usize = 4;
+
+// Number of software timers reserved for the kernel at boot
+pub const TIMER_POOL_BLOCKS: usize = 8;
+
+type BlockLink = Option<&'static mut PoolBlock>;
+
+/*
+PoolBlocks are the 'header' of each block of a pool that is NOT allocated.
+Just like the heap does with its segments, free blocks are kept in a linked
+list that lives inside the blocks themselves, so a pool needs no memory
+besides its own storage.
+*/
+
+pub struct PoolBlock {
+    next: BlockLink,
+}
+
+/*
+A Pool hands out blocks of a single, fixed size, carved from a contiguous
+region of memory. Both allocation and deallocation simply pop/push the head
+of the free list, therefore the region can never get fragmented, and both
+run in constant time. Only the blocks freed by the application, which may
+be freed twice, are also checked against the free list, see `free_checked`.
+
+Blocks that were never handed out are not linked into the free list up
+front: `watermark` counts how many blocks, from the start of the region,
+have been used at least once. This way a pool can be built by a `const fn`,
+before the address of its storage is known.
+*/
+
+pub struct Pool {
+    start: usize,
+    block_size: usize,
+    capacity: usize,
+    watermark: usize,
+    used: usize,
+    free: BlockLink,
+}
+
+/*
+This type wraps a Pool into a mutex, the same way LockedHeap does with the
+Heap. Pools created through the syscall layer are handed to the application
+as pointers to a LockedPool.
+*/
+
+pub struct LockedPool {
+    pool: Mutex<Pool>,
+}
+
+/*
+MemoryPool is the typed front-end to a pool: it owns the storage for N
+objects of type T, and can therefore be declared as a static variable.
+As for the kernel's heap, it should be declared `static mut`, otherwise the
+linker might place it in FLASH memory.
+*/
+
+pub struct MemoryPool<T, const N: usize> {
+    storage: UnsafeCell<MaybeUninit<[PoolSlot<T>; N]>>,
+    pool: LockedPool,
+}
+
+/*
+Each slot of a MemoryPool's storage either holds an object or, when free,
+the header of the block. The union makes sure a slot is large enough and
+correctly aligned for both.
+*/
+
+#[repr(C)]
+union PoolSlot<T> {
+    value: ManuallyDrop<T>,
+    block: ManuallyDrop<PoolBlock>,
+}
+
+/*
+An object allocated from a MemoryPool. Much like a `Box`, it gives access
+to the object, and returns its block to the pool when it goes out of scope.
+*/
+
+pub struct PoolBox<'a, T> {
+    ptr: *mut T,
+    pool: &'a LockedPool,
+    _marker: PhantomData<T>,
+}
+
+impl Pool {
+    pub const fn new(block_size: usize, capacity: usize) -> Self {
+        Self {
+            start: 0,
+            block_size: Self::block_size_for(block_size, mem::align_of::<PoolBlock>()),
+            capacity,
+            watermark: 0,
+            used: 0,
+            free: None,
+        }
+    }
+
+    /*
+    Computes the size of the blocks needed to store objects of the given
+    size and alignment. A block is never smaller than POOL_BLOCK_HEADER_SIZE,
+    and its size is a multiple of the alignment, so that every block in the
+    pool is correctly aligned.
+    */
+
+    pub const fn block_size_for(size: usize, align: usize) -> usize {
+        let size = if size > POOL_BLOCK_HEADER_SIZE { size } else { POOL_BLOCK_HEADER_SIZE };
+        let align = if align > mem::align_of::<PoolBlock>() { align } else { mem::align_of::<PoolBlock>() };
+        (size + align - 1) & !(align - 1)
+    }
+
+    /* Sets the start address of the memory region managed by the pool */
+
+    pub fn init(&mut self, start_address: usize) {
+        self.start = start_address;
+        self.watermark = 0;
+        self.used = 0;
+        self.free = None;
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        self.start != 0
+    }
+
+    /* Allocates a block to the caller */
+
+    pub fn allocate_block(&mut self) -> Option<*mut u8> {
+        if !self.is_initialized() {
+            return None;
+        }
+
+        // Previously freed blocks are reused first
+        if let Some(block) = self.free.take() {
+            self.free = block.next.take();
+            self.used += 1;
+            return Some(block as *mut PoolBlock as *mut u8);
+        }
+
+        // Otherwise the first block that was never used is handed out
+        if self.watermark < self.capacity {
+            let address = self.start + self.watermark * self.block_size;
+            self.watermark += 1;
+            self.used += 1;
+            return Some(address as *mut u8);
+        }
+
+        // The pool is exhausted
+        None
+    }
+
+    /*
+    The block is put back at the head of the free list. Addresses that are
+    not the start of a block handed out by the pool are rejected with
+    InvalidArgument. The block must not be free already.
+    */
+
+    pub fn free_block(&mut self, address: usize) -> Result<(), KernelError> {
+        if !self.contains(address) || (address - self.start) % self.block_size != 0 {
+            return Err(KernelError::InvalidArgument);
+        }
+        if address >= self.start + self.watermark * self.block_size {
+            return Err(KernelError::InvalidArgument);
+        }
+
+        let block = unsafe{ Self::init_block(address) };
+        block.next = self.free.take();
+        self.free = Some(block);
+        self.used -= 1;
+        Ok(())
+    }
+
+    /*
+    Same as `free_block`, but blocks that are already free are rejected with
+    InvalidArgument as well. It walks the free list, so it is only used for
+    the blocks that come from the application, through the `pool_free`
+    system call.
+    */
+
+    pub fn free_checked(&mut self, address: usize) -> Result<(), KernelError> {
+        if self.is_free(address) {
+            return Err(KernelError::InvalidArgument);
+        }
+        self.free_block(address)
+    }
+
+    // returns true if the block is in the free list
+    fn is_free(&self, address: usize) -> bool {
+        let mut link = self.free.as_deref();
+        while let Some(block) = link {
+            if block as *const PoolBlock as usize == address {
+                return true;
+            }
+            link = block.next.as_deref();
+        }
+        false
+    }
+
+    /* Returns true if the address belongs to the pool's memory region */
+
+    pub fn contains(&self, address: usize) -> bool {
+        self.is_initialized() && address >= self.start && address < self.end_address()
+    }
+
+    pub fn start_address(&self) -> usize {
+        self.start
+    }
+
+    pub fn end_address(&self) -> usize {
+        self.start + self.storage_size()
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /* Size of the memory region needed by the pool */
+
+    pub fn storage_size(&self) -> usize {
+        self.block_size * self.capacity
+    }
+
+    /* Utility function that returns the number of blocks that can still be allocated */
+
+    pub fn available_blocks(&self) -> usize {
+        self.capacity - self.used
+    }
+
+    /*
+    This function copies an empty `PoolBlock` struct at the desired address,
+    while returning a mutable reference to it.
+    */
+
+    unsafe fn init_block(address: usize) -> &'static mut PoolBlock {
+        let address_ptr = address as *mut PoolBlock;
+        address_ptr.write(PoolBlock { next: None });
+        &mut *address_ptr
+    }
+}
+
+impl LockedPool {
+    pub const fn new(block_size: usize, capacity: usize) -> Self {
+        Self { pool: Mutex::new(Pool::new(block_size, capacity)) }
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, Pool> {
+        self.pool.lock()
+    }
+
+    pub fn init(&self, start_address: usize) {
+        self.lock().init(start_address);
+    }
+
+    pub fn alloc(&self) -> Option<*mut u8> {
+        self.lock().allocate_block()
+    }
+
+    pub fn free(&self, ptr: *mut u8) -> Result<(), KernelError> {
+        self.lock().free_block(ptr as usize)
+    }
+
+    pub fn free_checked(&self, ptr: *mut u8) -> Result<(), KernelError> {
+        self.lock().free_checked(ptr as usize)
+    }
+
+    pub fn contains(&self, ptr: *const u8) -> bool {
+        self.lock().contains(ptr as usize)
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.lock().block_size()
+    }
+
+    pub fn available_blocks(&self) -> usize {
+        self.lock().available_blocks()
+    }
+}
+
+impl<T, const N: usize> MemoryPool<T, N> {
+    pub const fn new() -> Self {
+        Self {
+            storage: UnsafeCell::new(MaybeUninit::uninit()),
+            pool: LockedPool::new(mem::size_of::<PoolSlot<T>>(), N),
+        }
+    }
+
+    /*
+    The object is moved into a free block of the pool. None is returned
+    if the pool is exhausted.
+    */
+
+    pub fn alloc(&self, value: T) -> Option<PoolBox<'_, T>> {
+        let ptr = {
+            let mut pool = self.pool.lock();
+
+            // The pool's storage address is only known at runtime, so the
+            // pool is initialized on its first use
+            if !pool.is_initialized() {
+                pool.init(self.storage.get() as usize);
+            }
+            pool.allocate_block()? as *mut T
+        };
+
+        unsafe{ ptr.write(value) };
+        Some(PoolBox { ptr, pool: &self.pool, _marker: PhantomData })
+    }
+
+    pub fn available(&self) -> usize {
+        if self.pool.lock().is_initialized() {
+            self.pool.available_blocks()
+        } else {
+            N
+        }
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+}
+
+unsafe impl<T, const N: usize> Sync for MemoryPool<T, N> {}
+
+impl<T> PoolBox<'_, T> {
+    pub fn as_ptr(&self) -> *mut T {
+        self.ptr
+    }
+}
+
+impl<T> Deref for PoolBox<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.ptr }
+    }
+}
+
+impl<T> DerefMut for PoolBox<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.ptr }
+    }
+}
+
+impl<T> Drop for PoolBox<'_, T> {
+    fn drop(&mut self) {
+        unsafe{ ptr::drop_in_place(self.ptr) };
+        // The block was handed out by the pool, therefore it is valid
+        let _ = self.pool.free(self.ptr as *mut u8);
+    }
+}
+
+/*
+Kernel objects are allocated from fixed-size pools rather than from the
+general purpose heap. Each type of kernel object has a pool of its own,
+which the kernel asks for explicitly through `try_new_pooled`: the
+allocations of the application never take the blocks reserved for the
+kernel, whatever their size. The pools are registered with the global
+allocator, so that the `Box` of a pooled object is given back to its pool
+when dropped, and it never fragments the heap. When a kernel pool is
+exhausted, the object is allocated from the heap instead.
+*/
+
+pub trait PooledObject: Sized {
+    // The kernel pool that holds the objects of this type
+    fn pool() -> &'static LockedPool;
+}
+
+/*
+Moves a kernel object into a block of its kernel pool, or into the heap if
+the pool has no free blocks. OutOfMemory is returned if both are exhausted.
+*/
+pub fn try_new_pooled<T: PooledObject>(value: T) -> Result<Box<T>, KernelError> {
+    let ptr = crate::HEAP.alloc_pooled(T::pool(), Layout::new::<T>()) as *mut T;
+    if ptr.is_null() {
+        return Err(KernelError::OutOfMemory);
+    }
+    unsafe {
+        ptr.write(value);
+        Ok(Box::from_raw(ptr))
+    }
+}
+
+static mut tcb_pool: LockedPool = LockedPool::new(
+    Pool::block_size_for(mem::size_of::<TaskTCB>(), mem::align_of::<TaskTCB>()),
+    TCB_POOL_BLOCKS,
+);
+pub static TCB_POOL: &LockedPool = unsafe{&tcb_pool};
+
+impl PooledObject for TaskTCB {
+    fn pool() -> &'static LockedPool {
+        TCB_POOL
+    }
+}
+
+static mut task_mutex_pool: LockedPool = LockedPool::new(
+    Pool::block_size_for(mem::size_of::<TaskMutex>(), mem::align_of::<TaskMutex>()),
+    TASK_MUTEX_POOL_BLOCKS,
+);
+pub static TASK_MUTEX_POOL: &LockedPool = unsafe{&task_mutex_pool};
+
+impl PooledObject for TaskMutex {
+    fn pool() -> &'static LockedPool {
+        TASK_MUTEX_POOL
+    }
+}
+
+static mut semaphore_pool: LockedPool = LockedPool::new(
+    Pool::block_size_for(mem::size_of::<Semaphore>(), mem::align_of::<Semaphore>()),
+    SEMAPHORE_POOL_BLOCKS,
+);
+pub static SEMAPHORE_POOL: &LockedPool = unsafe{&semaphore_pool};
+
+impl PooledObject for Semaphore {
+    fn pool() -> &'static LockedPool {
+        SEMAPHORE_POOL
+    }
+}
+
+static mut message_queue_pool: LockedPool = LockedPool::new(
+    Pool::block_size_for(mem::size_of::<MessageQueue>(), mem::align_of::<MessageQueue>()),
+    MESSAGE_QUEUE_POOL_BLOCKS,
+);
+pub static MESSAGE_QUEUE_POOL: &LockedPool = unsafe{&message_queue_pool};
+
+impl PooledObject for MessageQueue {
+    fn pool() -> &'static LockedPool {
+        MESSAGE_QUEUE_POOL
+    }
+}
+
+static mut event_group_pool: LockedPool = LockedPool::new(
+    Pool::block_size_for(mem::size_of::<EventGroup>(), mem::align_of::<EventGroup>()),
+    EVENT_GROUP_POOL_BLOCKS,
+);
+pub static EVENT_GROUP_POOL: &LockedPool = unsafe{&event_group_pool};
+
+impl PooledObject for EventGroup {
+    fn pool() -> &'static LockedPool {
+        EVENT_GROUP_POOL
+    }
+}
+
+static mut stream_buffer_pool: LockedPool = LockedPool::new(
+    Pool::block_size_for(mem::size_of::<StreamBuffer>(), mem::align_of::<StreamBuffer>()),
+    STREAM_BUFFER_POOL_BLOCKS,
+);
+pub static STREAM_BUFFER_POOL: &LockedPool = unsafe{&stream_buffer_pool};
+
+impl PooledObject for StreamBuffer {
+    fn pool() -> &'static LockedPool {
+        STREAM_BUFFER_POOL
+    }
+}
+
+static mut barrier_pool: LockedPool = LockedPool::new(
+    Pool::block_size_for(mem::size_of::<Barrier>(), mem::align_of::<Barrier>()),
+    BARRIER_POOL_BLOCKS,
+);
+pub static BARRIER_POOL: &LockedPool = unsafe{&barrier_pool};
+
+impl PooledObject for Barrier {
+    fn pool() -> &'static LockedPool {
+        BARRIER_POOL
+    }
+}
+
+static mut timer_pool: LockedPool = LockedPool::new(
+    Pool::block_size_for(mem::size_of::<Timer>(), mem::align_of::<Timer>()),
+    TIMER_POOL_BLOCKS,
+);
+pub static TIMER_POOL: &LockedPool = unsafe{&timer_pool};
+
+impl PooledObject for Timer {
+    fn pool() -> &'static LockedPool {
+        TIMER_POOL
+    }
+}
+
+/*
+The storage for the kernel pools is carved from the heap during boot. As it
+is never freed, it sits at the very start of the heap and does not
+contribute to fragmentation.
+*/
+
+pub fn init_kernel_pools(heap: &LockedHeap) {
+    let pools = [
+        TCB_POOL,
+        TASK_MUTEX_POOL,
+        SEMAPHORE_POOL,
+        MESSAGE_QUEUE_POOL,
+        EVENT_GROUP_POOL,
+        STREAM_BUFFER_POOL,
+        BARRIER_POOL,
+        TIMER_POOL,
+    ];
+    for pool in pools {
+        // The storage keeps the heap blocks after it aligned
+        let size = pool.lock().storage_size();
+        let size = (size + HEAP_MAX_ALIGN - 1) & !(HEAP_MAX_ALIGN - 1);
+
+        // If there is not enough memory, kernel objects are simply
+        // allocated from the heap
+        let start = match heap.lock().allocate_segment(size) {
+            Some(start) => start as usize,
+            None => continue,
+        };
+
+        // A pool the allocator does not know about could not get its blocks
+        // back, so it is left unused
+        if heap.register_pool(pool) {
+            pool.init(start);
+        } else {
+            let _ = heap.lock().free_segment(start, size);
+        }
+    }
+}
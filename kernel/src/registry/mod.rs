@@ -0,0 +1,108 @@
+use crate::error::KernelError;
+use crate::mutex::Mutex;
+use core::marker::Sync;
+
+// Max number of kernel objects that can be registered at the same time
+pub const MAX_KERNEL_OBJECTS: usize = 32;
+
+/* The types of kernel objects that can be registered */
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectKind {
+    MutexObject = 0,
+    SemaphoreObject = 1,
+    QueueObject = 2,
+    EventGroupObject = 3,
+    StreamBufferObject = 4,
+    BarrierObject = 5,
+    TimerObject = 6,
+}
+
+/*
+An entry of the registry: the type of the object and its address, which is
+also the handle used by the syscall layer.
+*/
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KernelObject {
+    pub kind: ObjectKind,
+    pub address: usize,
+}
+
+/*
+The registry keeps track of the synchronization objects that currently
+exist, so that they can be listed by the application, e.g. for debugging.
+Objects created through the syscall layer are registered by the kernel,
+while objects declared as static variables should be registered by the
+application with `register`.
+*/
+pub struct Registry {
+    objects: [Option<KernelObject>; MAX_KERNEL_OBJECTS],
+}
+
+static mut registry: Mutex<Registry> = Mutex::new(Registry::new());
+pub static REGISTRY: &Mutex<Registry> = unsafe{&registry};
+
+impl Registry {
+    pub const fn new() -> Self {
+        Self { objects: [None; MAX_KERNEL_OBJECTS] }
+    }
+
+    //adds the object to the registry, it fails if the registry is full
+    pub fn register(&mut self, kind: ObjectKind, address: usize) -> Result<(), KernelError> {
+        if self.contains(address) {
+            return Ok(());
+        }
+        let slot = self.objects.iter_mut()
+            .find(|object| object.is_none())
+            .ok_or(KernelError::TooManyObjects)?;
+        *slot = Some(KernelObject { kind, address });
+        Ok(())
+    }
+
+    pub fn unregister(&mut self, address: usize) {
+        for object in self.objects.iter_mut() {
+            if object.map_or(false, |object| object.address == address) {
+                *object = None;
+            }
+        }
+    }
+
+    pub fn contains(&self, address: usize) -> bool {
+        self.iter().any(|object| object.address == address)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &KernelObject> {
+        self.objects.iter().flatten()
+    }
+
+    //returns the number of registered objects of the given kind
+    pub fn count(&self, kind: ObjectKind) -> usize {
+        self.iter().filter(|object| object.kind == kind).count()
+    }
+}
+
+unsafe impl Sync for Registry {}
+
+/* Registers a kernel object, e.g. one declared as a static variable */
+pub fn register<T>(kind: ObjectKind, object: &'static T) -> Result<(), KernelError> {
+    REGISTRY.lock().register(kind, object as *const T as usize)
+}
+
+/*
+Copies up to `len` entries of the registry into `objects`, and returns the
+number of entries copied.
+*/
+#[no_mangle]
+pub extern "C" fn get_kernel_objects(objects: *mut KernelObject, len: usize) -> usize {
+    if objects.is_null() {
+        return 0;
+    }
+    let table = REGISTRY.lock();
+    let mut copied = 0;
+    for object in table.iter().take(len) {
+        unsafe{ objects.add(copied).write(*object) };
+        copied += 1;
+    }
+    copied
+}
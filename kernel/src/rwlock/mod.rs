@@ -0,0 +1,155 @@
+use crate::error::KernelError;
+use crate::mutex::{CriticalSection, Mutex};
+use crate::task::{self, Queue};
+use crate::wait;
+use core::marker::Sync;
+
+/*
+A reader-writer lock can be held either by many readers at the same time,
+or by a single writer. It suits data that is read often and written rarely.
+
+The lock is fair to writers: as soon as a writer is waiting, new readers
+wait as well, so a steady flow of readers cannot starve it. In turn, when
+a writer releases the lock, all the readers waiting at that moment get it
+before the next writer, so readers are never starved either.
+
+As for a TaskMutex, the lock is handed over directly to the tasks that are
+woken up. Only the writer is tracked, so that it alone can unlock.
+*/
+pub struct RwLock {
+    inner: Mutex<RwLockState>,
+}
+
+struct RwLockState {
+    readers: usize,         //number of readers holding the lock
+    writer: Option<usize>,  //id of the writer holding the lock, if any
+    waiting_readers: Queue,
+    waiting_writers: Queue,
+}
+
+impl RwLock {
+    pub const fn new() -> Self {
+        Self {
+            inner: Mutex::new(RwLockState {
+                readers: 0,
+                writer: None,
+                waiting_readers: Queue::new(),
+                waiting_writers: Queue::new(),
+            }),
+        }
+    }
+
+    /*
+    Locks for reading, waiting at most `timeout` ticks while a writer holds
+    the lock or is waiting for it.
+    */
+    pub fn read(&self, timeout: u32) -> Result<(), KernelError> {
+        {
+            let _section = CriticalSection::enter();
+            let mut state = self.inner.lock();
+            if state.writer.is_none() && state.waiting_writers.empty() {
+                state.readers += 1;
+                return Ok(());
+            }
+            wait::block_running(&mut state.waiting_readers, timeout)?;
+        }
+        // When the task is woken up, it already holds the lock
+        wait::wait_for_wakeup()
+    }
+
+    /* Releases a read lock. The last reader hands the lock over to a writer */
+    pub fn read_unlock(&self) -> Result<(), KernelError> {
+        let _section = CriticalSection::enter();
+        let mut state = self.inner.lock();
+        if state.readers == 0 {
+            return Err(KernelError::NotOwner);
+        }
+        state.readers -= 1;
+        if state.readers == 0 {
+            state.wake_writer();
+        }
+        Ok(())
+    }
+
+    /* Locks for writing, waiting at most `timeout` ticks for the lock to be free */
+    pub fn write(&self, timeout: u32) -> Result<(), KernelError> {
+        let id = task::running_task_id();
+        {
+            let _section = CriticalSection::enter();
+            let mut state = self.inner.lock();
+            if state.writer == Some(id) {
+                return Err(KernelError::Deadlock);
+            }
+            if state.writer.is_none() && state.readers == 0 {
+                state.writer = Some(id);
+                return Ok(());
+            }
+            let object = self as *const Self as *const ();
+            wait::block_running_with_hook(&mut state.waiting_writers, timeout, object, Self::writer_left)?;
+        }
+        // When the task is woken up, it already holds the lock
+        wait::wait_for_wakeup()
+    }
+
+    /*
+    Releases the write lock, which must be held by the caller. The readers
+    that are waiting get the lock first, otherwise the next writer does.
+    */
+    pub fn write_unlock(&self) -> Result<(), KernelError> {
+        let _section = CriticalSection::enter();
+        let mut state = self.inner.lock();
+        if state.writer != Some(task::running_task_id()) {
+            return Err(KernelError::NotOwner);
+        }
+        state.writer = None;
+        if !state.wake_readers() {
+            state.wake_writer();
+        }
+        Ok(())
+    }
+
+    /* Returns the number of readers holding the lock */
+    pub fn readers(&self) -> usize {
+        self.inner.lock().readers
+    }
+
+    /* Returns the id of the writer holding the lock, if any */
+    pub fn writer(&self) -> Option<usize> {
+        self.inner.lock().writer
+    }
+
+    /*
+    Called when a waiting writer gives up, because of its timeout: if no
+    other writer is waiting, the readers it was holding back can proceed.
+    */
+    unsafe fn writer_left(lock: *const ()) {
+        let lock = &*(lock as *const Self);
+        let mut state = lock.inner.lock();
+        if state.writer.is_none() && state.waiting_writers.empty() {
+            state.wake_readers();
+        }
+    }
+}
+
+impl RwLockState {
+    // all the waiting readers get the lock, returns false if there were none
+    fn wake_readers(&mut self) -> bool {
+        let mut woken = false;
+        while let Some(reader) = self.waiting_readers.dequeue() {
+            self.readers += 1;
+            wait::make_ready(reader, Ok(()));
+            woken = true;
+        }
+        woken
+    }
+
+    // the first waiting writer gets the lock, if any
+    fn wake_writer(&mut self) {
+        if let Some(writer) = self.waiting_writers.dequeue() {
+            self.writer = Some(writer.id);
+            wait::make_ready(writer, Ok(()));
+        }
+    }
+}
+
+unsafe impl Sync for RwLock {}
@@ -0,0 +1,371 @@
+use crate::config::NO_TIME_SLICE;
+use crate::deadline;
+use crate::task::{Queue, TaskTCB, MAX_PRIORITY};
+use alloc::boxed::Box;
+
+/*
+The scheduling policy, i.e. the order in which the ready tasks run. The
+kernel keeps the ready tasks in a `Scheduler`, chosen at build time through
+`KernelScheduler`:
+- `FixedPriority`, the default: the task with the highest priority runs, and
+  the tasks with the same priority share the CPU in round robin;
+- `Edf`, with the `edf` cargo feature: the tasks with a pending job, see the
+  `deadline` module, are ordered by deadline whatever their priority, and
+  run before the others, which are scheduled by fixed priority;
+- `RoundRobin`, with the `round-robin` cargo feature: priorities are
+  ignored, and all the tasks share the CPU in turn.
+
+A scheduler only sees the TCBs it is given, and the current tick is passed
+to its hooks: it never touches the hardware or the kernel's globals, so
+that it can be tested on its own, on the host too.
+*/
+pub trait Scheduler {
+    /* Adds a ready task */
+    fn enqueue(&mut self, tcb: Box<TaskTCB>);
+
+    /* Removes and returns the task that must run next, if any is ready */
+    fn dequeue_next(&mut self) -> Option<Box<TaskTCB>>;
+
+    /* Returns the task that `dequeue_next` would return, without removing it */
+    fn first(&self) -> Option<&TaskTCB>;
+
+    /* Removes the ready task with the given id */
+    fn remove(&mut self, id: usize) -> Option<Box<TaskTCB>>;
+
+    /* Returns the number of ready tasks */
+    fn count_tasks(&mut self) -> usize;
+
+    /* Returns true if `tcb` must take the CPU from the `running` task right away */
+    fn preempts(&self, tcb: &TaskTCB, running: &TaskTCB) -> bool;
+
+    fn empty(&self) -> bool {
+        self.first().is_none()
+    }
+
+    /*
+    Called on every tick with the RUNNING task. Returns true if the task
+    must be switched out: its time slice is over, and the first ready task
+    does not have to wait for it.
+    */
+    fn on_tick(&mut self, running: &mut TaskTCB) -> bool {
+        if !slice_over(running) {
+            return false;
+        }
+        match self.first() {
+            Some(first) => !self.preempts(running, first),
+            None => false,
+        }
+    }
+
+    /*
+    Called when the RUNNING task blocks at tick `now`. Its job goes on: the
+    wait is part of it, but a job that is already late is counted now.
+    */
+    fn on_block(&mut self, tcb: &mut TaskTCB, now: u32) {
+        deadline::check(tcb, now);
+    }
+
+    /*
+    Called when a blocked task becomes ready at tick `now`: a new job starts
+    if the previous one is complete.
+    */
+    fn on_wake(&mut self, tcb: &mut TaskTCB, now: u32) {
+        deadline::release(tcb, now);
+    }
+}
+
+#[cfg(all(feature = "edf", feature = "round-robin"))]
+compile_error!("the `edf` and `round-robin` features select different schedulers");
+
+#[cfg(feature = "edf")]
+pub type KernelScheduler = Edf;
+#[cfg(all(feature = "round-robin", not(feature = "edf")))]
+pub type KernelScheduler = RoundRobin;
+#[cfg(not(any(feature = "edf", feature = "round-robin")))]
+pub type KernelScheduler = FixedPriority;
+
+/*
+Charges one tick to the time slice of a task. Returns true if the slice is
+over, in which case a new one starts. Tasks with no time slice never reach
+the end of it.
+*/
+pub fn slice_over(tcb: &mut TaskTCB) -> bool {
+    if tcb.quantum == NO_TIME_SLICE {
+        return false;
+    }
+    tcb.slice_left = tcb.slice_left.saturating_sub(1);
+    if tcb.slice_left > 0 {
+        return false;
+    }
+    tcb.slice_left = tcb.quantum;
+    true
+}
+
+// priorities beyond the maximum share the highest level
+fn level(priority: usize) -> usize {
+    priority.min(MAX_PRIORITY as usize - 1)
+}
+
+/*
+A single FIFO queue: every task runs for its time slice, then goes back to
+the end of the queue. A task that becomes ready never preempts the RUNNING
+task.
+*/
+pub struct RoundRobin {
+    queue: Queue,
+}
+
+impl RoundRobin {
+    pub const fn new() -> Self {
+        Self { queue: Queue::new() }
+    }
+}
+
+impl Scheduler for RoundRobin {
+    fn enqueue(&mut self, tcb: Box<TaskTCB>) {
+        self.queue.enqueue(tcb);
+    }
+
+    fn dequeue_next(&mut self) -> Option<Box<TaskTCB>> {
+        self.queue.dequeue()
+    }
+
+    fn first(&self) -> Option<&TaskTCB> {
+        self.queue.first()
+    }
+
+    fn remove(&mut self, id: usize) -> Option<Box<TaskTCB>> {
+        self.queue.remove(id)
+    }
+
+    fn count_tasks(&mut self) -> usize {
+        self.queue.count_tasks()
+    }
+
+    fn preempts(&self, _tcb: &TaskTCB, _running: &TaskTCB) -> bool {
+        false
+    }
+}
+
+/*
+One FIFO queue for each priority level. The task with the highest priority
+is always dequeued first, and tasks with the same priority are served in
+round robin.
+*/
+pub struct FixedPriority {
+    queues: [Queue; MAX_PRIORITY as usize],
+}
+
+impl FixedPriority {
+    pub const fn new() -> Self {
+        const EMPTY: Queue = Queue::new();
+        Self { queues: [EMPTY; MAX_PRIORITY as usize] }
+    }
+}
+
+impl Scheduler for FixedPriority {
+    fn enqueue(&mut self, tcb: Box<TaskTCB>) {
+        self.queues[level(tcb.priority)].enqueue(tcb);
+    }
+
+    fn dequeue_next(&mut self) -> Option<Box<TaskTCB>> {
+        self.queues.iter_mut().rev().find_map(|queue| queue.dequeue())
+    }
+
+    fn first(&self) -> Option<&TaskTCB> {
+        self.queues.iter().rev().find_map(|queue| queue.first())
+    }
+
+    fn remove(&mut self, id: usize) -> Option<Box<TaskTCB>> {
+        self.queues.iter_mut().find_map(|queue| queue.remove(id))
+    }
+
+    fn count_tasks(&mut self) -> usize {
+        self.queues.iter_mut().map(|queue| queue.count_tasks()).sum()
+    }
+
+    fn preempts(&self, tcb: &TaskTCB, running: &TaskTCB) -> bool {
+        tcb.priority > running.priority
+    }
+}
+
+/*
+Earliest Deadline First for the tasks created with a deadline, which form a
+class of their own: while one of their jobs is pending they are sorted by
+absolute deadline, whatever their priority, and they run before all the
+other tasks. A task of the class preempts the RUNNING one if its deadline
+comes first, and the tasks with the same deadline are served in round
+robin. The tasks with no pending job are scheduled as by `FixedPriority`,
+whenever no job is ready.
+*/
+pub struct Edf {
+    jobs: Queue,
+    others: FixedPriority,
+}
+
+impl Edf {
+    pub const fn new() -> Self {
+        Self { jobs: Queue::new(), others: FixedPriority::new() }
+    }
+}
+
+impl Scheduler for Edf {
+    fn enqueue(&mut self, tcb: Box<TaskTCB>) {
+        if tcb.deadline.is_some() {
+            self.jobs.insert_by_deadline(tcb);
+        } else {
+            self.others.enqueue(tcb);
+        }
+    }
+
+    fn dequeue_next(&mut self) -> Option<Box<TaskTCB>> {
+        self.jobs.dequeue().or_else(|| self.others.dequeue_next())
+    }
+
+    fn first(&self) -> Option<&TaskTCB> {
+        self.jobs.first().or_else(|| self.others.first())
+    }
+
+    fn remove(&mut self, id: usize) -> Option<Box<TaskTCB>> {
+        self.jobs.remove(id).or_else(|| self.others.remove(id))
+    }
+
+    fn count_tasks(&mut self) -> usize {
+        self.jobs.count_tasks() + self.others.count_tasks()
+    }
+
+    fn preempts(&self, tcb: &TaskTCB, running: &TaskTCB) -> bool {
+        if tcb.deadline.is_none() && running.deadline.is_none() {
+            return self.others.preempts(tcb, running);
+        }
+        deadline::earlier(tcb.deadline, running.deadline)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(id: usize, priority: usize, deadline: Option<u32>) -> Box<TaskTCB> {
+        let mut tcb = Box::new(TaskTCB::new(None, priority));
+        tcb.id = id;
+        tcb.deadline = deadline;
+        tcb.quantum = 2;
+        tcb.slice_left = 2;
+        tcb
+    }
+
+    fn drain(scheduler: &mut impl Scheduler) -> [usize; 4] {
+        let mut ids = [0; 4];
+        for id in ids.iter_mut() {
+            *id = scheduler.dequeue_next().unwrap().id;
+        }
+        assert!(scheduler.empty());
+        ids
+    }
+
+    fn fill(scheduler: &mut impl Scheduler) {
+        scheduler.enqueue(task(1, 1, None));
+        scheduler.enqueue(task(2, 2, Some(50)));
+        scheduler.enqueue(task(3, 1, Some(20)));
+        scheduler.enqueue(task(4, 2, Some(10)));
+    }
+
+    #[test]
+    fn round_robin_order() {
+        let mut scheduler = RoundRobin::new();
+        fill(&mut scheduler);
+        assert_eq!(scheduler.count_tasks(), 4);
+        assert_eq!(drain(&mut scheduler), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn fixed_priority_order() {
+        let mut scheduler = FixedPriority::new();
+        fill(&mut scheduler);
+        assert_eq!(scheduler.first().unwrap().id, 2);
+        assert_eq!(drain(&mut scheduler), [2, 4, 1, 3]);
+    }
+
+    #[test]
+    fn edf_order() {
+        let mut scheduler = Edf::new();
+        fill(&mut scheduler);
+        assert_eq!(scheduler.remove(3).unwrap().id, 3);
+        scheduler.enqueue(task(3, 1, Some(20)));
+        assert_eq!(drain(&mut scheduler), [4, 3, 2, 1]);
+    }
+
+    #[test]
+    fn preemption() {
+        let running = task(1, 1, Some(20));
+        let higher = task(2, 2, None);
+        let earlier = task(3, 0, Some(10));
+        let lower = task(4, 0, None);
+
+        assert!(!RoundRobin::new().preempts(&higher, &running));
+        assert!(FixedPriority::new().preempts(&higher, &running));
+        assert!(!FixedPriority::new().preempts(&earlier, &running));
+        assert!(!Edf::new().preempts(&higher, &running));
+        assert!(Edf::new().preempts(&earlier, &running));
+        assert!(!Edf::new().preempts(&running, &earlier));
+        assert!(Edf::new().preempts(&earlier, &higher));
+        assert!(Edf::new().preempts(&higher, &lower));
+    }
+
+    #[test]
+    fn time_slice() {
+        let mut running = task(1, 1, Some(20));
+
+        // With no task ready, the slice simply starts over
+        let mut scheduler = FixedPriority::new();
+        assert!(!scheduler.on_tick(&mut running));
+        assert!(!scheduler.on_tick(&mut running));
+
+        // A task with the same priority gets the CPU once the slice is over
+        scheduler.enqueue(task(2, 1, None));
+        assert!(!scheduler.on_tick(&mut running));
+        assert!(scheduler.on_tick(&mut running));
+
+        // Under EDF only if its deadline does not come later
+        let mut scheduler = Edf::new();
+        scheduler.enqueue(task(2, 1, Some(30)));
+        for _ in 0..4 {
+            assert!(!scheduler.on_tick(&mut running));
+        }
+        scheduler.enqueue(task(3, 1, Some(20)));
+        assert!(!scheduler.on_tick(&mut running));
+        assert!(scheduler.on_tick(&mut running));
+
+        // Round robin is off for a task with no time slice
+        running.quantum = NO_TIME_SLICE;
+        for _ in 0..4 {
+            assert!(!scheduler.on_tick(&mut running));
+        }
+    }
+
+    #[test]
+    fn jobs() {
+        let mut scheduler = Edf::new();
+        let mut tcb = task(1, 1, None);
+        tcb.relative_deadline = 5;
+
+        scheduler.on_wake(&mut tcb, 100);
+        assert_eq!(tcb.deadline, Some(105));
+
+        // A wait in the middle of the job does not complete it
+        scheduler.on_block(&mut tcb, 104);
+        scheduler.on_wake(&mut tcb, 104);
+        assert_eq!(tcb.deadline, Some(105));
+        assert_eq!(tcb.deadline_misses, 0);
+
+        scheduler.on_block(&mut tcb, 106);
+        assert_eq!(tcb.deadline_misses, 1);
+        deadline::complete(&mut tcb, 107);
+        assert_eq!(tcb.deadline, None);
+        assert_eq!(tcb.deadline_misses, 1);
+
+        scheduler.on_wake(&mut tcb, 200);
+        assert_eq!(tcb.deadline, Some(205));
+    }
+}
@@ -0,0 +1,98 @@
+use crate::error::KernelError;
+use crate::mutex::{CriticalSection, Mutex};
+use crate::task::Queue;
+use crate::wait;
+use core::marker::Sync;
+
+/*
+A counting semaphore: `take` consumes one unit, waiting for one to be
+available if needed, and `give` returns it. A binary semaphore is simply a
+semaphore whose count is at most 1.
+
+Tasks waiting for the semaphore are kept sorted by priority. When a unit is
+given while tasks are waiting, it is handed over directly to the waiting
+task with the highest priority, so that no other task can steal it.
+
+Unlike a TaskMutex a semaphore has no owner: any task, or interrupt
+handler, can give it.
+*/
+pub struct Semaphore {
+    inner: Mutex<SemaphoreState>,
+}
+
+struct SemaphoreState {
+    count: usize,     //units currently available
+    max_count: usize, //max number of units, `give` fails beyond it
+    waiters: Queue,   //tasks waiting for a unit, sorted by priority
+}
+
+impl Semaphore {
+    pub const fn new(initial_count: usize, max_count: usize) -> Self {
+        Self {
+            inner: Mutex::new(SemaphoreState {
+                count: initial_count,
+                max_count,
+                waiters: Queue::new(),
+            }),
+        }
+    }
+
+    /* Creates a binary semaphore, initially available or not */
+    pub const fn binary(available: bool) -> Self {
+        Self::new(available as usize, 1)
+    }
+
+    /*
+    Takes one unit, waiting at most `timeout` ticks for it. Returns Timeout
+    if no unit became available in time, or WouldBlock if the timeout is
+    NO_WAIT.
+    */
+    pub fn take(&self, timeout: u32) -> Result<(), KernelError> {
+        {
+            let _section = CriticalSection::enter();
+            let mut state = self.inner.lock();
+            if state.count > 0 {
+                state.count -= 1;
+                return Ok(());
+            }
+            wait::block_running(&mut state.waiters, timeout)?;
+        }
+        // When the task is woken up by `give`, the unit is already its own
+        wait::wait_for_wakeup()
+    }
+
+    /*
+    Gives one unit back, waking up the waiting task with the highest
+    priority, if any. Returns Full if the semaphore is at its max count.
+    */
+    pub fn give(&self) -> Result<(), KernelError> {
+        let _section = CriticalSection::enter();
+        let mut state = self.inner.lock();
+
+        if wait::wake_first(&mut state.waiters, Ok(())).is_some() {
+            return Ok(());
+        }
+        if state.count == state.max_count {
+            return Err(KernelError::Full);
+        }
+        state.count += 1;
+        Ok(())
+    }
+
+    /* Same as `give`, for interrupt handlers (see the `wait` module) */
+    pub fn give_from_isr(&self) -> Result<(), KernelError> {
+        self.give()
+    }
+
+    /* Returns the number of units currently available */
+    pub fn count(&self) -> usize {
+        self.inner.lock().count
+    }
+
+    /* Returns the number of tasks waiting for the semaphore */
+    pub fn waiting_tasks(&self) -> usize {
+        self.inner.lock().waiters.count_tasks()
+    }
+}
+
+unsafe impl Sync for Semaphore {}
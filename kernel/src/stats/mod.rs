@@ -0,0 +1,163 @@
+use crate::idle;
+use crate::mutex::CriticalSection;
+use crate::task::{TaskState, TaskTCB, MAX_TASKS, RUNNING, TASK_TABLE};
+use crate::time;
+use cortex_m::peripheral::{DCB, DWT};
+
+/*
+Runtime statistics of the tasks. Every task accumulates the CPU time it
+has been running for, measured with the DWT cycle counter when the core
+has one, and in ticks otherwise: `cpu_time_in_cycles` tells which unit is
+used. The scheduler charges a task when it is switched out, and counts how
+many times it has been switched in. The RUNNING task is also charged on
+every tick: the cycle counter wraps around every 2^32 cycles, and a task
+that is never switched out, like the idle task of a quiet system, would
+otherwise lose the time beyond that.
+
+`task_stats` takes a snapshot of all the tasks, the idle task included, in
+the spirit of `top`: comparing the CPU time of the tasks shows which of
+them is keeping the CPU busy.
+*/
+
+// True if the CPU time is measured in cycles rather than ticks
+static mut use_cycles: bool = false;
+
+/* A snapshot of the statistics of a task */
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TaskStats {
+    pub id: usize,
+    pub priority: usize,
+    pub state: TaskState,
+    pub idle: bool,             //true for the idle task
+    pub cpu_time: u64,          //cycles, or ticks, spent running
+    pub cpu_percent: u32,       //share of the CPU time of all the tasks, in percent
+    pub context_switches: u32,  //number of times the task was switched in
+    pub last_run: u32,          //tick at which the task was last switched in
+    pub deadline_misses: u32,   //number of jobs that missed their deadline
+}
+
+/*
+Starts the DWT cycle counter, if the core has one. Called by `kernel_init`;
+the CPU time is measured in ticks if the counter does not run.
+*/
+pub fn init(dcb: &mut DCB, dwt: &mut DWT) {
+    if !DWT::has_cycle_counter() {
+        return;
+    }
+    dcb.enable_trace();
+    DWT::unlock();
+    dwt.enable_cycle_counter();
+
+    // Some cores, and emulators, report a counter that never moves
+    let start = DWT::cycle_count();
+    cortex_m::asm::nop();
+    cortex_m::asm::nop();
+    unsafe{ use_cycles = DWT::cycle_count() != start };
+}
+
+/* Returns true if the CPU time is measured in cycles, false if in ticks */
+#[no_mangle]
+pub extern "C" fn cpu_time_in_cycles() -> bool {
+    unsafe{ use_cycles }
+}
+
+// the current time, in the unit of the CPU time
+fn now() -> u32 {
+    if cpu_time_in_cycles() {
+        DWT::cycle_count()
+    } else {
+        time::ticks()
+    }
+}
+
+/* Called by the scheduler when a task is switched in */
+pub fn switched_in(tcb: &mut TaskTCB) {
+    tcb.context_switches = tcb.context_switches.wrapping_add(1);
+    tcb.last_run = time::ticks();
+    tcb.charged_at = now();
+}
+
+/* Called by the scheduler when a task is switched out, to charge its CPU time */
+pub fn switched_out(tcb: &mut TaskTCB) {
+    charge(tcb);
+}
+
+/* Called on every tick, to charge the RUNNING task for its CPU time so far */
+pub fn tick() {
+    let _section = CriticalSection::enter();
+    if let Some(tcb) = unsafe{ RUNNING.as_mut() } {
+        charge(tcb);
+    }
+}
+
+fn charge(tcb: &mut TaskTCB) {
+    let now = now();
+    tcb.cpu_time += now.wrapping_sub(tcb.charged_at) as u64;
+    tcb.charged_at = now;
+}
+
+// time since the task was last charged
+fn running_time(tcb: &TaskTCB) -> u32 {
+    now().wrapping_sub(tcb.charged_at)
+}
+
+/*
+Writes the statistics of up to `buffer.len()` tasks into `buffer`, and
+returns the number of tasks written.
+*/
+pub fn snapshot(buffer: &mut [TaskStats]) -> usize {
+    task_stats(buffer.as_mut_ptr(), buffer.len())
+}
+
+/*
+Writes the statistics of up to `len` tasks into `buffer`, and returns the
+number of tasks written. The RUNNING task is charged for the time it has
+been running so far.
+*/
+#[no_mangle]
+pub extern "C" fn task_stats(buffer: *mut TaskStats, len: usize) -> usize {
+    if buffer.is_null() {
+        return 0;
+    }
+    let _section = CriticalSection::enter();
+    let running = unsafe{ RUNNING.as_ref() }.map(|tcb| tcb.id);
+    let mut table = TASK_TABLE.lock();
+
+    let mut count = 0;
+    let mut total: u64 = 0;
+    for id in 1..=MAX_TASKS {
+        if count == len {
+            break;
+        }
+        if let Some(tcb) = table.get(id) {
+            let mut cpu_time = tcb.cpu_time;
+            if running == Some(id) {
+                cpu_time += running_time(tcb) as u64;
+            }
+            total += cpu_time;
+            let stats = TaskStats {
+                id,
+                priority: tcb.priority,
+                state: tcb.state,
+                idle: idle::is_idle(id),
+                cpu_time,
+                cpu_percent: 0,
+                context_switches: tcb.context_switches,
+                last_run: tcb.last_run,
+                deadline_misses: tcb.deadline_misses,
+            };
+            unsafe{ buffer.add(count).write(stats) };
+            count += 1;
+        }
+    }
+
+    // The share of each task is known once all of them have been counted
+    if total > 0 {
+        for i in 0..count {
+            let stats = unsafe{ &mut *buffer.add(i) };
+            stats.cpu_percent = (stats.cpu_time * 100 / total) as u32;
+        }
+    }
+    count
+}
@@ -0,0 +1,176 @@
+use crate::error::KernelError;
+use crate::mutex::{CriticalSection, Mutex};
+use crate::task::Queue;
+use crate::utility::memcpy;
+use crate::wait;
+use alloc::alloc::{alloc, dealloc, Layout};
+use core::marker::Sync;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/*
+A StreamBuffer carries a stream of bytes from a single writer, typically an
+interrupt handler, to a single reader task, e.g. the bytes received by a
+UART. Unlike a MessageQueue there are no item boundaries: the reader gets
+as many bytes as are available, up to the size of its buffer.
+
+- Writing never blocks and never takes a lock: the writer only moves the
+  write index, and the reader only moves the read index. Both indexes run
+  modulo twice the size of the buffer, so that a full buffer can be told
+  apart from an empty one.
+- The reader can wait until at least `trigger_level` bytes are available.
+  The writer enters a critical section only to wake it up, and only when
+  the reader is actually waiting.
+
+Since there is a single reader, the bytes that woke it up cannot be taken
+by anyone else: the reader simply reads them once it's resumed.
+*/
+pub struct StreamBuffer {
+    buffer: *mut u8,
+    size: usize,
+    trigger_level: AtomicUsize,
+    read_index: AtomicUsize,     //moved by the reader only
+    write_index: AtomicUsize,    //moved by the writer only
+    reader_waiting: AtomicBool,
+    reader: Mutex<Queue>,        //the reader, while it's waiting
+}
+
+impl StreamBuffer {
+    /*
+    Creates a buffer that holds up to `size` bytes. Fails with OutOfMemory
+    if the buffer cannot be allocated.
+    */
+    pub fn new(size: usize, trigger_level: usize) -> Result<Self, KernelError> {
+        if size == 0 || trigger_level == 0 || trigger_level > size {
+            return Err(KernelError::InvalidArgument);
+        }
+        let buffer = unsafe{ alloc(Self::layout(size)) };
+        if buffer.is_null() {
+            return Err(KernelError::OutOfMemory);
+        }
+
+        Ok(Self {
+            buffer,
+            size,
+            trigger_level: AtomicUsize::new(trigger_level),
+            read_index: AtomicUsize::new(0),
+            write_index: AtomicUsize::new(0),
+            reader_waiting: AtomicBool::new(false),
+            reader: Mutex::new(Queue::new()),
+        })
+    }
+
+    fn layout(size: usize) -> Layout {
+        Layout::from_size_align(size, 1).unwrap()
+    }
+
+    /* Returns the number of bytes that can be read */
+    pub fn available(&self) -> usize {
+        let write_index = self.write_index.load(Ordering::Acquire);
+        let read_index = self.read_index.load(Ordering::Acquire);
+        (write_index + 2 * self.size - read_index) % (2 * self.size)
+    }
+
+    /* Returns the number of bytes that can be written */
+    pub fn space(&self) -> usize {
+        self.size - self.available()
+    }
+
+    pub fn set_trigger_level(&self, trigger_level: usize) -> Result<(), KernelError> {
+        if trigger_level == 0 || trigger_level > self.size {
+            return Err(KernelError::InvalidArgument);
+        }
+        self.trigger_level.store(trigger_level, Ordering::Release);
+        Ok(())
+    }
+
+    /*
+    Writes up to `len` bytes, as many as fit in the buffer, and returns the
+    number of bytes written. It can be called from interrupt handlers.
+    */
+    pub fn write(&self, data: *const u8, len: usize) -> usize {
+        let write_index = self.write_index.load(Ordering::Relaxed);
+        let count = len.min(self.space());
+
+        for i in 0..count {
+            unsafe{ *self.slot(write_index + i) = *data.add(i) };
+        }
+        // The bytes are published to the reader
+        self.write_index.store(self.advance(write_index, count), Ordering::Release);
+
+        if self.reader_waiting.load(Ordering::Acquire)
+            && self.available() >= self.trigger_level.load(Ordering::Acquire) {
+            let _section = CriticalSection::enter();
+            self.reader_waiting.store(false, Ordering::Release);
+            wait::wake_first(&mut self.reader.lock(), Ok(()));
+        }
+        count
+    }
+
+    /*
+    Reads up to `len` bytes, as many as available, without waiting. Returns
+    the number of bytes read.
+    */
+    pub fn try_read(&self, buffer: *mut u8, len: usize) -> usize {
+        let read_index = self.read_index.load(Ordering::Relaxed);
+        let count = len.min(self.available());
+
+        // The bytes may wrap around the end of the buffer
+        let start = read_index % self.size;
+        let first = count.min(self.size - start);
+        unsafe {
+            memcpy(self.slot(read_index), buffer, first);
+            memcpy(self.buffer, buffer.add(first), count - first);
+        }
+        self.read_index.store(self.advance(read_index, count), Ordering::Release);
+        count
+    }
+
+    /*
+    Waits at most `timeout` ticks for the trigger level to be reached. Only
+    the reader may call it.
+    */
+    pub fn wait_for_data(&self, timeout: u32) -> Result<(), KernelError> {
+        {
+            let _section = CriticalSection::enter();
+            if self.available() >= self.trigger_level.load(Ordering::Acquire) {
+                return Ok(());
+            }
+            self.reader_waiting.store(true, Ordering::Release);
+            if let Err(error) = wait::block_running(&mut self.reader.lock(), timeout) {
+                self.reader_waiting.store(false, Ordering::Release);
+                return Err(error);
+            }
+        }
+        wait::wait_for_wakeup()
+    }
+
+    /*
+    Reads up to `len` bytes, waiting at most `timeout` ticks for the trigger
+    level to be reached. If it is not reached in time, the bytes available
+    are read anyway, and Timeout (or WouldBlock) is returned only if there
+    were none.
+    */
+    pub fn read(&self, buffer: *mut u8, len: usize, timeout: u32) -> Result<usize, KernelError> {
+        let waited = self.wait_for_data(timeout);
+        match self.try_read(buffer, len) {
+            0 => waited.map(|_| 0),
+            count => Ok(count),
+        }
+    }
+
+    fn advance(&self, index: usize, count: usize) -> usize {
+        (index + count) % (2 * self.size)
+    }
+
+    fn slot(&self, index: usize) -> *mut u8 {
+        unsafe{ self.buffer.add(index % self.size) }
+    }
+}
+
+impl Drop for StreamBuffer {
+    fn drop(&mut self) {
+        unsafe{ dealloc(self.buffer, Self::layout(self.size)) };
+    }
+}
+
+unsafe impl Sync for StreamBuffer {}
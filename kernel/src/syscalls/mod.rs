@@ -1,8 +1,25 @@
 use crate::WAITING_QUEUE;
-use crate::task::{TaskTCB, RUNNING};
-use core::mem::size_of;
+use crate::task::{self, TaskTCB, RUNNING, TASK_TABLE};
+use crate::barrier::Barrier;
+use crate::config::{NO_DEADLINE, NO_TIME_SLICE};
+use crate::deadline;
+use crate::event_group::EventGroup;
+use crate::message_queue::MessageQueue;
+use crate::notification::{self, NotifyAction};
+use crate::pool::{self, LockedPool};
+use crate::registry::{ObjectKind, REGISTRY};
+use crate::semaphore::Semaphore;
+use crate::stream_buffer::StreamBuffer;
+use crate::task_mutex::TaskMutex;
+use crate::timer::{self, Timer, TimerCallback};
+use crate::wait;
+use crate::error::{self, KernelError, SUCCESS};
+use crate::time;
+use core::mem::{size_of, align_of};
+use core::ptr;
 use core::arch::asm;
 use alloc::boxed::Box;
+use alloc::alloc::{alloc, dealloc, Layout};
 use cortex_m_semihosting::{hprint, hprintln};
 use cortex_m::interrupt::disable;
 
@@ -10,30 +27,120 @@ use cortex_m::interrupt::disable;
 This enum lists all the services that can be requested by an application to 
 the kernel.
 Each service has a numeric identifier.
+The services are requested with the `svc` instruction, which must not be
+executed inside a critical section (see `mutex::CriticalSection`).
 */
 pub enum SysCallID {
     CREATE_TASK_ID = 1,
+    CREATE_POOL_ID = 2,
+    POOL_ALLOC_ID = 3,
+    POOL_FREE_ID = 4,
+    EXIT_TASK_ID = 5,
+    CREATE_TASK_WITH_QUOTA_ID = 6,
+    WAIT_RESULT_ID = 7,
+    CREATE_TASK_MUTEX_ID = 8,
+    TASK_MUTEX_LOCK_ID = 9,
+    TASK_MUTEX_UNLOCK_ID = 10,
+    CREATE_SEMAPHORE_ID = 11,
+    SEMAPHORE_TAKE_ID = 12,
+    SEMAPHORE_GIVE_ID = 13,
+    CREATE_MESSAGE_QUEUE_ID = 14,
+    MESSAGE_QUEUE_SEND_ID = 15,
+    MESSAGE_QUEUE_RECEIVE_ID = 16,
+    MESSAGE_QUEUE_PEEK_ID = 17,
+    CREATE_EVENT_GROUP_ID = 18,
+    EVENT_GROUP_SET_ID = 19,
+    EVENT_GROUP_CLEAR_ID = 20,
+    EVENT_GROUP_WAIT_ID = 21,
+    NOTIFY_ID = 22,
+    NOTIFY_WAIT_ID = 23,
+    GET_TASK_ID_ID = 24,
+    CREATE_STREAM_BUFFER_ID = 25,
+    STREAM_BUFFER_WRITE_ID = 26,
+    STREAM_BUFFER_READ_ID = 27,
+    STREAM_BUFFER_WAIT_ID = 28,
+    CREATE_BARRIER_ID = 29,
+    BARRIER_WAIT_ID = 30,
+    CREATE_TIMER_ID = 31,
+    TIMER_START_ID = 32,
+    TIMER_STOP_ID = 33,
+    TIMER_RESET_ID = 34,
+    TIMER_CHANGE_PERIOD_ID = 35,
+    CREATE_TASK_WITH_QUANTUM_ID = 36,
+    SET_PRIORITY_ID = 37,
+    GET_PRIORITY_ID = 38,
+    SUSPEND_TASK_ID = 39,
+    RESUME_TASK_ID = 40,
+    CREATE_TASK_WITH_DEADLINE_ID = 41,
+    CREATE_PERIODIC_TASK_ID = 42,
+    WAIT_NEXT_PERIOD_ID = 43,
+    NOTIFY_TAKE_ID = 44,
+    COMPLETE_JOB_ID = 45,
 }
 
-/* 
-This is the system call provided to the user application, in order to
-create a new task.
+// The system calls of the user application only exist on the target
+#[cfg(target_arch = "arm")]
+mod user;
+#[cfg(target_arch = "arm")]
+pub use user::*;
+
+// Only four arguments can be passed to a system call, in r0-r3, therefore
+// the parameters of a wait are packed into a struct
+#[repr(C)]
+struct EventWait {
+    bits: u32,
+    options: u32,
+    timeout: u32,
+}
 
-It accepts a function pointer, a pointer to its arguments, and a priority.
+// The same goes for the timing of a periodic task, which is created with a
+// priority as well
+#[repr(C)]
+struct PeriodicTiming {
+    period_ms: u32,
+    deadline_ms: u32,
+}
 
-The function simply invokes the kernel to request the given service.
+/*
+The services that interrupt handlers call directly, as they cannot invoke
+system calls: each one is the same as the system call without the
+`_from_isr` suffix (see the `user` module).
 */
 #[no_mangle]
-#[naked]
-pub fn create_task(code: fn(*mut u8), args: *mut u8, priority: usize) {
-    unsafe {
-        asm!(
-            "svc {syscall_id}",
-            "mov pc, lr",
-            syscall_id = const SysCallID::CREATE_TASK_ID as u8,
-            options(noreturn)
-        );
+pub extern "C" fn semaphore_give_from_isr(semaphore: *mut Semaphore) -> usize {
+    if semaphore.is_null() {
+        return KernelError::InvalidArgument as usize;
+    }
+    error::status(unsafe{ (*semaphore).give_from_isr() })
+}
+
+#[no_mangle]
+pub extern "C" fn message_queue_send_from_isr(queue: *mut MessageQueue, item: *const u8) -> usize {
+    if queue.is_null() || item.is_null() {
+        return KernelError::InvalidArgument as usize;
+    }
+    error::status(unsafe{ (*queue).send_from_isr(item) })
+}
+
+#[no_mangle]
+pub extern "C" fn event_group_set_from_isr(group: *mut EventGroup, bits: u32) -> u32 {
+    if group.is_null() {
+        return 0;
+    }
+    unsafe{ (*group).set_from_isr(bits) }
+}
+
+#[no_mangle]
+pub extern "C" fn notify_from_isr(task_id: usize, value: u32, action: NotifyAction) -> usize {
+    error::status(notification::notify_from_isr(task_id, value, action))
+}
+
+#[no_mangle]
+pub extern "C" fn stream_buffer_write_from_isr(stream: *mut StreamBuffer, data: *const u8, len: usize) -> usize {
+    if stream.is_null() || data.is_null() {
+        return 0;
     }
+    unsafe{ (*stream).write(data, len) }
 }
 
 #[no_mangle]
@@ -86,7 +193,92 @@ pub(crate) fn unknownService(){
     executed by the task. 
 */
 #[no_mangle]
-pub fn kcreate_task(code: fn(*mut u8), args: *mut u8, priority: usize) {
+pub fn kcreate_task(code: fn(*mut u8), args: *mut u8, priority: usize) -> usize {
+    kcreate_task_with_quota(code, args, priority, 0)
+}
+
+#[no_mangle]
+pub fn kcreate_task_with_quota(code: fn(*mut u8), args: *mut u8, priority: usize, heap_quota: usize) -> usize {
+    error::status(spawn_task(code, args, priority, heap_quota).map(|_| ()))
+}
+
+#[no_mangle]
+pub fn kcreate_task_with_quantum(code: fn(*mut u8), args: *mut u8, priority: usize, quantum_ms: u32) -> usize {
+    let mut tcb = match new_task(code, args, priority, 0) {
+        Ok(tcb) => tcb,
+        Err(error) => return error as usize,
+    };
+    tcb.quantum = match quantum_ms {
+        NO_TIME_SLICE => NO_TIME_SLICE,
+        quantum_ms => time::ms_to_ticks(quantum_ms),
+    };
+    WAITING_QUEUE.enqueue(tcb);
+    SUCCESS
+}
+
+#[no_mangle]
+pub fn kcreate_task_with_deadline(code: fn(*mut u8), args: *mut u8, priority: usize, deadline_ms: u32) -> usize {
+    if deadline_ms == NO_DEADLINE {
+        return KernelError::InvalidArgument as usize;
+    }
+    let mut tcb = match new_task(code, args, priority, 0) {
+        Ok(tcb) => tcb,
+        Err(error) => return error as usize,
+    };
+    tcb.relative_deadline = time::ms_to_ticks(deadline_ms);
+    deadline::release(&mut tcb, time::ticks());
+    wait::enqueue_ready(tcb);
+    SUCCESS
+}
+
+#[no_mangle]
+pub fn kcomplete_job() -> usize {
+    error::status(deadline::complete_job())
+}
+
+#[no_mangle]
+fn kcreate_periodic_task(code: fn(*mut u8), args: *mut u8, priority: usize, timing: *const PeriodicTiming) -> usize {
+    if timing.is_null() {
+        return KernelError::InvalidArgument as usize;
+    }
+    let PeriodicTiming { period_ms, deadline_ms } = unsafe{ ptr::read(timing) };
+    if period_ms == 0 {
+        return KernelError::InvalidArgument as usize;
+    }
+    let mut tcb = match new_task(code, args, priority, 0) {
+        Ok(tcb) => tcb,
+        Err(error) => return error as usize,
+    };
+    let period = time::ms_to_ticks(period_ms);
+    let relative_deadline = match deadline_ms {
+        NO_DEADLINE => period,
+        deadline_ms => time::ms_to_ticks(deadline_ms),
+    };
+    deadline::start_periodic(&mut tcb, period, relative_deadline, time::ticks());
+    wait::enqueue_ready(tcb);
+    SUCCESS
+}
+
+#[no_mangle]
+pub fn kwait_next_period() -> usize {
+    error::status(deadline::wait_next_period())
+}
+
+/* Creates a task, and returns its id */
+pub(crate) fn spawn_task(code: fn(*mut u8), args: *mut u8, priority: usize, heap_quota: usize) -> Result<usize, KernelError> {
+    let tcb = new_task(code, args, priority, heap_quota)?;
+    let id = tcb.id;
+
+    // The task is inserted into the tasks queue
+    WAITING_QUEUE.enqueue(tcb);
+    Ok(id)
+}
+
+/*
+Creates and registers the TCB of a new task, ready to be switched in, but
+does not schedule it.
+*/
+pub(crate) fn new_task(code: fn(*mut u8), args: *mut u8, priority: usize, heap_quota: usize) -> Result<Box<TaskTCB>, KernelError> {
     // The task's TCB is created
     let mut tcb = TaskTCB::new(None, priority); 
 
@@ -112,11 +304,389 @@ pub fn kcreate_task(code: fn(*mut u8), args: *mut u8, priority: usize) {
 
     tcb.stack_push(&args as *const *mut u8 as *const u8, size_of::<*mut u8>());
 
-    let mut heap_allocated_tcb = Box::new(tcb);
+    // The allocation is fallible, so that running out of memory is
+    // reported to the caller instead of halting the system
+    let mut heap_allocated_tcb = pool::try_new_pooled(tcb)?;
     heap_allocated_tcb.stp = unsafe{ heap_allocated_tcb.stack_end().sub(14 * 4) };
+    heap_allocated_tcb.heap_quota = heap_quota;
 
-    // The task is inserted into the tasks queue
-    WAITING_QUEUE.enqueue(heap_allocated_tcb);
+    // The task is given its id. If the maximum number of tasks has been
+    // reached, the task is not created
+    TASK_TABLE.lock().register(&mut heap_allocated_tcb).ok_or(KernelError::TooManyTasks)?;
+    Ok(heap_allocated_tcb)
+}
+
+/*
+The running task is terminated. Its TCB is dropped by the scheduler when the
+task is switched out.
+*/
+#[no_mangle]
+pub fn kexit_task() {
+    unsafe {
+        if let Some(tcb) = RUNNING.as_mut() {
+            task::terminate(tcb);
+        }
+    }
+    wait::request_switch();
+}
+
+/* Returns the outcome of the last wait of the calling task */
+#[no_mangle]
+pub fn kwait_result() -> usize {
+    error::status(wait::running_wait_result())
+}
+
+/*
+Task mutexes are allocated like any other kernel object, therefore they
+are served by their kernel pool as long as it has free blocks.
+*/
+#[no_mangle]
+pub fn kcreate_task_mutex(recursive: bool) -> *mut TaskMutex {
+    match pool::try_new_pooled(TaskMutex::new(recursive)) {
+        Ok(mutex) => register_object(ObjectKind::MutexObject, mutex),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/*
+Objects created through the syscall layer are added to the kernel object
+registry. If the registry is full the object is dropped, and a null
+pointer is returned.
+*/
+fn register_object<T>(kind: ObjectKind, object: Box<T>) -> *mut T {
+    let object = Box::into_raw(object);
+    match REGISTRY.lock().register(kind, object as usize) {
+        Ok(()) => object,
+        Err(_) => {
+            drop(unsafe{ Box::from_raw(object) });
+            ptr::null_mut()
+        }
+    }
+}
+
+#[no_mangle]
+pub fn ktask_mutex_lock(mutex: *mut TaskMutex, timeout: u32) -> usize {
+    if mutex.is_null() {
+        return KernelError::InvalidArgument as usize;
+    }
+    error::status(unsafe{ (*mutex).lock_timeout(timeout) })
+}
+
+#[no_mangle]
+pub fn kcreate_semaphore(initial_count: usize, max_count: usize) -> *mut Semaphore {
+    if max_count == 0 || initial_count > max_count {
+        return ptr::null_mut();
+    }
+    match pool::try_new_pooled(Semaphore::new(initial_count, max_count)) {
+        Ok(semaphore) => register_object(ObjectKind::SemaphoreObject, semaphore),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub fn ksemaphore_take(semaphore: *mut Semaphore, timeout: u32) -> usize {
+    if semaphore.is_null() {
+        return KernelError::InvalidArgument as usize;
+    }
+    error::status(unsafe{ (*semaphore).take(timeout) })
+}
+
+#[no_mangle]
+pub fn ksemaphore_give(semaphore: *mut Semaphore) -> usize {
+    if semaphore.is_null() {
+        return KernelError::InvalidArgument as usize;
+    }
+    error::status(unsafe{ (*semaphore).give() })
+}
+
+#[no_mangle]
+pub fn kcreate_message_queue(item_size: usize, capacity: usize) -> *mut MessageQueue {
+    let queue = match MessageQueue::new(item_size, capacity) {
+        Ok(queue) => queue,
+        Err(_) => return ptr::null_mut(),
+    };
+    match pool::try_new_pooled(queue) {
+        Ok(queue) => register_object(ObjectKind::QueueObject, queue),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub fn kmessage_queue_send(queue: *mut MessageQueue, item: *const u8, timeout: u32) -> usize {
+    if queue.is_null() || item.is_null() {
+        return KernelError::InvalidArgument as usize;
+    }
+    error::status(unsafe{ (*queue).send(item, timeout) })
+}
+
+#[no_mangle]
+pub fn kmessage_queue_receive(queue: *mut MessageQueue, buffer: *mut u8, timeout: u32) -> usize {
+    if queue.is_null() || buffer.is_null() {
+        return KernelError::InvalidArgument as usize;
+    }
+    error::status(unsafe{ (*queue).receive(buffer, timeout) })
+}
+
+#[no_mangle]
+pub fn kmessage_queue_peek(queue: *mut MessageQueue, buffer: *mut u8) -> usize {
+    if queue.is_null() || buffer.is_null() {
+        return KernelError::InvalidArgument as usize;
+    }
+    error::status(unsafe{ (*queue).peek(buffer) })
+}
+
+#[no_mangle]
+pub fn kcreate_event_group() -> *mut EventGroup {
+    match pool::try_new_pooled(EventGroup::new()) {
+        Ok(group) => register_object(ObjectKind::EventGroupObject, group),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub fn kevent_group_set(group: *mut EventGroup, bits: u32) -> u32 {
+    if group.is_null() {
+        return 0;
+    }
+    unsafe{ (*group).set(bits) }
+}
+
+#[no_mangle]
+pub fn kevent_group_clear(group: *mut EventGroup, bits: u32) -> u32 {
+    if group.is_null() {
+        return 0;
+    }
+    unsafe{ (*group).clear(bits) }
+}
+
+#[no_mangle]
+fn kevent_group_wait(group: *mut EventGroup, request: *const EventWait, value: *mut u32) -> usize {
+    if group.is_null() || request.is_null() || value.is_null() {
+        return KernelError::InvalidArgument as usize;
+    }
+    unsafe {
+        let request = &*request;
+        error::status((*group).wait_into(request.bits, request.options, request.timeout, value))
+    }
+}
+
+#[no_mangle]
+pub fn kget_task_id() -> usize {
+    task::running_task_id()
+}
+
+#[no_mangle]
+pub fn kset_priority(task_id: usize, priority: usize) -> usize {
+    error::status(task::set_priority(task_id, priority))
+}
+
+#[no_mangle]
+pub fn kget_priority(task_id: usize, priority: *mut usize) -> usize {
+    if priority.is_null() {
+        return KernelError::InvalidArgument as usize;
+    }
+    match task::get_priority(task_id) {
+        Some(value) => {
+            unsafe { *priority = value };
+            SUCCESS
+        }
+        None => KernelError::InvalidArgument as usize,
+    }
+}
+
+#[no_mangle]
+pub fn ksuspend_task(task_id: usize) -> usize {
+    error::status(task::suspend(task_id))
+}
+
+#[no_mangle]
+pub fn kresume_task(task_id: usize) -> usize {
+    error::status(task::resume(task_id))
+}
+
+#[no_mangle]
+pub fn knotify(task_id: usize, value: u32, action: NotifyAction) -> usize {
+    error::status(notification::notify(task_id, value, action))
+}
+
+#[no_mangle]
+pub fn knotify_wait(timeout: u32, value: *mut u32) -> usize {
+    if value.is_null() {
+        return KernelError::InvalidArgument as usize;
+    }
+    error::status(notification::notify_wait_into(timeout, value))
+}
+
+#[no_mangle]
+pub fn knotify_take(timeout: u32, value: *mut u32) -> usize {
+    if value.is_null() {
+        return KernelError::InvalidArgument as usize;
+    }
+    error::status(notification::notify_take_into(timeout, value))
+}
+
+#[no_mangle]
+pub fn kcreate_stream_buffer(size: usize, trigger_level: usize) -> *mut StreamBuffer {
+    let stream = match StreamBuffer::new(size, trigger_level) {
+        Ok(stream) => stream,
+        Err(_) => return ptr::null_mut(),
+    };
+    match pool::try_new_pooled(stream) {
+        Ok(stream) => register_object(ObjectKind::StreamBufferObject, stream),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub fn kstream_buffer_write(stream: *mut StreamBuffer, data: *const u8, len: usize) -> usize {
+    if stream.is_null() || data.is_null() {
+        return 0;
+    }
+    unsafe{ (*stream).write(data, len) }
+}
+
+#[no_mangle]
+pub fn kstream_buffer_read(stream: *mut StreamBuffer, buffer: *mut u8, len: usize) -> usize {
+    if stream.is_null() || buffer.is_null() {
+        return 0;
+    }
+    unsafe{ (*stream).try_read(buffer, len) }
+}
+
+#[no_mangle]
+pub fn kstream_buffer_wait(stream: *mut StreamBuffer, timeout: u32) -> usize {
+    if stream.is_null() {
+        return KernelError::InvalidArgument as usize;
+    }
+    error::status(unsafe{ (*stream).wait_for_data(timeout) })
+}
+
+#[no_mangle]
+pub fn kcreate_barrier(parties: usize) -> *mut Barrier {
+    if parties == 0 {
+        return ptr::null_mut();
+    }
+    match pool::try_new_pooled(Barrier::new(parties)) {
+        Ok(barrier) => register_object(ObjectKind::BarrierObject, barrier),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub fn kbarrier_wait(barrier: *mut Barrier, timeout: u32) -> usize {
+    if barrier.is_null() {
+        return KernelError::InvalidArgument as usize;
+    }
+    error::status(unsafe{ (*barrier).wait_timeout(timeout) })
+}
+
+#[no_mangle]
+pub fn kcreate_timer(callback: TimerCallback, arg: *mut u8, period: u32, auto_reload: bool) -> *mut Timer {
+    if period == 0 || period == wait::WAIT_FOREVER || timer::start_daemon().is_err() {
+        return ptr::null_mut();
+    }
+    match pool::try_new_pooled(Timer::new(callback, arg, period, auto_reload)) {
+        Ok(timer) => register_object(ObjectKind::TimerObject, timer),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub fn ktimer_start(timer: *mut Timer) -> usize {
+    if timer.is_null() {
+        return KernelError::InvalidArgument as usize;
+    }
+    error::status(unsafe{ (*timer).start() })
+}
+
+#[no_mangle]
+pub fn ktimer_stop(timer: *mut Timer) -> usize {
+    if timer.is_null() {
+        return KernelError::InvalidArgument as usize;
+    }
+    unsafe{ (*timer).stop() };
+    error::SUCCESS
+}
+
+#[no_mangle]
+pub fn ktimer_reset(timer: *mut Timer) -> usize {
+    if timer.is_null() {
+        return KernelError::InvalidArgument as usize;
+    }
+    error::status(unsafe{ (*timer).reset() })
+}
+
+#[no_mangle]
+pub fn ktimer_change_period(timer: *mut Timer, period: u32) -> usize {
+    if timer.is_null() {
+        return KernelError::InvalidArgument as usize;
+    }
+    error::status(unsafe{ (*timer).change_period(period) })
+}
+
+#[no_mangle]
+pub fn ktask_mutex_unlock(mutex: *mut TaskMutex) -> usize {
+    if mutex.is_null() {
+        return KernelError::InvalidArgument as usize;
+    }
+    error::status(unsafe{ (*mutex).unlock() })
+}
+
+/*
+- kcreate_pool(), brief description:
+    The pool's descriptor and its storage are allocated from the heap. The
+    storage is allocated with a single request, and it is never given back
+    to the heap, therefore the pool's blocks never fragment it.
+*/
+#[no_mangle]
+pub fn kcreate_pool(block_size: usize, blocks: usize) -> *mut LockedPool {
+    if block_size == 0 || blocks == 0 {
+        return ptr::null_mut();
+    }
+
+    let pool = LockedPool::new(block_size, blocks);
+    let storage_size = pool.lock().storage_size();
+    let layout = match Layout::from_size_align(storage_size, align_of::<usize>()) {
+        Ok(layout) => layout,
+        Err(_) => return ptr::null_mut(),
+    };
+
+    let storage = unsafe{ alloc(layout) };
+    if storage.is_null() {
+        return ptr::null_mut();
+    }
+    pool.init(storage as usize);
+
+    match Box::try_new(pool) {
+        Ok(pool) => Box::into_raw(pool),
+        Err(_) => {
+            unsafe{ dealloc(storage, layout) };
+            ptr::null_mut()
+        }
+    }
+}
+
+#[no_mangle]
+pub fn kpool_alloc(pool: *mut LockedPool) -> *mut u8 {
+    if pool.is_null() {
+        return ptr::null_mut();
+    }
+    match unsafe{ (*pool).alloc() } {
+        Some(block) => block,
+        None => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub fn kpool_free(pool: *mut LockedPool, block: *mut u8) {
+    if pool.is_null() || block.is_null() {
+        return;
+    }
+    // Blocks that do not belong to the pool, or that are already free, are
+    // ignored
+    unsafe{
+        let _ = (*pool).free_checked(block);
+    }
 }
 
 //this function does the context switch for a task
@@ -188,23 +758,15 @@ pub unsafe fn task_switch() {
 
 /*
 This function serves as prologue to task_switch(), and it
-returns pointer to the currently running task. It is needed
-to correctly handle return from task_switch to the caller.
+returns pointer to the task whose context is loaded in the CPU
+(see `task::CURRENT`). It is needed to correctly handle return
+from task_switch to the caller.
 */
 
 #[no_mangle]
 #[cfg(target_arch = "arm")]
 pub unsafe extern "C" fn task_switch_prologue() {
-    use core::ptr;
-
-    let mut running_ptr = match &mut RUNNING {
-        Some(tcb) => {
-            &mut **tcb
-        }
-        None => {
-            0 as *mut TaskTCB
-        }
-    };
+    let mut running_ptr = task::CURRENT;
 
     // The pointer is saved into r0, the return register
     asm!(
@@ -0,0 +1,823 @@
+use super::{EventWait, PeriodicTiming, SysCallID};
+use crate::barrier::Barrier;
+use crate::error::{KernelError, SUCCESS};
+use crate::event_group::EventGroup;
+use crate::message_queue::MessageQueue;
+use crate::notification::NotifyAction;
+use crate::pool::LockedPool;
+use crate::semaphore::Semaphore;
+use crate::stream_buffer::StreamBuffer;
+use crate::task_mutex::TaskMutex;
+use crate::timer::{Timer, TimerCallback};
+use core::arch::asm;
+
+/*
+The system calls of the user application. Each of them requests a service
+of the kernel with the `svc` instruction, from a naked function: they only
+exist on the target, while the services they request are in the parent
+module.
+*/
+
+/* 
+This is the system call provided to the user application, in order to
+create a new task.
+
+It accepts a function pointer, a pointer to its arguments, and a priority.
+It returns 0 if the task was created, otherwise the code of the error.
+
+The function simply invokes the kernel to request the given service.
+*/
+#[no_mangle]
+#[naked]
+pub fn create_task(code: fn(*mut u8), args: *mut u8, priority: usize) -> usize {
+    unsafe {
+        asm!(
+            "svc {syscall_id}",
+            "mov pc, lr",
+            syscall_id = const SysCallID::CREATE_TASK_ID as u8,
+            options(noreturn)
+        );
+    }
+}
+
+/*
+Same as `create_task`, but the task can allocate at most `heap_quota` bytes
+from the heap. Allocations that would exceed the quota fail.
+*/
+#[no_mangle]
+#[naked]
+pub fn create_task_with_quota(code: fn(*mut u8), args: *mut u8, priority: usize, heap_quota: usize) -> usize {
+    unsafe {
+        asm!(
+            "svc {syscall_id}",
+            "mov pc, lr",
+            syscall_id = const SysCallID::CREATE_TASK_WITH_QUOTA_ID as u8,
+            options(noreturn)
+        );
+    }
+}
+
+/*
+Same as `create_task`, but the task's time slice lasts `quantum_ms`
+milliseconds rather than DEFAULT_QUANTUM_MS. With a quantum of
+NO_TIME_SLICE the task is never switched out for the tasks with its same
+priority: it runs until it blocks, or a task with a higher priority is
+ready.
+*/
+#[no_mangle]
+#[naked]
+pub fn create_task_with_quantum(code: fn(*mut u8), args: *mut u8, priority: usize, quantum_ms: u32) -> usize {
+    unsafe {
+        asm!(
+            "svc {syscall_id}",
+            "mov pc, lr",
+            syscall_id = const SysCallID::CREATE_TASK_WITH_QUANTUM_ID as u8,
+            options(noreturn)
+        );
+    }
+}
+
+/*
+Same as `create_task`, but each job of the task must complete within
+`deadline_ms` milliseconds from its release. The first job is released
+right away, and the task calls `complete_job` once it is done: the next
+job is released the next time the task becomes ready after a wait. The
+waits in the middle of a job do not complete it. Deadline misses are
+counted in the task's statistics, and with the `edf` feature the tasks
+with the same priority are scheduled by earliest deadline.
+
+`create_task_with_deadline` returns 0 if the task was created, otherwise
+the code of the error: a deadline of NO_DEADLINE is an InvalidArgument.
+`complete_job` returns 0, or InvalidArgument if the calling task has no
+deadline or is periodic.
+*/
+#[no_mangle]
+#[naked]
+pub fn create_task_with_deadline(code: fn(*mut u8), args: *mut u8, priority: usize, deadline_ms: u32) -> usize {
+    unsafe {
+        asm!(
+            "svc {syscall_id}",
+            "mov pc, lr",
+            syscall_id = const SysCallID::CREATE_TASK_WITH_DEADLINE_ID as u8,
+            options(noreturn)
+        );
+    }
+}
+
+#[no_mangle]
+#[naked]
+pub fn complete_job() -> usize {
+    unsafe {
+        asm!(
+            "svc {syscall_id}",
+            "mov pc, lr",
+            syscall_id = const SysCallID::COMPLETE_JOB_ID as u8,
+            options(noreturn)
+        );
+    }
+}
+
+/*
+Creates a periodic task with the given priority. The kernel releases a job
+of the task every `period_ms` milliseconds, the first one right away: the
+task calls `wait_next_period` once its job is done, and sleeps until the
+next release. A job that has not called `wait_next_period` within
+`deadline_ms` milliseconds from its release misses its deadline: misses
+are counted in the task's statistics, and reported to the hook set with
+`kernel_set_deadline_miss_hook`. With a deadline of NO_DEADLINE the
+deadline equals the period.
+
+`create_periodic_task` returns 0 if the task was created, otherwise the
+code of the error. `wait_next_period` returns 0 once the next job is
+released, or InvalidArgument if the calling task is not periodic.
+*/
+#[no_mangle]
+pub fn create_periodic_task(code: fn(*mut u8), args: *mut u8, priority: usize, period_ms: u32, deadline_ms: u32) -> usize {
+    let timing = PeriodicTiming { period_ms, deadline_ms };
+    unsafe{ svc_create_periodic_task(code, args, priority, &timing) }
+}
+
+#[naked]
+unsafe fn svc_create_periodic_task(code: fn(*mut u8), args: *mut u8, priority: usize, timing: *const PeriodicTiming) -> usize {
+    asm!(
+        "svc {syscall_id}",
+        "mov pc, lr",
+        syscall_id = const SysCallID::CREATE_PERIODIC_TASK_ID as u8,
+        options(noreturn)
+    );
+}
+
+#[no_mangle]
+pub fn wait_next_period() -> usize {
+    // The wait always ends with the timeout, at the release of the job
+    match wait_result(unsafe{ svc_wait_next_period() }) {
+        status if status == KernelError::Timeout as usize => SUCCESS,
+        status => status,
+    }
+}
+
+#[naked]
+unsafe fn svc_wait_next_period() -> usize {
+    asm!(
+        "svc {syscall_id}",
+        "mov pc, lr",
+        syscall_id = const SysCallID::WAIT_NEXT_PERIOD_ID as u8,
+        options(noreturn)
+    );
+}
+
+/*
+Terminates the calling task. The heap memory still owned by the task is
+reclaimed by the kernel. The function never returns: the task spins until
+the scheduler switches it out for the last time.
+*/
+#[no_mangle]
+#[naked]
+pub fn exit_task() -> ! {
+    unsafe {
+        asm!(
+            "svc {syscall_id}",
+            "2:",
+            "b 2b",
+            syscall_id = const SysCallID::EXIT_TASK_ID as u8,
+            options(noreturn)
+        );
+    }
+}
+
+/*
+System calls that give the application access to fixed-size memory pools.
+
+`create_pool` returns a handle to a new pool of `blocks` blocks, each
+`block_size` bytes large, or a null pointer if there is not enough memory.
+`pool_alloc` returns a null pointer when the pool is exhausted.
+*/
+#[no_mangle]
+#[naked]
+pub fn create_pool(block_size: usize, blocks: usize) -> *mut LockedPool {
+    unsafe {
+        asm!(
+            "svc {syscall_id}",
+            "mov pc, lr",
+            syscall_id = const SysCallID::CREATE_POOL_ID as u8,
+            options(noreturn)
+        );
+    }
+}
+
+#[no_mangle]
+#[naked]
+pub fn pool_alloc(pool: *mut LockedPool) -> *mut u8 {
+    unsafe {
+        asm!(
+            "svc {syscall_id}",
+            "mov pc, lr",
+            syscall_id = const SysCallID::POOL_ALLOC_ID as u8,
+            options(noreturn)
+        );
+    }
+}
+
+#[no_mangle]
+#[naked]
+pub fn pool_free(pool: *mut LockedPool, ptr: *mut u8) {
+    unsafe {
+        asm!(
+            "svc {syscall_id}",
+            "mov pc, lr",
+            syscall_id = const SysCallID::POOL_FREE_ID as u8,
+            options(noreturn)
+        );
+    }
+}
+
+/*
+System calls that give the application access to task mutexes (see the
+`task_mutex` module).
+
+`create_task_mutex` returns a handle to a new mutex, or a null pointer if
+there is not enough memory. `task_mutex_lock` waits at most `timeout` ticks
+for the mutex: NO_WAIT only tries to lock it, WAIT_FOREVER waits for as long
+as needed, and Timeout is returned if the wait expires. Both
+`task_mutex_lock` and `task_mutex_unlock` return 0 on success, otherwise the
+code of the error.
+*/
+#[no_mangle]
+#[naked]
+pub fn create_task_mutex(recursive: bool) -> *mut TaskMutex {
+    unsafe {
+        asm!(
+            "svc {syscall_id}",
+            "mov pc, lr",
+            syscall_id = const SysCallID::CREATE_TASK_MUTEX_ID as u8,
+            options(noreturn)
+        );
+    }
+}
+
+#[no_mangle]
+pub fn task_mutex_lock(mutex: *mut TaskMutex, timeout: u32) -> usize {
+    wait_result(unsafe{ svc_task_mutex_lock(mutex, timeout) })
+}
+
+#[naked]
+unsafe fn svc_task_mutex_lock(mutex: *mut TaskMutex, timeout: u32) -> usize {
+    asm!(
+        "svc {syscall_id}",
+        "mov pc, lr",
+        syscall_id = const SysCallID::TASK_MUTEX_LOCK_ID as u8,
+        options(noreturn)
+    );
+}
+
+#[no_mangle]
+#[naked]
+pub fn task_mutex_unlock(mutex: *mut TaskMutex) -> usize {
+    unsafe {
+        asm!(
+            "svc {syscall_id}",
+            "mov pc, lr",
+            syscall_id = const SysCallID::TASK_MUTEX_UNLOCK_ID as u8,
+            options(noreturn)
+        );
+    }
+}
+
+/*
+System calls that give the application access to semaphores (see the
+`semaphore` module).
+
+`create_semaphore` returns a handle to a new semaphore, or a null pointer
+if there is not enough memory or too many kernel objects exist. A binary
+semaphore has a `max_count` of 1. `semaphore_take` waits at most `timeout`
+ticks for the semaphore, like `task_mutex_lock` does.
+
+Interrupt handlers must use `semaphore_give_from_isr` instead of
+`semaphore_give`, as they cannot invoke system calls.
+*/
+#[no_mangle]
+#[naked]
+pub fn create_semaphore(initial_count: usize, max_count: usize) -> *mut Semaphore {
+    unsafe {
+        asm!(
+            "svc {syscall_id}",
+            "mov pc, lr",
+            syscall_id = const SysCallID::CREATE_SEMAPHORE_ID as u8,
+            options(noreturn)
+        );
+    }
+}
+
+#[no_mangle]
+pub fn semaphore_take(semaphore: *mut Semaphore, timeout: u32) -> usize {
+    wait_result(unsafe{ svc_semaphore_take(semaphore, timeout) })
+}
+
+#[naked]
+unsafe fn svc_semaphore_take(semaphore: *mut Semaphore, timeout: u32) -> usize {
+    asm!(
+        "svc {syscall_id}",
+        "mov pc, lr",
+        syscall_id = const SysCallID::SEMAPHORE_TAKE_ID as u8,
+        options(noreturn)
+    );
+}
+
+#[no_mangle]
+#[naked]
+pub fn semaphore_give(semaphore: *mut Semaphore) -> usize {
+    unsafe {
+        asm!(
+            "svc {syscall_id}",
+            "mov pc, lr",
+            syscall_id = const SysCallID::SEMAPHORE_GIVE_ID as u8,
+            options(noreturn)
+        );
+    }
+}
+
+/*
+System calls that give the application access to message queues (see the
+`message_queue` module).
+
+`create_message_queue` returns a handle to a new queue of `capacity` items,
+each `item_size` bytes large, or a null pointer if it cannot be created.
+Items are copied from `item` when sent, and into `buffer` when received or
+peeked. Sending and receiving wait at most `timeout` ticks: with a timeout
+of 0 they only try, and fail with WouldBlock if they'd have to wait.
+
+Interrupt handlers must use `message_queue_send_from_isr` instead of
+`message_queue_send`, as they cannot invoke system calls.
+*/
+#[no_mangle]
+#[naked]
+pub fn create_message_queue(item_size: usize, capacity: usize) -> *mut MessageQueue {
+    unsafe {
+        asm!(
+            "svc {syscall_id}",
+            "mov pc, lr",
+            syscall_id = const SysCallID::CREATE_MESSAGE_QUEUE_ID as u8,
+            options(noreturn)
+        );
+    }
+}
+
+#[no_mangle]
+pub fn message_queue_send(queue: *mut MessageQueue, item: *const u8, timeout: u32) -> usize {
+    wait_result(unsafe{ svc_message_queue_send(queue, item, timeout) })
+}
+
+#[naked]
+unsafe fn svc_message_queue_send(queue: *mut MessageQueue, item: *const u8, timeout: u32) -> usize {
+    asm!(
+        "svc {syscall_id}",
+        "mov pc, lr",
+        syscall_id = const SysCallID::MESSAGE_QUEUE_SEND_ID as u8,
+        options(noreturn)
+    );
+}
+
+#[no_mangle]
+pub fn message_queue_receive(queue: *mut MessageQueue, buffer: *mut u8, timeout: u32) -> usize {
+    wait_result(unsafe{ svc_message_queue_receive(queue, buffer, timeout) })
+}
+
+#[naked]
+unsafe fn svc_message_queue_receive(queue: *mut MessageQueue, buffer: *mut u8, timeout: u32) -> usize {
+    asm!(
+        "svc {syscall_id}",
+        "mov pc, lr",
+        syscall_id = const SysCallID::MESSAGE_QUEUE_RECEIVE_ID as u8,
+        options(noreturn)
+    );
+}
+
+#[no_mangle]
+#[naked]
+pub fn message_queue_peek(queue: *mut MessageQueue, buffer: *mut u8) -> usize {
+    unsafe {
+        asm!(
+            "svc {syscall_id}",
+            "mov pc, lr",
+            syscall_id = const SysCallID::MESSAGE_QUEUE_PEEK_ID as u8,
+            options(noreturn)
+        );
+    }
+}
+
+/*
+System calls that give the application access to event groups (see the
+`event_group` module).
+
+`create_event_group` returns a handle to a new event group, with all the
+flags cleared, or a null pointer if it cannot be created. `event_group_set`
+and `event_group_clear` return the value of the flags after setting them,
+and before clearing them, respectively.
+
+`event_group_wait` waits at most `timeout` ticks for the flags in `bits`,
+according to `options` (EVENT_WAIT_ALL, EVENT_CLEAR_ON_EXIT). It returns 0
+on success, and writes the value of the flags that satisfied the wait to
+`value`, otherwise it returns the code of the error.
+
+Interrupt handlers must use `event_group_set_from_isr` instead of
+`event_group_set`, as they cannot invoke system calls.
+*/
+#[no_mangle]
+#[naked]
+pub fn create_event_group() -> *mut EventGroup {
+    unsafe {
+        asm!(
+            "svc {syscall_id}",
+            "mov pc, lr",
+            syscall_id = const SysCallID::CREATE_EVENT_GROUP_ID as u8,
+            options(noreturn)
+        );
+    }
+}
+
+#[no_mangle]
+#[naked]
+pub fn event_group_set(group: *mut EventGroup, bits: u32) -> u32 {
+    unsafe {
+        asm!(
+            "svc {syscall_id}",
+            "mov pc, lr",
+            syscall_id = const SysCallID::EVENT_GROUP_SET_ID as u8,
+            options(noreturn)
+        );
+    }
+}
+
+#[no_mangle]
+#[naked]
+pub fn event_group_clear(group: *mut EventGroup, bits: u32) -> u32 {
+    unsafe {
+        asm!(
+            "svc {syscall_id}",
+            "mov pc, lr",
+            syscall_id = const SysCallID::EVENT_GROUP_CLEAR_ID as u8,
+            options(noreturn)
+        );
+    }
+}
+
+#[no_mangle]
+pub fn event_group_wait(group: *mut EventGroup, bits: u32, options: u32, timeout: u32, value: *mut u32) -> usize {
+    let request = EventWait { bits, options, timeout };
+    wait_result(unsafe{ svc_event_group_wait(group, &request, value) })
+}
+
+#[naked]
+unsafe fn svc_event_group_wait(group: *mut EventGroup, request: *const EventWait, value: *mut u32) -> usize {
+    asm!(
+        "svc {syscall_id}",
+        "mov pc, lr",
+        syscall_id = const SysCallID::EVENT_GROUP_WAIT_ID as u8,
+        options(noreturn)
+    );
+}
+
+/*
+System calls for direct-to-task notifications (see the `notification`
+module).
+
+`get_task_id` returns the id of the calling task, which other tasks use to
+notify it. `notify` returns 0 on success, otherwise the code of the error.
+`notify_wait` waits at most `timeout` ticks for the calling task to be
+notified: it returns 0 on success, and writes the notification word to
+`value`, otherwise it returns the code of the error. `notify_take` is the
+same, but it takes a single notification sent with the Increment action:
+the word is decremented instead of reset, and `value` gets its previous
+value.
+
+Interrupt handlers must use `notify_from_isr` instead of `notify`, as they
+cannot invoke system calls.
+*/
+#[no_mangle]
+#[naked]
+pub fn get_task_id() -> usize {
+    unsafe {
+        asm!(
+            "svc {syscall_id}",
+            "mov pc, lr",
+            syscall_id = const SysCallID::GET_TASK_ID_ID as u8,
+            options(noreturn)
+        );
+    }
+}
+
+#[no_mangle]
+#[naked]
+pub fn notify(task_id: usize, value: u32, action: NotifyAction) -> usize {
+    unsafe {
+        asm!(
+            "svc {syscall_id}",
+            "mov pc, lr",
+            syscall_id = const SysCallID::NOTIFY_ID as u8,
+            options(noreturn)
+        );
+    }
+}
+
+#[no_mangle]
+pub fn notify_wait(timeout: u32, value: *mut u32) -> usize {
+    wait_result(unsafe{ svc_notify_wait(timeout, value) })
+}
+
+#[naked]
+unsafe fn svc_notify_wait(timeout: u32, value: *mut u32) -> usize {
+    asm!(
+        "svc {syscall_id}",
+        "mov pc, lr",
+        syscall_id = const SysCallID::NOTIFY_WAIT_ID as u8,
+        options(noreturn)
+    );
+}
+
+#[no_mangle]
+pub fn notify_take(timeout: u32, value: *mut u32) -> usize {
+    wait_result(unsafe{ svc_notify_take(timeout, value) })
+}
+
+#[naked]
+unsafe fn svc_notify_take(timeout: u32, value: *mut u32) -> usize {
+    asm!(
+        "svc {syscall_id}",
+        "mov pc, lr",
+        syscall_id = const SysCallID::NOTIFY_TAKE_ID as u8,
+        options(noreturn)
+    );
+}
+
+/*
+Task handles are the ids returned by `get_task_id`.
+
+`set_priority` changes the priority of a task. The task is moved to the
+ready queue of its new priority right away, and the calling task is
+preempted if a ready task now has a higher priority. A task that holds
+task mutexes keeps the priority it inherited until it releases them.
+`get_priority` writes the priority the task is currently scheduled with
+to `priority`.
+
+`suspend_task` stops a task from being scheduled until `resume_task` is
+called on it; a task can suspend itself. A blocked task completes its
+wait, but it is not scheduled until it is resumed. The idle task cannot be
+suspended.
+
+They return 0 on success, otherwise the code of the error.
+*/
+#[no_mangle]
+#[naked]
+pub fn set_priority(task_id: usize, priority: usize) -> usize {
+    unsafe {
+        asm!(
+            "svc {syscall_id}",
+            "mov pc, lr",
+            syscall_id = const SysCallID::SET_PRIORITY_ID as u8,
+            options(noreturn)
+        );
+    }
+}
+
+#[no_mangle]
+#[naked]
+pub fn get_priority(task_id: usize, priority: *mut usize) -> usize {
+    unsafe {
+        asm!(
+            "svc {syscall_id}",
+            "mov pc, lr",
+            syscall_id = const SysCallID::GET_PRIORITY_ID as u8,
+            options(noreturn)
+        );
+    }
+}
+
+#[no_mangle]
+#[naked]
+pub fn suspend_task(task_id: usize) -> usize {
+    unsafe {
+        asm!(
+            "svc {syscall_id}",
+            "mov pc, lr",
+            syscall_id = const SysCallID::SUSPEND_TASK_ID as u8,
+            options(noreturn)
+        );
+    }
+}
+
+#[no_mangle]
+#[naked]
+pub fn resume_task(task_id: usize) -> usize {
+    unsafe {
+        asm!(
+            "svc {syscall_id}",
+            "mov pc, lr",
+            syscall_id = const SysCallID::RESUME_TASK_ID as u8,
+            options(noreturn)
+        );
+    }
+}
+
+/*
+System calls that give the application access to stream buffers (see the
+`stream_buffer` module).
+
+`create_stream_buffer` returns a handle to a new buffer of `size` bytes, or
+a null pointer if it cannot be created. `stream_buffer_write` returns the
+number of bytes written, which is less than `len` if the buffer is full.
+`stream_buffer_read` waits at most `timeout` ticks for `trigger_level`
+bytes to be available, then it reads up to `len` bytes, and returns the
+number of bytes read.
+
+Interrupt handlers must use `stream_buffer_write_from_isr` instead of
+`stream_buffer_write`, as they cannot invoke system calls.
+*/
+#[no_mangle]
+#[naked]
+pub fn create_stream_buffer(size: usize, trigger_level: usize) -> *mut StreamBuffer {
+    unsafe {
+        asm!(
+            "svc {syscall_id}",
+            "mov pc, lr",
+            syscall_id = const SysCallID::CREATE_STREAM_BUFFER_ID as u8,
+            options(noreturn)
+        );
+    }
+}
+
+#[no_mangle]
+#[naked]
+pub fn stream_buffer_write(stream: *mut StreamBuffer, data: *const u8, len: usize) -> usize {
+    unsafe {
+        asm!(
+            "svc {syscall_id}",
+            "mov pc, lr",
+            syscall_id = const SysCallID::STREAM_BUFFER_WRITE_ID as u8,
+            options(noreturn)
+        );
+    }
+}
+
+// The bytes are read once the wait is over, by a second system call: there
+// is a single reader, so no one else can take them in the meantime
+#[no_mangle]
+pub fn stream_buffer_read(stream: *mut StreamBuffer, buffer: *mut u8, len: usize, timeout: u32) -> usize {
+    unsafe {
+        wait_result(svc_stream_buffer_wait(stream, timeout));
+        svc_stream_buffer_read(stream, buffer, len)
+    }
+}
+
+#[naked]
+unsafe fn svc_stream_buffer_wait(stream: *mut StreamBuffer, timeout: u32) -> usize {
+    asm!(
+        "svc {syscall_id}",
+        "mov pc, lr",
+        syscall_id = const SysCallID::STREAM_BUFFER_WAIT_ID as u8,
+        options(noreturn)
+    );
+}
+
+#[naked]
+unsafe fn svc_stream_buffer_read(stream: *mut StreamBuffer, buffer: *mut u8, len: usize) -> usize {
+    asm!(
+        "svc {syscall_id}",
+        "mov pc, lr",
+        syscall_id = const SysCallID::STREAM_BUFFER_READ_ID as u8,
+        options(noreturn)
+    );
+}
+
+/*
+System calls that give the application access to barriers (see the
+`barrier` module).
+
+`create_barrier` returns a handle to a new barrier for a group of `parties`
+tasks, or a null pointer if it cannot be created. `barrier_wait` waits at
+most `timeout` ticks for the rest of the group to arrive.
+*/
+#[no_mangle]
+#[naked]
+pub fn create_barrier(parties: usize) -> *mut Barrier {
+    unsafe {
+        asm!(
+            "svc {syscall_id}",
+            "mov pc, lr",
+            syscall_id = const SysCallID::CREATE_BARRIER_ID as u8,
+            options(noreturn)
+        );
+    }
+}
+
+#[no_mangle]
+pub fn barrier_wait(barrier: *mut Barrier, timeout: u32) -> usize {
+    wait_result(unsafe{ svc_barrier_wait(barrier, timeout) })
+}
+
+#[naked]
+unsafe fn svc_barrier_wait(barrier: *mut Barrier, timeout: u32) -> usize {
+    asm!(
+        "svc {syscall_id}",
+        "mov pc, lr",
+        syscall_id = const SysCallID::BARRIER_WAIT_ID as u8,
+        options(noreturn)
+    );
+}
+
+/*
+System calls that give the application access to software timers (see the
+`timer` module).
+
+`create_timer` returns a handle to a new timer, or a null pointer if it
+cannot be created. The timer is created stopped: `timer_start` starts it,
+`timer_reset` restarts it from the current tick, and `timer_change_period`
+gives it a new period and restarts it. The callback runs in the timer
+daemon task, which is created along with the first timer.
+*/
+#[no_mangle]
+#[naked]
+pub fn create_timer(callback: TimerCallback, arg: *mut u8, period: u32, auto_reload: bool) -> *mut Timer {
+    unsafe {
+        asm!(
+            "svc {syscall_id}",
+            "mov pc, lr",
+            syscall_id = const SysCallID::CREATE_TIMER_ID as u8,
+            options(noreturn)
+        );
+    }
+}
+
+#[no_mangle]
+#[naked]
+pub fn timer_start(timer: *mut Timer) -> usize {
+    unsafe {
+        asm!(
+            "svc {syscall_id}",
+            "mov pc, lr",
+            syscall_id = const SysCallID::TIMER_START_ID as u8,
+            options(noreturn)
+        );
+    }
+}
+
+#[no_mangle]
+#[naked]
+pub fn timer_stop(timer: *mut Timer) -> usize {
+    unsafe {
+        asm!(
+            "svc {syscall_id}",
+            "mov pc, lr",
+            syscall_id = const SysCallID::TIMER_STOP_ID as u8,
+            options(noreturn)
+        );
+    }
+}
+
+#[no_mangle]
+#[naked]
+pub fn timer_reset(timer: *mut Timer) -> usize {
+    unsafe {
+        asm!(
+            "svc {syscall_id}",
+            "mov pc, lr",
+            syscall_id = const SysCallID::TIMER_RESET_ID as u8,
+            options(noreturn)
+        );
+    }
+}
+
+#[no_mangle]
+#[naked]
+pub fn timer_change_period(timer: *mut Timer, period: u32) -> usize {
+    unsafe {
+        asm!(
+            "svc {syscall_id}",
+            "mov pc, lr",
+            syscall_id = const SysCallID::TIMER_CHANGE_PERIOD_ID as u8,
+            options(noreturn)
+        );
+    }
+}
+
+/*
+When a system call blocks the calling task, it returns the Pending status.
+By the time the task gets to see that status it has been resumed, so it
+asks the kernel for the actual outcome of the wait.
+*/
+fn wait_result(status: usize) -> usize {
+    if status == KernelError::Pending as usize {
+        unsafe{ svc_wait_result() }
+    } else {
+        status
+    }
+}
+
+#[naked]
+unsafe fn svc_wait_result() -> usize {
+    asm!(
+        "svc {syscall_id}",
+        "mov pc, lr",
+        syscall_id = const SysCallID::WAIT_RESULT_ID as u8,
+        options(noreturn)
+    );
+}
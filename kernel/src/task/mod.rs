@@ -1,4 +1,13 @@
-use crate::{mutex::Mutex, utility::memcpy, WAITING_QUEUE};
+use crate::{mutex::{CriticalSection, Mutex}, utility::memcpy, WAITING_QUEUE, HEAP};
+use crate::error::KernelError;
+use crate::config::{DEFAULT_QUANTUM_MS, NO_DEADLINE};
+use crate::deadline;
+use crate::scheduler::{KernelScheduler, Scheduler};
+use crate::idle;
+use crate::time;
+use crate::stats;
+use crate::task_mutex::{self, TaskMutex};
+use crate::wait::{self, TimeoutHook};
 use alloc::boxed::Box;
 use core::borrow::BorrowMut;
 use core::marker::Sync;
@@ -12,6 +21,7 @@ pub const STACK_SIZE: usize = 4096; //size of the stack for every task
 
 //global variables
 pub const MAX_PRIORITY: u8 = 10; //max priority and size of the priority queues array
+pub const MAX_TASKS: usize = 16; //max number of tasks that can exist at the same time
 
 /*
 RUNNING is the pointer to the currently executing task. It is 
@@ -20,6 +30,46 @@ null.
 */
 pub static mut RUNNING: Option<Box<TaskTCB>> = None; 
 
+/*
+CURRENT points to the task whose context is loaded in the CPU. It usually
+is the RUNNING task, but a task that blocks is moved to a wait queue right
+away, and it keeps executing until the context switch it requested takes
+place: its context must still be saved when that happens.
+*/
+pub static mut CURRENT: *mut TaskTCB = ptr::null_mut();
+
+/*
+ZOMBIE holds the TCB of the task that terminated at the last context switch.
+The switch keeps running on that task's stack until the next task is loaded,
+therefore the TCB, and the stack in it, is only dropped at the next switch.
+*/
+static mut ZOMBIE: Option<Box<TaskTCB>> = None;
+
+/*
+The table of all the tasks currently alive, used to look a task up by its
+id. A task's id is its position in the table plus one: id 0 is reserved for
+the kernel itself, and is the id of tasks that were never registered.
+*/
+static mut task_table: Mutex<TaskTable> = Mutex::new(TaskTable::new());
+pub static TASK_TABLE: &Mutex<TaskTable> = unsafe{&task_table};
+
+// The id used for resources owned by the kernel rather than by a task
+pub const KERNEL_ID: usize = 0;
+
+// The tasks that are suspended, until they are resumed
+static mut suspended_tasks: Mutex<Queue> = Mutex::new(Queue::new());
+pub static SUSPENDED_TASKS: &Mutex<Queue> = unsafe{&suspended_tasks};
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskState {
+    Ready,
+    Running,
+    Blocked,
+    Suspended,
+    Terminated,
+}
+
 // Definition of the Task Control Block.
 // 'repr(C)' is added to ensure that the struct's fields are stored
 // in the order they appear in the definition: 
@@ -32,6 +82,36 @@ pub struct TaskTCB {
     pub priority: usize,            //priority of the task
     pub stack: [u8; STACK_SIZE], //stack associated to the task
     pub next: TcbBlock,          //reference to the next Task_TCB
+    pub id: usize,               //id of the task, assigned by the task table
+    pub state: TaskState,        //current state of the task
+    pub heap_used: usize,        //bytes of heap currently allocated by the task
+    pub heap_quota: usize,       //max bytes of heap the task can allocate, 0 if unlimited
+    pub base_priority: usize,    //priority assigned to the task, without inheritance
+    pub held_mutexes: usize,     //number of task mutexes currently owned by the task
+    pub waiting_on: *mut Queue,  //wait queue the task is blocked in, null if not blocked
+    pub blocked_on_mutex: *const TaskMutex, //task mutex the task is waiting for, if any
+    pub timeout_hook: Option<TimeoutHook>, //called if the wait times out, see the `wait` module
+    pub wait_object: *const (),  //object passed to the timeout hook
+    pub wake_tick: Option<u32>,  //tick at which the wait times out, None if it never does
+    pub wait_result: Result<(), KernelError>, //outcome of the last wait
+    pub wait_buffer: *mut u8,    //data the task sends, or receives, while blocked
+    pub wait_value: u32,         //what the task waits for, e.g. the bits of an event group
+    pub wait_options: u32,       //how the task waits, e.g. for all the bits or any of them
+    pub notification_value: u32, //notification word, see the `notification` module
+    pub notification_pending: bool, //true if the task was notified since its last wait
+    pub cpu_time: u64,           //time spent running, see the `stats` module
+    pub context_switches: u32,   //number of times the task was switched in
+    pub last_run: u32,           //tick at which the task was last switched in
+    pub charged_at: u32,         //time up to which the task was charged for its CPU time
+    pub quantum: u32,            //length of the time slice in ticks, NO_TIME_SLICE for none
+    pub slice_left: u32,         //ticks left in the current time slice
+    pub suspended: bool,         //true if the task was suspended, see `suspend`
+    pub period: u32,             //period of a periodic task in ticks, 0 if it has none
+    pub relative_deadline: u32,  //deadline of each job in ticks, NO_DEADLINE for none
+    pub deadline: Option<u32>,   //absolute deadline of the current job, see the `deadline` module
+    pub deadline_missed: bool,   //true if the current job missed its deadline
+    pub deadline_misses: u32,    //number of jobs that missed their deadline
+    pub next_release: Option<u32>, //tick of the next job of a periodic task, None if not periodic
 }
 
 impl TaskTCB {
@@ -43,6 +123,36 @@ impl TaskTCB {
             priority: p,
             stp:  0x0 as *mut u8,
             stack: [0; STACK_SIZE],
+            id: KERNEL_ID,
+            state: TaskState::Ready,
+            heap_used: 0,
+            heap_quota: 0,
+            base_priority: p,
+            held_mutexes: 0,
+            waiting_on: ptr::null_mut(),
+            blocked_on_mutex: ptr::null(),
+            timeout_hook: None,
+            wait_object: ptr::null(),
+            wake_tick: None,
+            wait_result: Ok(()),
+            wait_buffer: ptr::null_mut(),
+            wait_value: 0,
+            wait_options: 0,
+            notification_value: 0,
+            notification_pending: false,
+            cpu_time: 0,
+            context_switches: 0,
+            last_run: 0,
+            charged_at: 0,
+            quantum: time::ms_to_ticks(DEFAULT_QUANTUM_MS),
+            slice_left: 0,
+            suspended: false,
+            period: 0,
+            relative_deadline: NO_DEADLINE,
+            deadline: None,
+            deadline_missed: false,
+            deadline_misses: 0,
+            next_release: None,
         };
 
         // The stack pointer is initialized to the start address of the task's
@@ -78,20 +188,309 @@ impl TaskTCB {
 }
 
 /*
-This struct is simply a wrapper to the `Queue` struct,
-it uses a mutex to encapsulate the queue.
+The task table holds raw pointers to the TCBs, which are owned by whichever
+queue the task currently sits in. A task must be unregistered before its
+TCB is dropped.
+*/
+pub struct TaskTable {
+    tasks: [*mut TaskTCB; MAX_TASKS],
+}
+
+impl TaskTable {
+    pub const fn new() -> Self {
+        Self { tasks: [ptr::null_mut(); MAX_TASKS] }
+    }
+
+    //registers the task and assigns it its id, returns None if the table is full
+    pub fn register(&mut self, tcb: &mut TaskTCB) -> Option<usize> {
+        let slot = self.tasks.iter().position(|task| task.is_null())?;
+        self.tasks[slot] = tcb as *mut TaskTCB;
+        tcb.id = slot + 1;
+        Some(tcb.id)
+    }
+
+    pub fn unregister(&mut self, id: usize) {
+        if id != KERNEL_ID && id <= MAX_TASKS {
+            self.tasks[id - 1] = ptr::null_mut();
+        }
+    }
+
+    //returns the task with the given id, if it exists
+    pub fn get(&mut self, id: usize) -> Option<&mut TaskTCB> {
+        if id == KERNEL_ID || id > MAX_TASKS {
+            return None;
+        }
+        unsafe { self.tasks[id - 1].as_mut() }
+    }
+
+    //returns the number of registered tasks
+    pub fn count_tasks(&self) -> usize {
+        self.tasks.iter().filter(|task| !task.is_null()).count()
+    }
+}
+
+unsafe impl Sync for TaskTable {}
+
+/*
+The context the kernel is called from. Tasks are switched in by
+`task_switch` from the PendSV handler, and they keep running in its
+context: PendSV counts as the task's own code, as thread mode does.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallerContext {
+    Task,      // the code of the RUNNING task
+    SysCall,   // the SVCall handler, on behalf of the RUNNING task
+    Interrupt, // any other exception or interrupt handler
+}
+
+pub fn caller_context() -> CallerContext {
+    use cortex_m::peripheral::{scb::{Exception, VectActive}, SCB};
+
+    match SCB::vect_active() {
+        VectActive::ThreadMode => CallerContext::Task,
+        VectActive::Exception(Exception::PendSV) => CallerContext::Task,
+        VectActive::Exception(Exception::SVCall) => CallerContext::SysCall,
+        _ => CallerContext::Interrupt,
+    }
+}
+
+/*
+Returns the id of the task on whose behalf the kernel is running, which
+the heap charges for its allocations:
+- the RUNNING task, while its code executes;
+- the kernel, while handling a system call: the objects it creates, such as
+  the TCBs of new tasks, outlive the calling task;
+- the kernel, while handling any other exception or interrupt.
+*/
+pub fn current_task_id() -> usize {
+    match caller_context() {
+        CallerContext::Task => running_task_id(),
+        _ => KERNEL_ID,
+    }
+}
+
+/*
+Returns the id of the RUNNING task, whatever the active exception: unlike
+`current_task_id`, system calls see the task that invoked them.
+*/
+pub fn running_task_id() -> usize {
+    unsafe {
+        match &RUNNING {
+            Some(tcb) => tcb.id,
+            None => KERNEL_ID,
+        }
+    }
+}
+
+/* Returns the priority of the RUNNING task, or None if no task is running */
+pub fn running_priority() -> Option<usize> {
+    unsafe { RUNNING.as_ref().map(|tcb| tcb.priority) }
+}
+
+/*
+Changes the priority a task is scheduled with. A ready task is moved to the
+ready queue of its new priority, and a blocked one is moved within its wait
+queue, so that wait queues stay sorted.
+*/
+pub fn reprioritize(id: usize, priority: usize) {
+    let _section = CriticalSection::enter();
+    let tcb = match TASK_TABLE.lock().get(id) {
+        Some(tcb) => tcb as *mut TaskTCB,
+        None => return,
+    };
+
+    unsafe {
+        match (*tcb).state {
+            TaskState::Ready => {
+                if let Some(mut block) = WAITING_QUEUE.remove(id) {
+                    block.priority = priority;
+                    WAITING_QUEUE.enqueue(block);
+                }
+            }
+            TaskState::Blocked if !(*tcb).waiting_on.is_null() => {
+                let queue = &mut *(*tcb).waiting_on;
+                if let Some(mut block) = queue.remove(id) {
+                    block.priority = priority;
+                    queue.insert_by_priority(block);
+                }
+            }
+            _ => (*tcb).priority = priority,
+        }
+    }
+}
+
+/*
+Changes the priority of a task at runtime: the task is moved to the ready
+queue of its new priority right away, and the RUNNING task is preempted if
+a ready task now has to run first. Task mutexes are taken into account (see
+`task_mutex::update_priority`): a task keeps the priority it inherited from
+the tasks waiting for its mutexes, and a task waiting for a mutex passes
+its new priority on to the owner.
+*/
+pub fn set_priority(id: usize, priority: usize) -> Result<(), KernelError> {
+    if priority >= MAX_PRIORITY as usize {
+        return Err(KernelError::InvalidArgument);
+    }
+    let _section = CriticalSection::enter();
+    match TASK_TABLE.lock().get(id) {
+        Some(tcb) if !idle::is_idle(id) => tcb.base_priority = priority,
+        _ => return Err(KernelError::InvalidArgument),
+    }
+    task_mutex::update_priority(id);
+
+    if let Some(running) = unsafe{ RUNNING.as_deref() } {
+        if WAITING_QUEUE.first_preempts(running) {
+            wait::request_switch();
+        }
+    }
+    Ok(())
+}
+
+/* Returns the priority a task is currently scheduled with, or None if there is no such task */
+pub fn get_priority(id: usize) -> Option<usize> {
+    TASK_TABLE.lock().get(id).map(|tcb| tcb.priority)
+}
+
+/*
+Suspends a task, which is not scheduled again until it is resumed. A ready
+task is suspended right away, and the RUNNING task as soon as it is
+switched out. A blocked task completes its wait, but it is suspended
+instead of becoming ready. The idle task cannot be suspended.
+*/
+pub fn suspend(id: usize) -> Result<(), KernelError> {
+    let _section = CriticalSection::enter();
+    let tcb = match TASK_TABLE.lock().get(id) {
+        Some(tcb) if !idle::is_idle(id) => tcb as *mut TaskTCB,
+        _ => return Err(KernelError::InvalidArgument),
+    };
+
+    unsafe {
+        (*tcb).suspended = true;
+        match (*tcb).state {
+            TaskState::Ready => {
+                if let Some(mut block) = WAITING_QUEUE.remove(id) {
+                    block.state = TaskState::Suspended;
+                    SUSPENDED_TASKS.lock().enqueue(block);
+                }
+            }
+            TaskState::Running => wait::request_switch(),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/*
+Resumes a suspended task, which becomes ready, and preempts the RUNNING
+task if it has a higher priority. A suspension that has not taken effect
+yet is cancelled.
+*/
+pub fn resume(id: usize) -> Result<(), KernelError> {
+    let _section = CriticalSection::enter();
+    let tcb = match TASK_TABLE.lock().get(id) {
+        Some(tcb) => tcb as *mut TaskTCB,
+        None => return Err(KernelError::InvalidArgument),
+    };
+
+    unsafe {
+        (*tcb).suspended = false;
+        if (*tcb).state == TaskState::Suspended {
+            if let Some(mut block) = SUSPENDED_TASKS.lock().remove(id) {
+                block.state = TaskState::Ready;
+                wait::enqueue_ready(block);
+            }
+        }
+    }
+    Ok(())
+}
+
+/*
+Charges one tick to the time slice of the RUNNING task. Returns true if the
+RUNNING task must be switched out: the scheduler decides when its slice is
+over, see `Scheduler::on_tick`, and the idle task gives way as soon as
+another task is ready.
+*/
+pub fn time_slice_tick() -> bool {
+    let _section = CriticalSection::enter();
+    let tcb = match unsafe{ RUNNING.as_mut() } {
+        Some(tcb) => tcb,
+        None => return false,
+    };
+    if idle::is_idle(tcb.id) {
+        return !WAITING_QUEUE.empty();
+    }
+    WAITING_QUEUE.on_tick(tcb)
+}
+
+/*
+Heap accounting: the bytes allocated by a task are charged to it, and fail
+if they exceed its quota. Allocations made by the kernel are never limited.
+*/
+pub fn charge_heap(id: usize, bytes: usize) -> bool {
+    let mut table = TASK_TABLE.lock();
+    match table.get(id) {
+        None => true,
+        Some(tcb) => {
+            if tcb.heap_quota != 0 && tcb.heap_used + bytes > tcb.heap_quota {
+                return false;
+            }
+            tcb.heap_used += bytes;
+            true
+        }
+    }
+}
+
+pub fn uncharge_heap(id: usize, bytes: usize) {
+    let mut table = TASK_TABLE.lock();
+    if let Some(tcb) = table.get(id) {
+        tcb.heap_used -= bytes;
+    }
+}
+
+/*
+Terminates the task: all the heap memory it still owns is given back to the
+heap, and the task is removed from the task table. The TCB itself is
+dropped by the scheduler, at the first context switch after the one that
+switches the task out, see ZOMBIE.
+*/
+pub fn terminate(tcb: &mut TaskTCB) -> usize {
+    tcb.state = TaskState::Terminated;
+    let reclaimed = HEAP.reclaim_task_allocations(tcb.id);
+    TASK_TABLE.lock().unregister(tcb.id);
+    tcb.heap_used = 0;
+    reclaimed
+}
+
+/*
+Terminates the running task from inside the kernel, where locks may be held
+and the task can't simply be switched out: the task is marked as terminated,
+and keeps running until the switch happens, as soon as the kernel's
+interrupts are unmasked. Its memory is reclaimed by the scheduler.
+*/
+pub fn kill_running() {
+    unsafe {
+        if let Some(tcb) = RUNNING.as_mut() {
+            tcb.state = TaskState::Terminated;
+        }
+    }
+    crate::wait::request_switch();
+}
+
+/*
+This struct is simply a wrapper to the scheduler, see the `scheduler`
+module, it uses a mutex to encapsulate the queue.
 It is necessary because the queue will be declared as
 static and because of rust rules it will be necessary
 to get a `&mut` reference out of a `&` reference.
 */
 pub struct LockedQueue {
-    mux: Mutex<Queue>,
+    mux: Mutex<KernelScheduler>,
 }
 
 impl LockedQueue {
     pub const fn new() -> Self {
         Self {
-            mux: Mutex::new(Queue::new()),
+            mux: Mutex::new(KernelScheduler::new()),
         }
     }
     pub fn enqueue(&self, block: Box<TaskTCB>) {
@@ -100,7 +499,7 @@ impl LockedQueue {
     }
     pub fn dequeue(&self) -> Option<Box<TaskTCB>> {
         let mut queue = self.mux.lock();
-        queue.dequeue()
+        queue.dequeue_next()
     }
     pub fn empty(&self) -> bool {
         let queue = self.mux.lock();
@@ -110,6 +509,38 @@ impl LockedQueue {
         let mut queue = self.mux.lock();
         queue.count_tasks()
     }
+    pub fn remove(&self, id: usize) -> Option<Box<TaskTCB>> {
+        let mut queue = self.mux.lock();
+        queue.remove(id)
+    }
+    //returns the priority of the first task that would be dequeued
+    pub fn highest_priority(&self) -> Option<usize> {
+        let queue = self.mux.lock();
+        queue.first().map(|tcb| tcb.priority)
+    }
+    //returns true if `tcb` must take the CPU from the `running` task
+    pub fn preempts(&self, tcb: &TaskTCB, running: &TaskTCB) -> bool {
+        let queue = self.mux.lock();
+        queue.preempts(tcb, running)
+    }
+    //returns true if the first task that would be dequeued must take the CPU
+    //from the `running` task
+    pub fn first_preempts(&self, running: &TaskTCB) -> bool {
+        let queue = self.mux.lock();
+        queue.first().map_or(false, |tcb| queue.preempts(tcb, running))
+    }
+    pub fn on_tick(&self, running: &mut TaskTCB) -> bool {
+        let mut queue = self.mux.lock();
+        queue.on_tick(running)
+    }
+    pub fn on_block(&self, tcb: &mut TaskTCB, now: u32) {
+        let mut queue = self.mux.lock();
+        queue.on_block(tcb, now)
+    }
+    pub fn on_wake(&self, tcb: &mut TaskTCB, now: u32) {
+        let mut queue = self.mux.lock();
+        queue.on_wake(tcb, now)
+    }
 }
 
 //struct of a queue of TaskTCB
@@ -182,26 +613,129 @@ impl Queue {
         }
         count 
     } 
+
+    //inserts a TaskTCB after all the tasks with the same or a higher priority,
+    //this is how wait queues are kept sorted
+    pub fn insert_by_priority(&mut self, mut block: Box<TaskTCB>) {
+        let priority = block.priority;
+        let mut link = &mut self.head;
+
+        while link.as_ref().map_or(false, |tcb| tcb.priority >= priority) {
+            link = &mut link.as_mut().unwrap().next;
+        }
+
+        block.next = link.take();
+        let is_tail = block.next.is_none();
+        let block_ptr: *mut TaskTCB = &mut *block;
+        *link = Some(block);
+        if is_tail {
+            self.tail = block_ptr;
+        }
+    }
+
+    //inserts a TaskTCB after all the tasks whose deadline is not later,
+    //this is how ready queues are kept sorted under EDF
+    pub fn insert_by_deadline(&mut self, mut block: Box<TaskTCB>) {
+        let deadline = block.deadline;
+        let mut link = &mut self.head;
+
+        while link.as_ref().map_or(false, |tcb| !deadline::earlier(deadline, tcb.deadline)) {
+            link = &mut link.as_mut().unwrap().next;
+        }
+
+        block.next = link.take();
+        let is_tail = block.next.is_none();
+        let block_ptr: *mut TaskTCB = &mut *block;
+        *link = Some(block);
+        if is_tail {
+            self.tail = block_ptr;
+        }
+    }
+
+    //removes the task with the given id from the queue, wherever it is
+    pub fn remove(&mut self, id: usize) -> Option<Box<TaskTCB>> {
+        let mut link = &mut self.head;
+        let mut previous: *mut TaskTCB = ptr::null_mut();
+
+        while link.as_ref().map_or(false, |tcb| tcb.id != id) {
+            let tcb = link.as_mut().unwrap();
+            previous = &mut **tcb;
+            link = &mut tcb.next;
+        }
+
+        let mut block = link.take()?;
+        *link = block.next.take();
+        if link.is_none() {
+            self.tail = previous; //the removed task was the last one
+        }
+        Some(block)
+    }
+
+    //returns the priority of the task at the head of the queue
+    pub fn highest_priority(&self) -> Option<usize> {
+        self.head.as_ref().map(|tcb| tcb.priority)
+    }
+
+    //returns the task at the head of the queue, without removing it
+    pub fn first(&self) -> Option<&TaskTCB> {
+        self.head.as_deref()
+    }
 }
  
-// scheduling function for now considering only one queue and never ending tasks
+// scheduling function: the ready task chosen by the scheduler is run
 #[no_mangle]
 pub unsafe fn schedule() -> *mut TaskTCB {
-    if !WAITING_QUEUE.empty() {
-        //take the first tasks in the queue
-        let mut tcb = WAITING_QUEUE.dequeue().unwrap();
-        let ptr = &mut *tcb as *mut TaskTCB;
-        RUNNING = Some(tcb);
-
-        ptr
-    } else {
-        match &mut RUNNING {
-            Some(tcb) => {
-                &mut **tcb
-            }   
-            None => {
-                ptr::null_mut()
+    // The task that terminated at the last switch doesn't execute anymore,
+    // its stack is not in use and its TCB can be dropped
+    ZOMBIE = None;
+
+    // The task whose context is being saved is charged for its CPU time,
+    // whether it is still RUNNING or it has just blocked
+    if let Some(previous) = CURRENT.as_mut() {
+        stats::switched_out(previous);
+    }
+
+    // The task that was running goes back to the end of its ready queue,
+    // unless it terminated, in which case its TCB becomes the zombie. A task
+    // that blocked is not RUNNING anymore, as it already sits in a wait
+    // queue. The idle task is kept apart from the ready queues.
+    if let Some(mut previous) = RUNNING.take() {
+        if previous.state != TaskState::Terminated {
+            previous.state = TaskState::Ready;
+            if previous.suspended {
+                previous.state = TaskState::Suspended;
+                SUSPENDED_TASKS.lock().enqueue(previous);
+            } else if idle::is_idle(previous.id) {
+                idle::park(previous);
+            } else {
+                WAITING_QUEUE.enqueue(previous);
+            }
+        } else {
+            // The task was killed by `kill_running`, and it still owns its
+            // memory
+            if TASK_TABLE.lock().get(previous.id).map_or(false, |tcb| ptr::eq(tcb, &*previous)) {
+                terminate(&mut previous);
             }
+            ZOMBIE = Some(previous);
+        }
+    }
+
+    // The idle task runs only if no other task is ready
+    match WAITING_QUEUE.dequeue().or_else(idle::take) {
+        Some(mut tcb) => {
+            tcb.state = TaskState::Running;
+            tcb.slice_left = tcb.quantum;
+            stats::switched_in(&mut tcb);
+            deadline::check(&mut tcb, time::ticks());
+            let ptr = &mut *tcb as *mut TaskTCB;
+            RUNNING = Some(tcb);
+            CURRENT = ptr;
+            ptr
+        }
+        // Only until the idle task is created, during boot
+        None => {
+            CURRENT = ptr::null_mut();
+            ptr::null_mut()
         }
     }
 }
@@ -0,0 +1,260 @@
+use crate::error::KernelError;
+use crate::mutex::{CriticalSection, Mutex};
+use crate::task::{self, Queue, TaskState, KERNEL_ID, MAX_TASKS, TASK_TABLE};
+use crate::wait::{self, NO_WAIT, WAIT_FOREVER};
+use core::marker::Sync;
+
+/*
+A TaskMutex provides mutual exclusion between tasks. Unlike the kernel's
+`Mutex`, which masks interrupts, a task that finds the TaskMutex locked is
+blocked until the owner releases it, or until its timeout expires.
+
+- Waiting tasks are kept sorted by priority: when the mutex is released it
+  is handed over directly to the waiting task with the highest priority.
+- Priority inheritance: while a task is waiting, the owner runs with the
+  priority of the waiting task, if higher than its own, so that tasks with
+  an intermediate priority cannot delay the owner indefinitely. If the owner
+  is itself waiting for another mutex, the priority is passed along the
+  chain. The owner gets back its own priority once it releases all the
+  mutexes it holds.
+- Only the owner can release the mutex. A recursive mutex can be locked
+  again by its owner, and it has to be unlocked as many times.
+
+The kernel itself (e.g. the code running before the scheduler starts) can
+use a TaskMutex as well, but it can never wait for it.
+*/
+pub struct TaskMutex {
+    inner: Mutex<TaskMutexState>,
+}
+
+struct TaskMutexState {
+    owner: Option<usize>, //id of the owner, None if the mutex is free
+    count: usize,         //number of times the owner locked the mutex
+    recursive: bool,
+    waiters: Queue,       //tasks waiting for the mutex, sorted by priority
+}
+
+impl TaskMutex {
+    pub const fn new(recursive: bool) -> Self {
+        Self {
+            inner: Mutex::new(TaskMutexState {
+                owner: None,
+                count: 0,
+                recursive,
+                waiters: Queue::new(),
+            }),
+        }
+    }
+
+    /* Locks the mutex, waiting for as long as needed */
+    pub fn lock(&self) -> Result<(), KernelError> {
+        self.lock_timeout(WAIT_FOREVER)
+    }
+
+    /* Locks the mutex only if that does not require waiting */
+    pub fn try_lock(&self) -> Result<(), KernelError> {
+        self.lock_timeout(NO_WAIT)
+    }
+
+    /*
+    Locks the mutex, waiting at most `timeout` ticks. Returns Timeout if
+    the mutex could not be locked in time, WouldBlock if the timeout is
+    NO_WAIT, and Deadlock if the owner tries to lock a non-recursive mutex
+    again.
+    */
+    pub fn lock_timeout(&self, timeout: u32) -> Result<(), KernelError> {
+        let id = task::running_task_id();
+        {
+            let _section = CriticalSection::enter();
+            let owner = {
+                let mut state = self.inner.lock();
+                match state.owner {
+                    None => {
+                        state.owner = Some(id);
+                        state.count = 1;
+                        mutex_acquired(id);
+                        return Ok(());
+                    }
+                    Some(owner) if owner == id => {
+                        if !state.recursive {
+                            return Err(KernelError::Deadlock);
+                        }
+                        state.count += 1;
+                        return Ok(());
+                    }
+                    Some(owner) => {
+                        let object = self as *const Self as *const ();
+                        wait::block_running_with_hook(&mut state.waiters, timeout, object, Self::waiter_left)?;
+                        owner
+                    }
+                }
+            };
+
+            // The task is now parked in the wait queue
+            let priority = match TASK_TABLE.lock().get(id) {
+                Some(tcb) => {
+                    tcb.blocked_on_mutex = self;
+                    tcb.priority
+                }
+                None => return wait::wait_for_wakeup(),
+            };
+            inherit_priority(owner, priority);
+        }
+        // When the task is woken up by `unlock`, it already owns the mutex
+        wait::wait_for_wakeup()
+    }
+
+    /* Unlocks the mutex, which must be owned by the caller */
+    pub fn unlock(&self) -> Result<(), KernelError> {
+        let id = task::running_task_id();
+        let _section = CriticalSection::enter();
+        let mut state = self.inner.lock();
+
+        if state.owner != Some(id) {
+            return Err(KernelError::NotOwner);
+        }
+        state.count -= 1;
+        if state.count > 0 {
+            return Ok(());
+        }
+        mutex_released(id);
+
+        // The mutex is handed over to the first waiting task, which inherits
+        // the priority of the tasks that keep waiting
+        match state.waiters.dequeue() {
+            None => state.owner = None,
+            Some(mut next) => {
+                state.owner = Some(next.id);
+                state.count = 1;
+                next.held_mutexes += 1;
+                if let Some(priority) = state.waiters.highest_priority() {
+                    next.priority = next.priority.max(priority);
+                }
+                wait::make_ready(next, Ok(()));
+            }
+        }
+        Ok(())
+    }
+
+    /* Returns the id of the owner, or None if the mutex is free */
+    pub fn owner(&self) -> Option<usize> {
+        self.inner.lock().owner
+    }
+
+    /*
+    Called when a waiting task gives up, because of its timeout. The owner
+    drops the priority it inherited from that task, unless it holds other
+    mutexes, which may still require it.
+    */
+    unsafe fn waiter_left(mutex: *const ()) {
+        let mutex = &*(mutex as *const Self);
+        let state = mutex.inner.lock();
+        let owner = match state.owner {
+            Some(owner) => owner,
+            None => return,
+        };
+        let (priority, base_priority, held_mutexes) = match TASK_TABLE.lock().get(owner) {
+            Some(tcb) => (tcb.priority, tcb.base_priority, tcb.held_mutexes),
+            None => return,
+        };
+
+        if held_mutexes == 1 {
+            let inherited = state.waiters.highest_priority().unwrap_or(0);
+            let new_priority = base_priority.max(inherited);
+            if new_priority < priority {
+                task::reprioritize(owner, new_priority);
+            }
+        }
+    }
+}
+
+unsafe impl Sync for TaskMutex {}
+
+/*
+Raises the priority of the owner of a mutex to `priority`. If the owner is
+waiting for another mutex, the priority is passed on to that mutex's owner,
+and so on. The length of the chain is bounded by the number of tasks.
+*/
+fn inherit_priority(mut owner: usize, priority: usize) {
+    for _ in 0..MAX_TASKS {
+        if owner == KERNEL_ID {
+            return;
+        }
+        let (current, next_mutex) = match TASK_TABLE.lock().get(owner) {
+            Some(tcb) => (tcb.priority, tcb.blocked_on_mutex),
+            None => return,
+        };
+        if current >= priority {
+            return;
+        }
+        task::reprioritize(owner, priority);
+
+        match unsafe{ next_mutex.as_ref() }.and_then(|mutex| mutex.owner()) {
+            Some(next_owner) => owner = next_owner,
+            None => return,
+        }
+    }
+}
+
+/*
+Recomputes the priority of a task whose base priority has changed: it runs
+with its base priority, or with the highest priority of the tasks waiting
+for the mutexes it holds. If the task is itself waiting for a mutex, the
+owner of that mutex is updated in turn, so that a raise is inherited along
+the chain, and a drop is given back.
+*/
+pub fn update_priority(mut id: usize) {
+    let _section = CriticalSection::enter();
+    for _ in 0..MAX_TASKS {
+        let (current, base_priority, next_mutex) = match TASK_TABLE.lock().get(id) {
+            Some(tcb) => (tcb.priority, tcb.base_priority, tcb.blocked_on_mutex),
+            None => return,
+        };
+        let priority = base_priority.max(inherited_priority(id));
+        if priority == current {
+            return;
+        }
+        task::reprioritize(id, priority);
+
+        match unsafe{ next_mutex.as_ref() }.and_then(|mutex| mutex.owner()) {
+            Some(owner) if owner != KERNEL_ID => id = owner,
+            _ => return,
+        }
+    }
+}
+
+// highest priority of the tasks waiting for the mutexes held by the task
+fn inherited_priority(owner: usize) -> usize {
+    let mut table = TASK_TABLE.lock();
+    let mut inherited = 0;
+    for id in 1..=MAX_TASKS {
+        if let Some(tcb) = table.get(id) {
+            let mutex = unsafe{ tcb.blocked_on_mutex.as_ref() };
+            if tcb.state == TaskState::Blocked && mutex.and_then(|mutex| mutex.owner()) == Some(owner) {
+                inherited = inherited.max(tcb.priority);
+            }
+        }
+    }
+    inherited
+}
+
+fn mutex_acquired(id: usize) {
+    if let Some(tcb) = TASK_TABLE.lock().get(id) {
+        tcb.held_mutexes += 1;
+    }
+}
+
+/* Once a task releases its last mutex, it gets back its own priority */
+fn mutex_released(id: usize) {
+    let base_priority = match TASK_TABLE.lock().get(id) {
+        Some(tcb) => {
+            tcb.held_mutexes -= 1;
+            if tcb.held_mutexes > 0 || tcb.priority == tcb.base_priority {
+                return;
+            }
+            tcb.base_priority
+        }
+        None => return,
+    };
+    task::reprioritize(id, base_priority);
+}
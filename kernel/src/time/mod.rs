@@ -0,0 +1,66 @@
+use crate::config::TICK_RATE_HZ;
+use crate::idle;
+use crate::mutex::CriticalSection;
+use crate::wait;
+
+/*
+The kernel's notion of time is the number of SysTick interrupts since boot.
+The counter wraps around, therefore ticks must always be compared through
+`deadline_reached` rather than with the `<` operator.
+*/
+static mut tick_count: u32 = 0;
+
+// SysTick reload value that makes it fire once per tick, set by `kernel_init`
+static mut reload_value: u32 = 0;
+
+/* Returns the number of ticks elapsed since boot */
+pub fn ticks() -> u32 {
+    let _section = CriticalSection::enter();
+    unsafe{ tick_count }
+}
+
+/* Converts a duration in milliseconds into ticks, rounding up */
+pub fn ms_to_ticks(ms: u32) -> u32 {
+    let ticks = (ms as u64 * TICK_RATE_HZ as u64 + 999) / 1000;
+    ticks.min(u32::MAX as u64) as u32
+}
+
+/* Returns true if `now` is at or past `deadline`, taking wrap-around into account */
+pub fn deadline_reached(deadline: u32, now: u32) -> bool {
+    (now.wrapping_sub(deadline) as i32) >= 0
+}
+
+pub fn set_tick_reload(reload: u32) {
+    unsafe{ reload_value = reload };
+}
+
+/* Returns the SysTick reload value of one tick */
+pub fn tick_reload() -> u32 {
+    unsafe{ reload_value }
+}
+
+/*
+Advances the kernel's time by one tick, and wakes up the tasks whose wait
+timed out. It must be called by the SysTick handler on every interrupt.
+*/
+#[no_mangle]
+pub extern "C" fn kernel_tick() {
+    step_ticks(1);
+}
+
+/*
+Advances the kernel's time by `count` ticks at once, e.g. the ticks during
+which SysTick was stopped by the idle task, and wakes up the tasks whose
+wait timed out in the meantime.
+*/
+pub fn step_ticks(count: u32) {
+    let now = {
+        let _section = CriticalSection::enter();
+        idle::account_ticks(count);
+        unsafe {
+            tick_count = tick_count.wrapping_add(count);
+            tick_count
+        }
+    };
+    wait::check_timeouts(now);
+}
@@ -0,0 +1,260 @@
+use crate::error::KernelError;
+use crate::mutex::{CriticalSection, Mutex};
+use crate::notification::{self, NotifyAction};
+use crate::syscalls;
+use crate::task::{KERNEL_ID, MAX_PRIORITY};
+use crate::time;
+use crate::wait::WAIT_FOREVER;
+use core::marker::Sync;
+use core::ptr;
+
+// Max number of timers that can be active at the same time
+pub const MAX_ACTIVE_TIMERS: usize = 32;
+
+// Priority of the timer daemon task: callbacks preempt the application tasks
+pub const TIMER_DAEMON_PRIORITY: usize = MAX_PRIORITY as usize - 1;
+
+/* The function run when a timer expires, with the argument given to the timer */
+pub type TimerCallback = fn(arg: *mut u8);
+
+/*
+Software timers run a callback once their period has elapsed, either once
+(one-shot timers) or every period (auto-reload timers), so that periodic
+jobs do not each need a task of their own.
+
+The active timers are kept in the TIMER_LIST, sorted by expiry time. The
+callbacks are not run by the tick interrupt, but by the timer daemon, a
+task with priority TIMER_DAEMON_PRIORITY: it sleeps until the first timer
+expires, by waiting for a notification with a timeout, and it is notified
+whenever the list changes. The callbacks therefore run one after the other
+in the daemon's context: they should be short and they should not block,
+as they would delay all the other timers.
+
+An auto-reload timer is reloaded from its expiry time rather than from the
+time its callback ran, so that it does not drift.
+*/
+pub struct Timer {
+    inner: Mutex<TimerState>,
+}
+
+struct TimerState {
+    callback: TimerCallback,
+    arg: *mut u8,
+    period: u32,
+    auto_reload: bool,
+}
+
+impl Timer {
+    pub const fn new(callback: TimerCallback, arg: *mut u8, period: u32, auto_reload: bool) -> Self {
+        Self {
+            inner: Mutex::new(TimerState { callback, arg, period, auto_reload }),
+        }
+    }
+
+    /*
+    Starts the timer, which expires `period` ticks from now. Nothing is
+    done if it is already active. The timer must not be moved while it is
+    active. Returns Full if too many timers are active.
+    */
+    pub fn start(&self) -> Result<(), KernelError> {
+        let _section = CriticalSection::enter();
+        if TIMER_LIST.lock().contains(self) {
+            return Ok(());
+        }
+        self.restart()
+    }
+
+    /* Stops the timer: its callback is not run, unless it is already running */
+    pub fn stop(&self) {
+        let _section = CriticalSection::enter();
+        TIMER_LIST.lock().remove(self);
+        wake_daemon();
+    }
+
+    /*
+    Restarts the timer, which expires `period` ticks from now, whether it
+    was active or not.
+    */
+    pub fn reset(&self) -> Result<(), KernelError> {
+        let _section = CriticalSection::enter();
+        self.restart()
+    }
+
+    /* Changes the period of the timer, and restarts it with the new period */
+    pub fn change_period(&self, period: u32) -> Result<(), KernelError> {
+        if period == 0 || period == WAIT_FOREVER {
+            return Err(KernelError::InvalidArgument);
+        }
+        let _section = CriticalSection::enter();
+        self.inner.lock().period = period;
+        self.restart()
+    }
+
+    pub fn is_active(&self) -> bool {
+        TIMER_LIST.lock().contains(self)
+    }
+
+    pub fn period(&self) -> u32 {
+        self.inner.lock().period
+    }
+
+    // must be called with the kernel interrupts masked
+    fn restart(&self) -> Result<(), KernelError> {
+        let period = self.inner.lock().period;
+        if period == 0 || period == WAIT_FOREVER {
+            return Err(KernelError::InvalidArgument);
+        }
+        let mut list = TIMER_LIST.lock();
+        list.remove(self);
+        list.insert(self, time::ticks().wrapping_add(period))?;
+        drop(list);
+        wake_daemon();
+        Ok(())
+    }
+}
+
+/* A timer that is dropped is stopped first */
+impl Drop for Timer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+unsafe impl Sync for Timer {}
+
+/*
+The active timers, sorted by expiry time. As for the TimeoutList of the
+`wait` module, expiry times are sorted by their distance from one another,
+so that the wrap-around of the tick counter is taken into account.
+*/
+pub struct TimerList {
+    entries: [(u32, *const Timer); MAX_ACTIVE_TIMERS], //(expiry, timer)
+    len: usize,
+}
+
+impl TimerList {
+    pub const fn new() -> Self {
+        Self { entries: [(0, ptr::null()); MAX_ACTIVE_TIMERS], len: 0 }
+    }
+
+    /* Adds a timer, after the timers that expire at the same time */
+    pub fn insert(&mut self, timer: *const Timer, expiry: u32) -> Result<(), KernelError> {
+        if self.len == MAX_ACTIVE_TIMERS {
+            return Err(KernelError::Full);
+        }
+        let position = self.entries[..self.len].iter()
+            .position(|&(other, _)| (other.wrapping_sub(expiry) as i32) > 0)
+            .unwrap_or(self.len);
+        self.entries.copy_within(position..self.len, position + 1);
+        self.entries[position] = (expiry, timer);
+        self.len += 1;
+        Ok(())
+    }
+
+    /* Removes a timer, if it is in the list */
+    pub fn remove(&mut self, timer: *const Timer) {
+        if let Some(position) = self.entries[..self.len].iter().position(|&(_, other)| other == timer) {
+            self.entries.copy_within(position + 1..self.len, position);
+            self.len -= 1;
+        }
+    }
+
+    pub fn contains(&self, timer: *const Timer) -> bool {
+        self.entries[..self.len].iter().any(|&(_, other)| other == timer)
+    }
+
+    /* Removes and returns the first timer that has expired at `now`, with its expiry time */
+    pub fn pop_expired(&mut self, now: u32) -> Option<(u32, *const Timer)> {
+        match self.entries[..self.len].first() {
+            Some(&(expiry, timer)) if time::deadline_reached(expiry, now) => {
+                self.remove(timer);
+                Some((expiry, timer))
+            }
+            _ => None,
+        }
+    }
+
+    /* Returns the expiry time of the first timer to expire, if any is active */
+    pub fn next_expiry(&self) -> Option<u32> {
+        self.entries[..self.len].first().map(|&(expiry, _)| expiry)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+}
+
+unsafe impl Sync for TimerList {}
+
+static mut timer_list: Mutex<TimerList> = Mutex::new(TimerList::new());
+pub static TIMER_LIST: &Mutex<TimerList> = unsafe{&timer_list};
+
+// Id of the timer daemon task, KERNEL_ID until it is created
+static mut timer_daemon: Mutex<usize> = Mutex::new(KERNEL_ID);
+static TIMER_DAEMON: &Mutex<usize> = unsafe{&timer_daemon};
+
+/*
+Creates the timer daemon task, unless it already exists. It is created by
+the kernel along with the first timer requested through a system call;
+kernel code that uses timers before then should call it itself.
+*/
+pub fn start_daemon() -> Result<(), KernelError> {
+    let _section = CriticalSection::enter();
+    let mut daemon = TIMER_DAEMON.lock();
+    if *daemon == KERNEL_ID {
+        *daemon = syscalls::spawn_task(daemon_task, ptr::null_mut(), TIMER_DAEMON_PRIORITY, 0)?;
+    }
+    Ok(())
+}
+
+// the daemon is told that the first timer to expire may have changed
+fn wake_daemon() {
+    let daemon = *TIMER_DAEMON.lock();
+    if daemon != KERNEL_ID {
+        let _ = notification::notify(daemon, 0, NotifyAction::SetBits);
+    }
+}
+
+/*
+Runs the callbacks of all the timers that have expired at `now`, and
+reloads the auto-reload ones. Called by the timer daemon.
+*/
+pub fn run_expired(now: u32) {
+    loop {
+        let (callback, arg) = {
+            let _section = CriticalSection::enter();
+            let mut list = TIMER_LIST.lock();
+            let (expiry, timer) = match list.pop_expired(now) {
+                Some(entry) => entry,
+                None => return,
+            };
+            let state = unsafe{ (*timer).inner.lock() };
+            if state.auto_reload {
+                let _ = list.insert(timer, expiry.wrapping_add(state.period));
+            }
+            (state.callback, state.arg)
+        };
+        // The callback runs with the interrupts unmasked, and it may itself
+        // start or stop timers
+        callback(arg);
+    }
+}
+
+fn daemon_task(_args: *mut u8) {
+    loop {
+        let timeout = {
+            let _section = CriticalSection::enter();
+            match TIMER_LIST.lock().next_expiry() {
+                None => WAIT_FOREVER,
+                Some(expiry) => {
+                    let remaining = expiry.wrapping_sub(time::ticks()) as i32;
+                    remaining.max(0) as u32
+                }
+            }
+        };
+        // The daemon wakes up when the first timer expires, or when it is
+        // notified that the list has changed
+        let _ = notification::notify_wait(timeout);
+        run_expired(time::ticks());
+    }
+}
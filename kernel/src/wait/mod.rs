@@ -0,0 +1,325 @@
+use crate::error::KernelError;
+use crate::idle;
+use crate::mutex::{CriticalSection, Mutex};
+use crate::task::{self, CallerContext, Queue, TaskState, TaskTCB, CURRENT, MAX_TASKS, RUNNING, TASK_TABLE};
+use crate::time;
+use crate::WAITING_QUEUE;
+use alloc::boxed::Box;
+use core::ptr;
+use cortex_m::peripheral::SCB;
+
+/*
+Blocking and waking tasks.
+
+A task that has to wait for a kernel object is moved from RUNNING to the
+object's wait queue, which is sorted by priority, and a context switch is
+requested. The switch is carried out by the PendSV handler, which has the
+lowest priority: it runs as soon as the kernel leaves its critical sections
+and returns from the system call, if any.
+
+Whoever satisfies the wait moves the task back to the ready queue, and
+stores the outcome of the wait in the task's TCB. When the task runs again
+it collects that outcome:
+ - from the task's own code, the task called the kernel directly, so the
+   outcome is read right after the context switch, see `wait_for_wakeup`
+ - within a system call the handler must return before the switch can take
+   place, so the call returns `KernelError::Pending`, and the user side of
+   the system call asks for the outcome once the task is resumed.
+
+Every blocking call takes a timeout in ticks: NO_WAIT makes the call fail
+right away with WouldBlock, WAIT_FOREVER makes it wait for as long as
+needed, and any other value (up to MAX_TIMEOUT) makes it fail with Timeout
+if the wait is not satisfied in time. The tasks that wait with a timeout
+are also kept in the TIMEOUT_LIST, sorted by deadline, so that on every
+tick the kernel only looks at the tasks whose deadline has actually come.
+
+Interrupt handlers never block, and they cannot invoke system calls either:
+they call into the kernel directly, through the `_from_isr` variants of the
+services that may wake a task up. Their priority must therefore not be
+higher than KERNEL_INTERRUPT_PRIORITY. If the woken task has a higher
+priority than the interrupted one, the context switch takes place as soon
+as the handler returns.
+*/
+
+// Timeout value that makes a blocking call fail rather than wait
+pub const NO_WAIT: u32 = 0;
+
+// Timeout value that makes a blocking call wait for as long as needed
+pub const WAIT_FOREVER: u32 = u32::MAX;
+
+/*
+Longest timeout that can be honoured: deadlines are compared with wrapping
+arithmetic, so they must lie less than 2^31 ticks ahead. Longer timeouts,
+other than WAIT_FOREVER, are clamped to it.
+*/
+pub const MAX_TIMEOUT: u32 = i32::MAX as u32;
+
+/*
+A function called with the kernel interrupts masked when a task's wait on
+`object` times out, after the task has been removed from the wait queue.
+Objects whose state depends on the tasks waiting for them use it to update
+that state, e.g. a TaskMutex restores the priority of its owner.
+*/
+pub type TimeoutHook = unsafe fn(object: *const ());
+
+/*
+The tasks waiting with a timeout, sorted by deadline. Deadlines are sorted
+by their distance from one another rather than by value, which is correct
+across the wrap-around of the tick counter as no timeout is longer than
+half of its range.
+*/
+pub struct TimeoutList {
+    entries: [(u32, usize); MAX_TASKS], //(deadline, id of the task)
+    len: usize,
+}
+
+impl TimeoutList {
+    pub const fn new() -> Self {
+        Self { entries: [(0, 0); MAX_TASKS], len: 0 }
+    }
+
+    /* Adds a task, after the tasks with the same deadline */
+    pub fn insert(&mut self, id: usize, deadline: u32) {
+        if self.len == MAX_TASKS {
+            return;
+        }
+        let position = self.entries[..self.len].iter()
+            .position(|&(other, _)| (other.wrapping_sub(deadline) as i32) > 0)
+            .unwrap_or(self.len);
+        self.entries.copy_within(position..self.len, position + 1);
+        self.entries[position] = (deadline, id);
+        self.len += 1;
+    }
+
+    /* Removes a task, if it is in the list */
+    pub fn remove(&mut self, id: usize) {
+        if let Some(position) = self.entries[..self.len].iter().position(|&(_, other)| other == id) {
+            self.entries.copy_within(position + 1..self.len, position);
+            self.len -= 1;
+        }
+    }
+
+    /* Removes and returns the first task whose deadline is reached at `now` */
+    pub fn pop_expired(&mut self, now: u32) -> Option<usize> {
+        match self.entries[..self.len].first() {
+            Some(&(deadline, id)) if time::deadline_reached(deadline, now) => {
+                self.remove(id);
+                Some(id)
+            }
+            _ => None,
+        }
+    }
+
+    /* Returns the earliest deadline, if any task is waiting with a timeout */
+    pub fn next_deadline(&self) -> Option<u32> {
+        self.entries[..self.len].first().map(|&(deadline, _)| deadline)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+}
+
+static mut timeout_list: Mutex<TimeoutList> = Mutex::new(TimeoutList::new());
+pub static TIMEOUT_LIST: &Mutex<TimeoutList> = unsafe{&timeout_list};
+
+/*
+Requests a context switch, which happens as soon as the kernel interrupts
+are unmasked. Nothing is done until the scheduler has started.
+*/
+pub fn request_switch() {
+    unsafe {
+        if !CURRENT.is_null() {
+            SCB::set_pendsv();
+        }
+    }
+}
+
+/*
+The RUNNING task is blocked in the given wait queue for at most `timeout`
+ticks. A timeout of NO_WAIT means that the caller is not willing to wait,
+and WouldBlock is returned. Only tasks can block, either from their own code
+or through a system call: interrupt handlers get WouldBlock as well.
+
+It must be called with the kernel interrupts masked, by the same critical
+section that checked that the caller has to wait.
+*/
+pub fn block_running(queue: &mut Queue, timeout: u32) -> Result<(), KernelError> {
+    if timeout == NO_WAIT {
+        return Err(KernelError::WouldBlock);
+    }
+    if task::caller_context() == CallerContext::Interrupt {
+        return Err(KernelError::WouldBlock);
+    }
+
+    // A task killed by `task::kill_running` must not leave the RUNNING slot
+    // before the scheduler reclaims it
+    match unsafe{ RUNNING.as_ref() } {
+        Some(tcb) if tcb.state != TaskState::Terminated => {}
+        _ => return Err(KernelError::WouldBlock),
+    }
+    let mut tcb = unsafe{ RUNNING.take() }.ok_or(KernelError::WouldBlock)?;
+    WAITING_QUEUE.on_block(&mut tcb, time::ticks());
+    tcb.state = TaskState::Blocked;
+    tcb.waiting_on = queue;
+    tcb.wait_result = Err(KernelError::Pending);
+    tcb.wake_tick = match timeout {
+        WAIT_FOREVER => None,
+        timeout => Some(time::ticks().wrapping_add(timeout.min(MAX_TIMEOUT))),
+    };
+    if let Some(deadline) = tcb.wake_tick {
+        TIMEOUT_LIST.lock().insert(tcb.id, deadline);
+    }
+    queue.insert_by_priority(tcb);
+
+    request_switch();
+    Ok(())
+}
+
+/*
+Same as `block_running`, but the task also leaves the address of a buffer:
+whoever wakes it up copies the data the task is waiting for into it, or
+takes the data the task wants to hand over from it.
+*/
+pub fn block_running_with_buffer(queue: &mut Queue, timeout: u32, buffer: *mut u8) -> Result<(), KernelError> {
+    unsafe {
+        if let Some(tcb) = RUNNING.as_mut() {
+            tcb.wait_buffer = buffer;
+        }
+    }
+    block_running(queue, timeout)
+}
+
+/*
+Same as `block_running`, but `hook` is called with `object` if the wait
+times out.
+*/
+pub fn block_running_with_hook(queue: &mut Queue, timeout: u32, object: *const (), hook: TimeoutHook) -> Result<(), KernelError> {
+    unsafe {
+        if let Some(tcb) = RUNNING.as_mut() {
+            tcb.wait_object = object;
+            tcb.timeout_hook = Some(hook);
+        }
+    }
+    block_running(queue, timeout)
+}
+
+/*
+Returns the outcome of the wait the RUNNING task has just blocked for. It
+must be called after leaving the critical section that blocked the task.
+Pending is returned if the task has not been resumed yet: the wait was
+started by a system call, or no context switch could take place.
+*/
+pub fn wait_for_wakeup() -> Result<(), KernelError> {
+    if task::caller_context() != CallerContext::Task {
+        return Err(KernelError::Pending);
+    }
+    // The context switch took place as soon as the interrupts were unmasked,
+    // so by now the task has been resumed
+    cortex_m::asm::isb();
+    running_wait_result()
+}
+
+/*
+Returns the outcome of the last wait of the RUNNING task, or Pending if no
+task is running, e.g. because the caller is still blocked.
+*/
+pub fn running_wait_result() -> Result<(), KernelError> {
+    unsafe {
+        match &RUNNING {
+            Some(tcb) => tcb.wait_result,
+            None => Err(KernelError::Pending),
+        }
+    }
+}
+
+/*
+The task with the highest priority in the wait queue is woken up, and the
+outcome of its wait is set to `result`. Returns the id of the task, or None
+if the queue was empty.
+*/
+pub fn wake_first(queue: &mut Queue, result: Result<(), KernelError>) -> Option<usize> {
+    let tcb = queue.dequeue()?;
+    let id = tcb.id;
+    make_ready(tcb, result);
+    Some(id)
+}
+
+/*
+The task, which must have been removed from its wait queue, is put into the
+ready queue, unless it was suspended while waiting. A task with a deadline
+starts a new job.
+*/
+pub fn make_ready(mut tcb: Box<TaskTCB>, result: Result<(), KernelError>) {
+    tcb.state = TaskState::Ready;
+    tcb.wait_result = result;
+    tcb.waiting_on = ptr::null_mut();
+    tcb.blocked_on_mutex = ptr::null();
+    tcb.timeout_hook = None;
+    tcb.wait_object = ptr::null();
+    tcb.wait_buffer = ptr::null_mut();
+    if tcb.wake_tick.take().is_some() {
+        TIMEOUT_LIST.lock().remove(tcb.id);
+    }
+    WAITING_QUEUE.on_wake(&mut tcb, time::ticks());
+
+    if tcb.suspended {
+        tcb.state = TaskState::Suspended;
+        task::SUSPENDED_TASKS.lock().enqueue(tcb);
+    } else {
+        enqueue_ready(tcb);
+    }
+}
+
+/*
+Puts a ready task into the ready queue. If the scheduler says it must take
+the CPU from the RUNNING task, or if the idle task is running, a context
+switch is requested.
+*/
+pub fn enqueue_ready(tcb: Box<TaskTCB>) {
+    let preempt = match unsafe{ RUNNING.as_deref() } {
+        Some(running) => WAITING_QUEUE.preempts(&tcb, running) || idle::is_running(),
+        None => true,
+    };
+    WAITING_QUEUE.enqueue(tcb);
+    if preempt {
+        request_switch();
+    }
+}
+
+/*
+Wakes up, with a Timeout error, all the blocked tasks whose timeout has
+expired, taking them off whatever wait queue they are in. Called by the
+kernel on every tick.
+*/
+pub fn check_timeouts(now: u32) {
+    let _section = CriticalSection::enter();
+
+    loop {
+        let id = match TIMEOUT_LIST.lock().pop_expired(now) {
+            Some(id) => id,
+            None => return,
+        };
+        let tcb = match TASK_TABLE.lock().get(id) {
+            Some(tcb) => tcb as *mut TaskTCB,
+            None => continue,
+        };
+
+        unsafe {
+            if (*tcb).state != TaskState::Blocked || (*tcb).waiting_on.is_null() {
+                continue;
+            }
+            // The task is no longer in the list
+            (*tcb).wake_tick = None;
+
+            let hook = (*tcb).timeout_hook;
+            let object = (*tcb).wait_object;
+            if let Some(block) = (*(*tcb).waiting_on).remove(id) {
+                make_ready(block, Err(KernelError::Timeout));
+            }
+            if let Some(hook) = hook {
+                hook(object);
+            }
+        }
+    }
+}
@@ -1,4 +1,11 @@
-use kernel::{allocator::{Heap}};
+use kernel::{allocator::{Heap, HeapError, LockedHeap, HeapRegionTag, HEAP_ALLOC_OVERHEAD}};
+use kernel::allocator::{set_oom_hook, OomAction, OOM_MAX_RETRIES};
+use kernel::task::{self, schedule, TaskState, TaskTCB, CURRENT, RUNNING, TASK_TABLE};
+use kernel::HEAP;
+use crate::test_support::{ready_task, registered_task};
+use alloc::alloc::{GlobalAlloc, Layout};
+use alloc::boxed::Box;
+use core::ptr;
 use cortex_m_semihosting::hprintln;
 
 /* Utility function to display the free segments present in the heap */
@@ -84,4 +91,284 @@ fn test_allocate_segment() {
     heap.free_segment(ptr1 as usize, 128);
     assert_eq!(heap.count_segments(), 1);
     assert_eq!(heap.available_space(), 1024);
-}
\ No newline at end of file
+}
+
+#[test_case]
+fn heap_regions_test() {
+    let default_mem: [u64; 128] = [0; 128];
+    let fast_mem: [u64; 64] = [0; 64];
+    let heap = LockedHeap::new();
+    heap.init(&default_mem[0] as *const u64 as usize, 1024);
+    heap.add_region(HeapRegionTag::Fast, &fast_mem[0] as *const u64 as usize, 512);
+    assert_eq!(heap.available_space(), 1536);
+    assert_eq!(heap.available_space_in(HeapRegionTag::Fast), 512);
+
+    // The allocation is served by the requested region only
+    let layout = Layout::from_size_align(128, 4).unwrap();
+    let ptr = heap.alloc_in(HeapRegionTag::Fast, layout) as usize;
+    assert!(ptr >= &fast_mem[0] as *const u64 as usize);
+    assert_eq!(heap.available_space_in(HeapRegionTag::Fast), 512 - 128 - HEAP_ALLOC_OVERHEAD);
+    assert_eq!(heap.available_space_in(HeapRegionTag::Default), 1024);
+    assert!(heap.alloc_in(HeapRegionTag::Dma, layout).is_null());
+
+    // Freed memory goes back to the region it was taken from
+    unsafe{ heap.dealloc(ptr as *mut u8, layout) };
+    assert_eq!(heap.available_space_in(HeapRegionTag::Fast), 512);
+
+    // Memory outside of every region is not taken in
+    let outside: [u8; 256] = [0; 256];
+    unsafe{ heap.dealloc(&outside[64] as *const u8 as *mut u8, layout) };
+    assert_eq!(heap.available_space(), 1536);
+
+    // The tags that come from C code are checked
+    assert_eq!(HeapRegionTag::from_raw(1), Some(HeapRegionTag::Fast));
+    assert_eq!(HeapRegionTag::from_raw(3), None);
+}
+
+#[test_case]
+fn heap_fallback_test() {
+    let default_mem: [u64; 32] = [0; 32];
+    let fast_mem: [u64; 128] = [0; 128];
+    let heap = LockedHeap::new();
+    heap.init(&default_mem[0] as *const u64 as usize, 256);
+    heap.add_region(HeapRegionTag::Fast, &fast_mem[0] as *const u64 as usize, 1024);
+
+    // The default region is too small, the allocation falls back to the
+    // fast one
+    let layout = Layout::from_size_align(512, 4).unwrap();
+    let ptr = unsafe{ heap.alloc(layout) };
+    assert!(!ptr.is_null());
+    assert_eq!(heap.available_space_in(HeapRegionTag::Fast), 1024 - 512 - HEAP_ALLOC_OVERHEAD);
+    unsafe{ heap.dealloc(ptr, layout) };
+
+    // Once the fast region is removed from the fallback order, the
+    // allocation fails
+    heap.set_fallback_order(&[HeapRegionTag::Default]);
+    assert!(unsafe{ heap.alloc(layout) }.is_null());
+}
+
+#[test_case]
+fn heap_integrity_test() {
+    let heap_mem: [usize; 256] = [0; 256];
+    let mut heap = Heap::new();
+    heap.init(&heap_mem[0] as *const usize as usize, 1024);
+
+    let ptr1 = heap.allocate_segment(128).unwrap();
+    let _ptr2 = heap.allocate_segment(128).unwrap();
+    heap.free_segment(ptr1 as usize, 128);
+    assert_eq!(heap.check_integrity(), Ok(()));
+
+    // The header of the first free segment is overwritten, as a buffer
+    // overrun would do: its size, the first word of the `repr(C)` header,
+    // now spans over the following segment
+    unsafe{ *(ptr1 as *mut usize) = 512 };
+    assert_eq!(heap.check_integrity(), Err(HeapError::Overlapping(ptr1 as usize + 256)));
+
+    // A size that runs past the end of the heap
+    unsafe{ *(ptr1 as *mut usize) = 4096 };
+    assert_eq!(heap.check_integrity(), Err(HeapError::OutOfBounds(ptr1 as usize)));
+}
+
+#[cfg(feature = "heap-debug")]
+#[test_case]
+fn heap_guards_test() {
+    use kernel::allocator::{HEAP_CANARY, HEAP_GUARD_SIZE, HEAP_POISON};
+
+    let heap_mem: [u64; 128] = [0; 128];
+    let heap = LockedHeap::new();
+    heap.init(&heap_mem[0] as *const u64 as usize, 1024);
+
+    // The allocation is surrounded by the guard words
+    let layout = Layout::from_size_align(64, 4).unwrap();
+    let ptr = unsafe{ heap.alloc(layout) };
+    unsafe {
+        assert_eq!((ptr.sub(HEAP_GUARD_SIZE) as *const usize).read_unaligned(), HEAP_CANARY);
+        assert_eq!((ptr.add(64) as *const usize).read_unaligned(), HEAP_CANARY);
+    }
+
+    // Once freed, the memory past the segment's header is poisoned
+    unsafe{ heap.dealloc(ptr, layout) };
+    assert_eq!(unsafe{ *ptr.add(32) }, HEAP_POISON);
+    assert_eq!(heap.check_integrity(), Ok(()));
+}
+
+#[test_case]
+fn heap_alignment_test() {
+    let heap_mem: [u64; 128] = [0; 128];
+    let heap = LockedHeap::new();
+    heap.init(&heap_mem[0] as *const u64 as usize + 4, 1020);
+    assert_eq!(heap.available_space(), 1016);
+
+    // Allocations of any size keep the next ones aligned
+    let small = Layout::from_size_align(3, 1).unwrap();
+    let ptr1 = unsafe{ heap.alloc(small) };
+    let layout = Layout::from_size_align(8, 8).unwrap();
+    let ptr2 = unsafe{ heap.alloc(layout) };
+    assert!(!ptr1.is_null() && !ptr2.is_null());
+    assert_eq!(ptr2 as usize % 8, 0);
+
+    // Larger alignments are not supported
+    let too_aligned = Layout::from_size_align(16, 16).unwrap();
+    assert!(unsafe{ heap.alloc(too_aligned) }.is_null());
+
+    unsafe {
+        heap.dealloc(ptr1, small);
+        heap.dealloc(ptr2, layout);
+    }
+    assert_eq!(heap.available_space(), 1016);
+}
+
+#[test_case]
+fn heap_stats_test() {
+    let heap_mem: [u64; 128] = [0; 128];
+    let heap = LockedHeap::new();
+    heap.init(&heap_mem[0] as *const u64 as usize, 1024);
+
+    let layout = Layout::from_size_align(128, 4).unwrap();
+    let ptr1 = unsafe{ heap.alloc(layout) };
+    let ptr2 = unsafe{ heap.alloc(layout) };
+    unsafe{ heap.dealloc(ptr1, layout) };
+
+    // Allocations that can't be satisfied are counted as well
+    let too_large = Layout::from_size_align(2048, 4).unwrap();
+    assert!(unsafe{ heap.alloc(too_large) }.is_null());
+
+    let stats = heap.stats();
+    assert_eq!(stats.total_size, 1024);
+    assert_eq!(stats.free, heap.available_space());
+    assert_eq!(stats.used, stats.total_size - stats.free);
+    assert_eq!(stats.min_free, 1024 - 2 * (128 + HEAP_ALLOC_OVERHEAD));
+    assert_eq!(stats.allocations, 2);
+    assert_eq!(stats.frees, 1);
+    assert_eq!(stats.failed_allocations, 1);
+
+    // The first segment was freed, so the free memory is split in two
+    assert_eq!(stats.largest_free_block, stats.free - (128 + HEAP_ALLOC_OVERHEAD));
+    assert!(stats.fragmentation > 0);
+
+    unsafe{ heap.dealloc(ptr2, layout) };
+    let stats = heap.stats();
+    assert_eq!(stats.largest_free_block, 1024);
+    assert_eq!(stats.fragmentation, 0);
+}
+
+#[test_case]
+fn heap_attribution_test() {
+    // The tests run in thread mode, as the code of a task does: the
+    // allocations are charged to the RUNNING task
+    let tcb = registered_task(0);
+    let id = tcb.id;
+    unsafe{ RUNNING = Some(tcb) };
+    assert_eq!(task::current_task_id(), id);
+
+    let layout = Layout::from_size_align(64, 4).unwrap();
+    let ptr = unsafe{ HEAP.alloc(layout) };
+    assert!(!ptr.is_null());
+    assert_eq!(unsafe{ RUNNING.as_ref().unwrap().heap_used }, 64);
+    assert_eq!(HEAP.task_allocations(id), (1, 64));
+
+    unsafe{ HEAP.dealloc(ptr, layout) };
+    assert_eq!(unsafe{ RUNNING.as_ref().unwrap().heap_used }, 0);
+
+    unsafe{ RUNNING = None };
+    TASK_TABLE.lock().unregister(id);
+    assert_eq!(task::current_task_id(), task::KERNEL_ID);
+}
+
+#[test_case]
+fn heap_accounting_test() {
+    // A task with a 256 bytes quota is made the running one
+    let mut tcb = Box::new(TaskTCB::new(None, 0));
+    tcb.heap_quota = 256;
+    let id = TASK_TABLE.lock().register(&mut tcb).unwrap();
+    unsafe{ RUNNING = Some(tcb) };
+
+    let layout = Layout::from_size_align(128, 4).unwrap();
+    let ptr1 = unsafe{ HEAP.alloc(layout) };
+    let ptr2 = unsafe{ HEAP.alloc(layout) };
+    assert!(!ptr1.is_null() && !ptr2.is_null());
+    assert_eq!(HEAP.task_allocations(id), (2, 256));
+
+    // The quota has been reached
+    assert!(unsafe{ HEAP.alloc(layout) }.is_null());
+
+    // Freeing memory makes room for new allocations
+    unsafe{ HEAP.dealloc(ptr1, layout) };
+    assert_eq!(unsafe{ RUNNING.as_ref().unwrap().heap_used }, 128);
+
+    // When the task terminates, its memory is reclaimed
+    let available_space = HEAP.available_space();
+    let mut tcb = unsafe{ RUNNING.take().unwrap() };
+    assert_eq!(task::terminate(&mut tcb), 128);
+    assert_eq!(HEAP.task_allocations(id), (0, 0));
+    assert_eq!(HEAP.available_space(), available_space + 128 + HEAP_ALLOC_OVERHEAD);
+    assert!(TASK_TABLE.lock().get(id).is_none());
+}
+
+static mut OOM_CALLS: usize = 0;
+
+extern "C" fn failing_hook(size: usize, _align: usize, task_id: usize) -> OomAction {
+    assert_eq!(size, 2048);
+    assert_eq!(task_id, task::KERNEL_ID);
+    unsafe{ OOM_CALLS += 1 };
+    OomAction::Fail
+}
+
+extern "C" fn retrying_hook(_size: usize, _align: usize, _task_id: usize) -> OomAction {
+    unsafe{ OOM_CALLS += 1 };
+    OomAction::Retry
+}
+
+#[test_case]
+fn oom_hook_test() {
+    let heap_mem: [u64; 128] = [0; 128];
+    let heap = LockedHeap::new();
+    heap.init(&heap_mem[0] as *const u64 as usize, 1024);
+    let layout = Layout::from_size_align(2048, 4).unwrap();
+
+    // The hook is called once, and the allocation fails
+    unsafe{ OOM_CALLS = 0 };
+    set_oom_hook(Some(failing_hook));
+    assert!(unsafe{ heap.alloc(layout) }.is_null());
+    assert_eq!(unsafe{ OOM_CALLS }, 1);
+
+    // The allocation is retried a bounded number of times
+    unsafe{ OOM_CALLS = 0 };
+    set_oom_hook(Some(retrying_hook));
+    assert!(unsafe{ heap.alloc(layout) }.is_null());
+    assert_eq!(unsafe{ OOM_CALLS }, OOM_MAX_RETRIES + 1);
+
+    set_oom_hook(None);
+}
+
+extern "C" fn killing_hook(_size: usize, _align: usize, _task_id: usize) -> OomAction {
+    OomAction::KillTask
+}
+
+#[test_case]
+fn oom_kill_test() {
+    // The running task owns some memory, and reached its quota
+    let mut tcb = registered_task(0);
+    tcb.heap_quota = 64;
+    let id = tcb.id;
+    unsafe{ RUNNING = Some(tcb) };
+    let layout = Layout::from_size_align(64, 4).unwrap();
+    assert!(!unsafe{ HEAP.alloc(layout) }.is_null());
+
+    // The allocation fails, and the task is only marked as terminated
+    set_oom_hook(Some(killing_hook));
+    assert!(unsafe{ HEAP.alloc(layout) }.is_null());
+    set_oom_hook(None);
+    assert_eq!(unsafe{ RUNNING.as_ref().unwrap().state }, TaskState::Terminated);
+    assert_eq!(HEAP.task_allocations(id), (1, 64));
+
+    // Its memory is reclaimed once it is switched out
+    let other_id = ready_task(0);
+    unsafe{ schedule() };
+    unsafe{ CURRENT = ptr::null_mut() };
+    assert_eq!(HEAP.task_allocations(id), (0, 0));
+    assert!(TASK_TABLE.lock().get(id).is_none());
+
+    unsafe{ RUNNING = None };
+    TASK_TABLE.lock().unregister(other_id);
+}
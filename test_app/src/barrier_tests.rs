@@ -0,0 +1,57 @@
+use kernel::WAITING_QUEUE;
+use kernel::barrier::Barrier;
+use kernel::error::KernelError;
+use kernel::task::{RUNNING, TASK_TABLE};
+use kernel::time::kernel_tick;
+use crate::test_support::registered_task;
+
+#[test_case]
+fn barrier_release_test() {
+    let barrier = Barrier::new(3);
+
+    // The kernel cannot wait for the group
+    assert_eq!(barrier.wait_timeout(10), Err(KernelError::WouldBlock));
+    assert_eq!(barrier.waiting_tasks(), 0);
+
+    let mut ids = [0; 2];
+    for (i, priority) in [1, 2].iter().enumerate() {
+        let tcb = registered_task(*priority);
+        ids[i] = tcb.id;
+        unsafe{ RUNNING = Some(tcb) };
+        assert_eq!(barrier.wait_timeout(10), Err(KernelError::Pending));
+        assert!(unsafe{ RUNNING.is_none() });
+    }
+    assert_eq!(barrier.waiting_tasks(), 2);
+
+    // The last task to arrive releases the whole group
+    assert_eq!(barrier.wait_timeout(10), Ok(()));
+    assert_eq!(barrier.waiting_tasks(), 0);
+    for id in [ids[1], ids[0]] {
+        let woken = WAITING_QUEUE.dequeue().unwrap();
+        assert_eq!(woken.id, id);
+        assert_eq!(woken.wait_result, Ok(()));
+    }
+
+    for id in ids {
+        TASK_TABLE.lock().unregister(id);
+    }
+}
+
+#[test_case]
+fn barrier_timeout_test() {
+    let barrier = Barrier::new(2);
+
+    let tcb = registered_task(1);
+    let id = tcb.id;
+    unsafe{ RUNNING = Some(tcb) };
+    assert_eq!(barrier.wait_timeout(1), Err(KernelError::Pending));
+    assert_eq!(barrier.waiting_tasks(), 1);
+
+    // The task gives up, and it no longer counts towards the group
+    kernel_tick();
+    kernel_tick();
+    assert_eq!(barrier.waiting_tasks(), 0);
+    let woken = WAITING_QUEUE.dequeue().unwrap();
+    assert_eq!(woken.wait_result, Err(KernelError::Timeout));
+    TASK_TABLE.lock().unregister(id);
+}
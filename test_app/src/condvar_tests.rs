@@ -0,0 +1,62 @@
+use kernel::WAITING_QUEUE;
+use kernel::condvar::Condvar;
+use kernel::error::KernelError;
+use kernel::task::{KERNEL_ID, RUNNING, TASK_TABLE};
+use kernel::task_mutex::TaskMutex;
+use crate::test_support::registered_task;
+
+#[test_case]
+fn condvar_owner_test() {
+    let condvar = Condvar::new();
+    let mutex = TaskMutex::new(false);
+
+    // The mutex must be locked by the caller
+    assert_eq!(condvar.wait_timeout(&mutex, 10), Err(KernelError::NotOwner));
+
+    // The kernel cannot wait, but it gets the mutex back
+    assert_eq!(mutex.try_lock(), Ok(()));
+    assert_eq!(condvar.wait_timeout(&mutex, 10), Err(KernelError::WouldBlock));
+    assert_eq!(mutex.owner(), Some(KERNEL_ID));
+    assert_eq!(mutex.unlock(), Ok(()));
+}
+
+#[test_case]
+fn condvar_notify_test() {
+    let condvar = Condvar::new();
+    let mutex = TaskMutex::new(false);
+
+    // Three tasks wait on the condition variable, releasing the mutex
+    let mut ids = [0; 3];
+    for (i, priority) in [1, 4, 2].iter().enumerate() {
+        let tcb = registered_task(*priority);
+        ids[i] = tcb.id;
+        unsafe{ RUNNING = Some(tcb) };
+        assert_eq!(mutex.try_lock(), Ok(()));
+        assert_eq!(condvar.wait_timeout(&mutex, 10), Err(KernelError::Pending));
+        assert!(unsafe{ RUNNING.is_none() });
+        // The mutex is locked again by the kernel, which runs in place of the
+        // blocked task here
+        assert_eq!(mutex.unlock(), Ok(()));
+    }
+    assert_eq!(condvar.waiting_tasks(), 3);
+
+    // The task with the highest priority is notified first
+    condvar.notify_one();
+    let woken = WAITING_QUEUE.dequeue().unwrap();
+    assert_eq!(woken.id, ids[1]);
+    assert_eq!(woken.wait_result, Ok(()));
+    assert_eq!(condvar.waiting_tasks(), 2);
+
+    condvar.notify_all();
+    assert_eq!(condvar.waiting_tasks(), 0);
+    assert_eq!(WAITING_QUEUE.dequeue().unwrap().id, ids[2]);
+    assert_eq!(WAITING_QUEUE.dequeue().unwrap().id, ids[0]);
+
+    // Nobody is waiting anymore
+    condvar.notify_one();
+    assert!(WAITING_QUEUE.dequeue().is_none());
+
+    for id in ids {
+        TASK_TABLE.lock().unregister(id);
+    }
+}
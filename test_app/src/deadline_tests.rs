@@ -0,0 +1,202 @@
+use kernel::WAITING_QUEUE;
+use kernel::error::KernelError;
+use kernel::deadline::{self, earlier};
+use kernel::semaphore::Semaphore;
+use kernel::task::{Queue, TaskState, TaskTCB, RUNNING, TASK_TABLE};
+use kernel::time::{step_ticks, ticks};
+use crate::test_support::registered_task;
+use alloc::boxed::Box;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+// Creates a task with a relative deadline, without scheduling it
+fn deadline_task(priority: usize, relative_deadline: u32) -> Box<TaskTCB> {
+    let mut tcb = registered_task(priority);
+    tcb.relative_deadline = relative_deadline;
+    tcb
+}
+
+#[test_case]
+fn deadline_order_test() {
+    assert!(earlier(Some(1), Some(2)));
+    assert!(!earlier(Some(2), Some(2)));
+    assert!(earlier(Some(u32::MAX), Some(1)));
+    assert!(earlier(Some(5), None));
+    assert!(!earlier(None, Some(5)));
+    assert!(!earlier(None, None));
+
+    // Tasks are sorted by deadline, those with none last, in FIFO order
+    let mut queue = Queue::new();
+    for (i, deadline) in [None, Some(30), Some(10), Some(30)].iter().enumerate() {
+        let mut tcb = Box::new(TaskTCB::new(None, 1));
+        tcb.id = i + 1;
+        tcb.deadline = *deadline;
+        queue.insert_by_deadline(tcb);
+    }
+    for id in [3, 2, 4, 1] {
+        assert_eq!(queue.dequeue().unwrap().id, id);
+    }
+    assert!(queue.empty());
+}
+
+#[test_case]
+fn deadline_miss_test() {
+    let mut tcb = deadline_task(1, 3);
+    let id = tcb.id;
+
+    // A task with no deadline is never late
+    let mut other = Box::new(TaskTCB::new(None, 1));
+    deadline::release(&mut other, ticks());
+    assert_eq!(other.deadline, None);
+    assert!(!deadline::check(&mut other, ticks().wrapping_add(100)));
+
+    // A job that completes in time is not counted
+    deadline::release(&mut tcb, ticks());
+    assert_eq!(tcb.deadline, Some(ticks().wrapping_add(3)));
+    step_ticks(1);
+    deadline::complete(&mut tcb, ticks());
+    assert_eq!(tcb.deadline_misses, 0);
+    assert_eq!(tcb.deadline, None);
+
+    // A late job is counted once, however many times it is checked
+    deadline::release(&mut tcb, ticks());
+    unsafe{ RUNNING = Some(tcb) };
+    for _ in 0..5 {
+        step_ticks(1);
+        deadline::check_tasks();
+    }
+    let mut tcb = unsafe{ RUNNING.take() }.unwrap();
+    assert_eq!(tcb.deadline_misses, 1);
+    deadline::complete(&mut tcb, ticks());
+    assert_eq!(tcb.deadline_misses, 1);
+
+    // A wait in the middle of a job does not complete it
+    let semaphore = Semaphore::binary(false);
+    deadline::release(&mut tcb, ticks());
+    let release = ticks();
+    unsafe{ RUNNING = Some(tcb) };
+    assert_eq!(semaphore.take(10), Err(KernelError::Pending));
+    step_ticks(1);
+    assert_eq!(semaphore.give(), Ok(()));
+    let tcb = WAITING_QUEUE.dequeue().unwrap();
+    assert_eq!(tcb.deadline, Some(release.wrapping_add(3)));
+
+    // Once the job is complete, waking up from a wait releases a new one
+    unsafe{ RUNNING = Some(tcb) };
+    assert_eq!(deadline::complete_job(), Ok(()));
+    assert_eq!(unsafe{ RUNNING.as_ref() }.unwrap().deadline, None);
+    assert_eq!(semaphore.take(10), Err(KernelError::Pending));
+    step_ticks(1);
+    assert_eq!(semaphore.give(), Ok(()));
+    let tcb = WAITING_QUEUE.dequeue().unwrap();
+    assert_eq!(tcb.deadline, Some(ticks().wrapping_add(3)));
+    assert!(!tcb.deadline_missed);
+
+    // Tasks with no deadline have no job to complete
+    unsafe{ RUNNING = Some(other) };
+    assert_eq!(deadline::complete_job(), Err(KernelError::InvalidArgument));
+    unsafe{ RUNNING = None };
+
+    TASK_TABLE.lock().unregister(id);
+}
+
+static MISSED_TASK: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn record_miss(id: usize) {
+    MISSED_TASK.store(id, Ordering::Relaxed);
+}
+
+#[test_case]
+fn periodic_task_test() {
+    let mut tcb = deadline_task(1, 0);
+    let id = tcb.id;
+    deadline::set_deadline_miss_hook(Some(record_miss));
+
+    // Tasks that are not periodic cannot wait for a period
+    assert_eq!(deadline::next_period(&mut tcb, ticks()), Err(KernelError::InvalidArgument));
+
+    // The first job is released right away, the next one a period later
+    let start = ticks();
+    deadline::start_periodic(&mut tcb, 10, 4, start);
+    assert_eq!(tcb.deadline, Some(start.wrapping_add(4)));
+    assert_eq!(tcb.next_release, Some(start.wrapping_add(10)));
+
+    // Blocking does not complete the job of a periodic task
+    deadline::complete(&mut tcb, start);
+    assert_eq!(tcb.deadline, Some(start.wrapping_add(4)));
+
+    // The task sleeps until the next release
+    unsafe{ RUNNING = Some(tcb) };
+    assert_eq!(deadline::wait_next_period(), Err(KernelError::Pending));
+    assert!(unsafe{ RUNNING.is_none() });
+    {
+        let mut table = TASK_TABLE.lock();
+        let tcb = table.get(id).unwrap();
+        assert_eq!(tcb.state, TaskState::Blocked);
+        assert_eq!(tcb.deadline, Some(start.wrapping_add(14)));
+    }
+    step_ticks(9);
+    assert!(WAITING_QUEUE.dequeue().is_none());
+    step_ticks(1);
+    let mut tcb = WAITING_QUEUE.dequeue().unwrap();
+    assert_eq!(tcb.id, id);
+    assert_eq!(tcb.deadline_misses, 0);
+
+    // A job that overruns its deadline is reported, once
+    step_ticks(4);
+    assert!(deadline::check(&mut tcb, ticks()));
+    assert!(!deadline::check(&mut tcb, ticks()));
+    assert_eq!(tcb.deadline_misses, 1);
+    assert_eq!(MISSED_TASK.load(Ordering::Relaxed), id);
+
+    // and one that overruns its period is followed right away by the next
+    step_ticks(10);
+    assert_eq!(deadline::next_period(&mut tcb, ticks()), Ok(None));
+    assert_eq!(deadline::next_period(&mut tcb, ticks()), Ok(Some(6)));
+
+    deadline::set_deadline_miss_hook(None);
+    TASK_TABLE.lock().unregister(id);
+}
+
+#[test_case]
+fn deadline_miss_hook_test() {
+    let mut tcb = deadline_task(1, 2);
+    let id = tcb.id;
+    deadline::set_deadline_miss_hook(Some(record_miss));
+    MISSED_TASK.store(0, Ordering::Relaxed);
+
+    // The misses found on a tick are reported to the hook
+    deadline::release(&mut tcb, ticks());
+    unsafe{ RUNNING = Some(tcb) };
+    step_ticks(1);
+    deadline::check_tasks();
+    assert_eq!(MISSED_TASK.load(Ordering::Relaxed), 0);
+    step_ticks(1);
+    deadline::check_tasks();
+    assert_eq!(MISSED_TASK.load(Ordering::Relaxed), id);
+    let tcb = unsafe{ RUNNING.take() }.unwrap();
+    assert_eq!(tcb.deadline_misses, 1);
+
+    deadline::set_deadline_miss_hook(None);
+    TASK_TABLE.lock().unregister(id);
+}
+
+#[cfg(feature = "edf")]
+#[test_case]
+fn edf_ready_queue_test() {
+    let late = deadline_task(1, 50);
+    let early = deadline_task(1, 5);
+    let higher = deadline_task(2, 100);
+    let background = registered_task(3);
+    let ids = [early.id, late.id, higher.id, background.id];
+    for mut tcb in [background, late, higher, early] {
+        deadline::release(&mut tcb, ticks());
+        WAITING_QUEUE.enqueue(tcb);
+    }
+
+    // The deadline comes first, whatever the priority, and the tasks with no
+    // deadline run last
+    for id in ids {
+        assert_eq!(WAITING_QUEUE.dequeue().unwrap().id, id);
+        TASK_TABLE.lock().unregister(id);
+    }
+}
@@ -0,0 +1,57 @@
+use kernel::WAITING_QUEUE;
+use kernel::error::KernelError;
+use kernel::event_group::{EventGroup, EVENT_CLEAR_ON_EXIT, EVENT_WAIT_ALL};
+use kernel::task::{RUNNING, TASK_TABLE};
+use crate::test_support::registered_task;
+
+#[test_case]
+fn event_group_test() {
+    let group = EventGroup::new();
+
+    assert_eq!(group.set(0b0101), 0b0101);
+    assert_eq!(group.clear(0b0001), 0b0101);
+    assert_eq!(group.get(), 0b0100);
+
+    // Waiting for any flag, or for all of them
+    assert_eq!(group.wait(0b0110, 0, 0), Ok(0b0100));
+    assert_eq!(group.wait(0b0110, EVENT_WAIT_ALL, 0), Err(KernelError::WouldBlock));
+    assert_eq!(group.wait(0, 0, 0), Err(KernelError::InvalidArgument));
+
+    // The flags are cleared when the wait is satisfied
+    assert_eq!(group.wait(0b0100, EVENT_CLEAR_ON_EXIT, 0), Ok(0b0100));
+    assert_eq!(group.get(), 0);
+}
+
+#[test_case]
+fn event_group_waiters_test() {
+    let group = EventGroup::new();
+
+    // One task waits for both flags, and clears them, the other for either
+    let mut values: [u32; 2] = [0; 2];
+    let options = [EVENT_WAIT_ALL | EVENT_CLEAR_ON_EXIT, 0];
+    let mut ids = [0; 2];
+    for i in 0..2 {
+        let tcb = registered_task(1);
+        ids[i] = tcb.id;
+        unsafe{ RUNNING = Some(tcb) };
+        assert_eq!(group.wait_into(0b11, options[i], 10, &mut values[i]), Err(KernelError::Pending));
+        assert!(unsafe{ RUNNING.is_none() });
+    }
+
+    // Only the second task's condition is met
+    assert_eq!(group.set(0b01), 0b01);
+    assert_eq!(WAITING_QUEUE.dequeue().unwrap().id, ids[1]);
+    assert_eq!(values[1], 0b01);
+    assert!(WAITING_QUEUE.empty());
+
+    // Now the first task wakes up, and its flags are cleared
+    assert_eq!(group.set(0b10), 0);
+    let woken = WAITING_QUEUE.dequeue().unwrap();
+    assert_eq!(woken.id, ids[0]);
+    assert_eq!(woken.wait_result, Ok(()));
+    assert_eq!(values[0], 0b11);
+
+    for id in ids {
+        TASK_TABLE.lock().unregister(id);
+    }
+}
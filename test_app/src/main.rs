@@ -5,9 +5,25 @@
 #![reexport_test_harness_main = "test_main"]
 
 pub mod allocator_tests;
+pub mod barrier_tests;
+pub mod condvar_tests;
+pub mod deadline_tests;
+pub mod event_group_tests;
+pub mod message_queue_tests;
+pub mod mutex_tests;
+pub mod notification_tests;
+pub mod pool_tests;
+pub mod rwlock_tests;
+pub mod semaphore_tests;
+pub mod stats_tests;
+pub mod stream_buffer_tests;
 pub mod syscalls_tests;
+pub mod task_mutex_tests;
 pub mod task_tests;
+pub mod test_support;
+pub mod timer_tests;
 pub mod utility_tests;
+pub mod wait_tests;
 
 extern crate alloc;
 use core::panic::PanicInfo;
@@ -24,7 +40,7 @@ static mut HEAP_MEM: [u8; 0x8000] = [0; 0x8000];
 fn _start() -> ! {
     // The kernel is initialized
     let heap_start = unsafe{ &HEAP_MEM[0] as *const u8 as usize };
-    kernel_init(heap_start, 0x8000, 120000);    
+    kernel_init(heap_start, 0x8000, 12_000_000);    
     
     #[cfg(test)]
     test_main();
@@ -0,0 +1,86 @@
+use kernel::WAITING_QUEUE;
+use kernel::error::KernelError;
+use kernel::message_queue::{MessageQueue, MsgQueue};
+use kernel::task::{RUNNING, TASK_TABLE};
+use crate::test_support::registered_task;
+
+#[test_case]
+fn message_queue_test() {
+    let queue: MsgQueue<u32> = MsgQueue::new(3).unwrap();
+    assert_eq!(queue.try_receive(), Err(KernelError::WouldBlock));
+    assert_eq!(queue.peek(), Err(KernelError::WouldBlock));
+
+    for item in 1..4 {
+        assert_eq!(queue.try_send(item), Ok(()));
+    }
+    assert_eq!(queue.try_send(4), Err(KernelError::WouldBlock));
+    assert_eq!(queue.len(), 3);
+
+    // Items are received in the order they were sent
+    assert_eq!(queue.peek(), Ok(1));
+    assert_eq!(queue.try_receive(), Ok(1));
+
+    // The ring buffer wraps around
+    assert_eq!(queue.send_from_isr(4), Ok(()));
+    for item in 2..5 {
+        assert_eq!(queue.try_receive(), Ok(item));
+    }
+    assert_eq!(queue.len(), 0);
+}
+
+#[test_case]
+fn message_queue_items_test() {
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct Message {
+        id: u8,
+        payload: [u16; 3],
+    }
+
+    let queue: MsgQueue<Message> = MsgQueue::new(2).unwrap();
+    let message = Message { id: 7, payload: [1, 2, 3] };
+    assert_eq!(queue.try_send(message), Ok(()));
+    assert_eq!(queue.try_receive(), Ok(message));
+
+    assert_eq!(MessageQueue::new(0, 4).err(), Some(KernelError::InvalidArgument));
+    assert_eq!(MessageQueue::new(4, 0).err(), Some(KernelError::InvalidArgument));
+}
+
+#[test_case]
+fn message_queue_waiters_test() {
+    let queue = MessageQueue::new(4, 1).unwrap();
+
+    // A task waiting on an empty queue gets the item straight into its buffer
+    let mut received: u32 = 0;
+    let receiver = registered_task(2);
+    let receiver_id = receiver.id;
+    unsafe{ RUNNING = Some(receiver) };
+    assert_eq!(queue.receive(&mut received as *mut u32 as *mut u8, 10), Err(KernelError::Pending));
+    assert!(unsafe{ RUNNING.is_none() });
+
+    let item: u32 = 42;
+    assert_eq!(queue.try_send(&item as *const u32 as *const u8), Ok(()));
+    assert_eq!(received, 42);
+    assert_eq!(queue.len(), 0);
+    let receiver = WAITING_QUEUE.dequeue().unwrap();
+    assert_eq!(receiver.id, receiver_id);
+    assert_eq!(receiver.wait_result, Ok(()));
+
+    // A task waiting on a full queue has its item queued as soon as there
+    // is room
+    let items: [u32; 2] = [1, 2];
+    assert_eq!(queue.try_send(&items[0] as *const u32 as *const u8), Ok(()));
+    let sender = registered_task(2);
+    let sender_id = sender.id;
+    unsafe{ RUNNING = Some(sender) };
+    assert_eq!(queue.send(&items[1] as *const u32 as *const u8, 10), Err(KernelError::Pending));
+    assert!(unsafe{ RUNNING.is_none() });
+
+    assert_eq!(queue.try_receive(&mut received as *mut u32 as *mut u8), Ok(()));
+    assert_eq!(received, 1);
+    assert_eq!(WAITING_QUEUE.dequeue().unwrap().id, sender_id);
+    assert_eq!(queue.try_receive(&mut received as *mut u32 as *mut u8), Ok(()));
+    assert_eq!(received, 2);
+
+    TASK_TABLE.lock().unregister(receiver_id);
+    TASK_TABLE.lock().unregister(sender_id);
+}
@@ -0,0 +1,45 @@
+use kernel::mutex::{Mutex, CriticalSection, kernel_interrupts_masked};
+
+#[test_case]
+fn test_lock() {
+    let mutex = Mutex::new(0);
+    {
+        let mut value = mutex.lock();
+        assert!(kernel_interrupts_masked());
+        *value += 1;
+    }
+    assert!(!kernel_interrupts_masked());
+    assert_eq!(*mutex.lock(), 1);
+}
+
+#[test_case]
+fn test_nested_locks() {
+    let outer = Mutex::new(0);
+    let inner = Mutex::new(0);
+
+    let outer_guard = outer.lock();
+    {
+        let _inner_guard = inner.lock();
+        assert!(kernel_interrupts_masked());
+    }
+
+    // Releasing the inner lock must not unmask interrupts while the outer
+    // lock is still held
+    assert!(kernel_interrupts_masked());
+    drop(outer_guard);
+    assert!(!kernel_interrupts_masked());
+}
+
+#[test_case]
+fn test_critical_section_restores_state() {
+    // A critical section entered with interrupts already disabled leaves
+    // them disabled
+    cortex_m::interrupt::disable();
+    {
+        let _section = CriticalSection::enter();
+        assert!(kernel_interrupts_masked());
+    }
+    assert!(kernel_interrupts_masked());
+    unsafe{ cortex_m::interrupt::enable() };
+    assert!(!kernel_interrupts_masked());
+}
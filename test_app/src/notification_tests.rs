@@ -0,0 +1,80 @@
+use kernel::WAITING_QUEUE;
+use kernel::error::KernelError;
+use kernel::notification::{notify, notify_take, notify_take_into, notify_wait, notify_wait_into, NotifyAction};
+use kernel::task::{MAX_TASKS, RUNNING, TASK_TABLE};
+use crate::test_support::registered_task;
+
+#[test_case]
+fn notify_actions_test() {
+    let tcb = registered_task(1);
+    let id = tcb.id;
+    unsafe{ RUNNING = Some(tcb) };
+
+    assert_eq!(notify_wait(0), Err(KernelError::WouldBlock));
+
+    assert_eq!(notify(id, 0b001, NotifyAction::SetBits), Ok(()));
+    assert_eq!(notify(id, 0b100, NotifyAction::SetBits), Ok(()));
+    assert_eq!(notify_wait(0), Ok(0b101));
+
+    // The word is reset by each wait
+    assert_eq!(notify(id, 0, NotifyAction::Increment), Ok(()));
+    assert_eq!(notify(id, 0, NotifyAction::Increment), Ok(()));
+    assert_eq!(notify_wait(0), Ok(2));
+
+    assert_eq!(notify(id, 7, NotifyAction::SetBits), Ok(()));
+    assert_eq!(notify(id, 42, NotifyAction::Overwrite), Ok(()));
+    assert_eq!(notify_wait(0), Ok(42));
+
+    unsafe{ RUNNING = None };
+    TASK_TABLE.lock().unregister(id);
+    assert_eq!(notify(id, 1, NotifyAction::SetBits), Err(KernelError::InvalidArgument));
+    assert_eq!(notify(MAX_TASKS + 1, 1, NotifyAction::SetBits), Err(KernelError::InvalidArgument));
+}
+
+#[test_case]
+fn notify_waiter_test() {
+    let mut value: u32 = 0;
+    let tcb = registered_task(1);
+    let id = tcb.id;
+    unsafe{ RUNNING = Some(tcb) };
+    assert_eq!(notify_wait_into(10, &mut value), Err(KernelError::Pending));
+    assert!(unsafe{ RUNNING.is_none() });
+
+    // The waiting task is woken up, and gets its notification word
+    assert_eq!(notify(id, 0x10, NotifyAction::Overwrite), Ok(()));
+    let woken = WAITING_QUEUE.dequeue().unwrap();
+    assert_eq!(woken.id, id);
+    assert_eq!(woken.wait_result, Ok(()));
+    assert_eq!(woken.notification_pending, false);
+    assert_eq!(value, 0x10);
+
+    TASK_TABLE.lock().unregister(id);
+}
+
+#[test_case]
+fn notify_take_test() {
+    let tcb = registered_task(1);
+    let id = tcb.id;
+    unsafe{ RUNNING = Some(tcb) };
+
+    // Each take consumes a single notification
+    assert_eq!(notify(id, 0, NotifyAction::Increment), Ok(()));
+    assert_eq!(notify(id, 0, NotifyAction::Increment), Ok(()));
+    assert_eq!(notify_take(0), Ok(2));
+    assert_eq!(notify_take(0), Ok(1));
+    assert_eq!(notify_take(0), Err(KernelError::WouldBlock));
+
+    // A waiting task is only woken up once its word is non-zero
+    let mut value: u32 = 0;
+    assert_eq!(notify_take_into(10, &mut value), Err(KernelError::Pending));
+    assert_eq!(notify(id, 0, NotifyAction::Overwrite), Ok(()));
+    assert!(WAITING_QUEUE.empty());
+    assert_eq!(notify(id, 0, NotifyAction::Increment), Ok(()));
+    let woken = WAITING_QUEUE.dequeue().unwrap();
+    assert_eq!(woken.id, id);
+    assert_eq!(woken.wait_result, Ok(()));
+    assert_eq!(woken.notification_value, 0);
+    assert_eq!(value, 1);
+
+    TASK_TABLE.lock().unregister(id);
+}
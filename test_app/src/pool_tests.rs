@@ -0,0 +1,140 @@
+use kernel::error::KernelError;
+use kernel::pool::{try_new_pooled, Pool, MemoryPool, TCB_POOL};
+use kernel::task::{TaskTCB, RUNNING, TASK_TABLE};
+use crate::test_support::registered_task;
+use kernel::HEAP;
+use alloc::boxed::Box;
+use core::mem::size_of;
+
+static mut TEST_POOL: MemoryPool<[u32; 4], 8> = MemoryPool::new();
+
+#[test_case]
+fn pool_init_test() {
+    let pool_mem: [usize; 64] = [0; 64];
+    let mut pool = Pool::new(16, 16);
+    pool.init(&pool_mem[0] as *const usize as usize);
+    assert_eq!(pool.block_size(), 16);
+    assert_eq!(pool.available_blocks(), 16);
+    assert_eq!(pool.storage_size(), 256);
+}
+
+#[test_case]
+fn pool_block_size_test() {
+    // Blocks are never smaller than the free list header, and are always
+    // a multiple of the alignment
+    assert!(Pool::block_size_for(1, 1) >= kernel::pool::POOL_BLOCK_HEADER_SIZE);
+    assert_eq!(Pool::block_size_for(20, 8), 24);
+    assert_eq!(Pool::block_size_for(32, 4), 32);
+}
+
+#[test_case]
+fn pool_allocate_test() {
+    let pool_mem: [usize; 64] = [0; 64];
+    let mut pool = Pool::new(16, 16);
+    let start = &pool_mem[0] as *const usize as usize;
+    pool.init(start);
+
+    for i in 0..16 {
+        let block = pool.allocate_block().unwrap();
+        assert_eq!(block as usize, start + i * 16);
+        assert_eq!(pool.available_blocks(), 16 - i - 1);
+    }
+
+    // The pool is exhausted
+    assert!(pool.allocate_block().is_none());
+}
+
+#[test_case]
+fn pool_free_test() {
+    let pool_mem: [usize; 64] = [0; 64];
+    let mut pool = Pool::new(16, 4);
+    pool.init(&pool_mem[0] as *const usize as usize);
+
+    let first = pool.allocate_block().unwrap();
+    let second = pool.allocate_block().unwrap();
+    assert_eq!(pool.available_blocks(), 2);
+
+    // The most recently freed block is the first to be handed out again
+    assert_eq!(pool.free_block(first as usize), Ok(()));
+    assert_eq!(pool.free_block(second as usize), Ok(()));
+    assert_eq!(pool.available_blocks(), 4);
+    assert_eq!(pool.allocate_block().unwrap(), second);
+    assert_eq!(pool.allocate_block().unwrap(), first);
+}
+
+#[test_case]
+fn pool_invalid_free_test() {
+    let pool_mem: [usize; 64] = [0; 64];
+    let mut pool = Pool::new(16, 4);
+    let start = &pool_mem[0] as *const usize as usize;
+    pool.init(start);
+    let block = pool.allocate_block().unwrap() as usize;
+
+    // Misaligned blocks, blocks outside the pool and blocks never handed out
+    // are rejected
+    assert_eq!(pool.free_block(block + 4), Err(KernelError::InvalidArgument));
+    assert_eq!(pool.free_block(start + 64), Err(KernelError::InvalidArgument));
+    assert_eq!(pool.free_block(start + 16), Err(KernelError::InvalidArgument));
+    assert_eq!(pool.available_blocks(), 3);
+
+    // and so are double frees, when the block comes from the application
+    assert_eq!(pool.free_checked(block), Ok(()));
+    assert_eq!(pool.free_checked(block), Err(KernelError::InvalidArgument));
+    assert_eq!(pool.available_blocks(), 4);
+}
+
+#[test_case]
+fn memory_pool_test() {
+    let pool = unsafe{ &TEST_POOL };
+    assert_eq!(pool.available(), 8);
+
+    {
+        let mut item = pool.alloc([1, 2, 3, 4]).unwrap();
+        assert_eq!(pool.available(), 7);
+        item[0] = 10;
+        assert_eq!(*item, [10, 2, 3, 4]);
+    }
+
+    // The block is given back when the item goes out of scope
+    assert_eq!(pool.available(), 8);
+}
+
+#[test_case]
+fn tcb_pool_test() {
+    // Task control blocks are allocated from the kernel's pool, and the
+    // heap is left untouched
+    let available_blocks = TCB_POOL.available_blocks();
+    let available_space = HEAP.available_space();
+
+    let tcb = try_new_pooled(TaskTCB::new(None, 0)).unwrap();
+    assert!(TCB_POOL.contains(&*tcb as *const TaskTCB as *const u8));
+    assert_eq!(TCB_POOL.available_blocks(), available_blocks - 1);
+    assert_eq!(HEAP.available_space(), available_space);
+
+    drop(tcb);
+    assert_eq!(TCB_POOL.available_blocks(), available_blocks);
+
+    // Other allocations of the same size are served by the heap
+    let other = Box::new(TaskTCB::new(None, 0));
+    assert!(!TCB_POOL.contains(&*other as *const TaskTCB as *const u8));
+    assert_eq!(TCB_POOL.available_blocks(), available_blocks);
+}
+
+#[test_case]
+fn pooled_task_allocation_test() {
+    // An object allocated on behalf of a task comes from the heap, and it is
+    // charged to the task
+    let tcb = registered_task(0);
+    let id = tcb.id;
+    unsafe{ RUNNING = Some(tcb) };
+    let available_blocks = TCB_POOL.available_blocks();
+
+    let object = try_new_pooled(TaskTCB::new(None, 0)).unwrap();
+    assert_eq!(TCB_POOL.available_blocks(), available_blocks);
+    assert_eq!(HEAP.task_allocations(id), (1, size_of::<TaskTCB>()));
+    drop(object);
+    assert_eq!(HEAP.task_allocations(id), (0, 0));
+
+    TASK_TABLE.lock().unregister(id);
+    unsafe{ RUNNING = None };
+}
@@ -0,0 +1,102 @@
+use kernel::WAITING_QUEUE;
+use kernel::error::KernelError;
+use kernel::rwlock::RwLock;
+use kernel::task::{KERNEL_ID, RUNNING, TASK_TABLE};
+use kernel::time::kernel_tick;
+use crate::test_support::registered_task;
+
+#[test_case]
+fn rwlock_readers_test() {
+    let lock = RwLock::new();
+
+    // Many readers can hold the lock, but then no writer can
+    assert_eq!(lock.read(0), Ok(()));
+    assert_eq!(lock.read(0), Ok(()));
+    assert_eq!(lock.readers(), 2);
+    assert_eq!(lock.write(0), Err(KernelError::WouldBlock));
+
+    assert_eq!(lock.read_unlock(), Ok(()));
+    assert_eq!(lock.read_unlock(), Ok(()));
+    assert_eq!(lock.read_unlock(), Err(KernelError::NotOwner));
+
+    assert_eq!(lock.write(0), Ok(()));
+    assert_eq!(lock.writer(), Some(KERNEL_ID));
+    assert_eq!(lock.read(0), Err(KernelError::WouldBlock));
+    assert_eq!(lock.write(0), Err(KernelError::Deadlock));
+    assert_eq!(lock.write_unlock(), Ok(()));
+    assert_eq!(lock.write_unlock(), Err(KernelError::NotOwner));
+}
+
+#[test_case]
+fn rwlock_writer_fairness_test() {
+    let lock = RwLock::new();
+    assert_eq!(lock.read(0), Ok(()));
+
+    // A writer waits for the reader, and then a new reader waits too
+    let writer = registered_task(1);
+    let writer_id = writer.id;
+    unsafe{ RUNNING = Some(writer) };
+    assert_eq!(lock.write(10), Err(KernelError::Pending));
+    assert!(unsafe{ RUNNING.is_none() });
+
+    let reader = registered_task(3);
+    let reader_id = reader.id;
+    unsafe{ RUNNING = Some(reader) };
+    assert_eq!(lock.read(10), Err(KernelError::Pending));
+    assert!(unsafe{ RUNNING.is_none() });
+    assert_eq!(lock.readers(), 1);
+
+    // The last reader hands the lock over to the writer
+    assert_eq!(lock.read_unlock(), Ok(()));
+    assert_eq!(lock.writer(), Some(writer_id));
+    let woken = WAITING_QUEUE.dequeue().unwrap();
+    assert_eq!(woken.id, writer_id);
+    assert_eq!(woken.wait_result, Ok(()));
+
+    // The writer hands it over to the waiting reader
+    unsafe{ RUNNING = Some(woken) };
+    assert_eq!(lock.write_unlock(), Ok(()));
+    unsafe{ RUNNING = None };
+    assert_eq!(lock.writer(), None);
+    assert_eq!(lock.readers(), 1);
+    assert_eq!(WAITING_QUEUE.dequeue().unwrap().id, reader_id);
+    assert_eq!(lock.read_unlock(), Ok(()));
+
+    for id in [writer_id, reader_id] {
+        TASK_TABLE.lock().unregister(id);
+    }
+}
+
+#[test_case]
+fn rwlock_writer_timeout_test() {
+    let lock = RwLock::new();
+    assert_eq!(lock.read(0), Ok(()));
+
+    let writer = registered_task(1);
+    let writer_id = writer.id;
+    unsafe{ RUNNING = Some(writer) };
+    assert_eq!(lock.write(1), Err(KernelError::Pending));
+
+    let reader = registered_task(1);
+    let reader_id = reader.id;
+    unsafe{ RUNNING = Some(reader) };
+    assert_eq!(lock.read(10), Err(KernelError::Pending));
+
+    // Once the writer gives up, the reader it held back gets the lock
+    kernel_tick();
+    kernel_tick();
+    assert_eq!(lock.readers(), 2);
+    let mut results = [(0, Ok(())); 2];
+    for result in results.iter_mut() {
+        let woken = WAITING_QUEUE.dequeue().unwrap();
+        *result = (woken.id, woken.wait_result);
+    }
+    assert!(results.contains(&(writer_id, Err(KernelError::Timeout))));
+    assert!(results.contains(&(reader_id, Ok(()))));
+
+    assert_eq!(lock.read_unlock(), Ok(()));
+    assert_eq!(lock.read_unlock(), Ok(()));
+    for id in [writer_id, reader_id] {
+        TASK_TABLE.lock().unregister(id);
+    }
+}
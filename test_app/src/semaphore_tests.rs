@@ -0,0 +1,81 @@
+use kernel::WAITING_QUEUE;
+use kernel::error::KernelError;
+use kernel::registry::{self, ObjectKind, REGISTRY};
+use kernel::semaphore::Semaphore;
+use kernel::task::{RUNNING, TASK_TABLE};
+use crate::test_support::registered_task;
+
+#[test_case]
+fn counting_semaphore_test() {
+    let semaphore = Semaphore::new(2, 3);
+
+    assert_eq!(semaphore.take(0), Ok(()));
+    assert_eq!(semaphore.take(0), Ok(()));
+    assert_eq!(semaphore.count(), 0);
+    assert_eq!(semaphore.take(0), Err(KernelError::WouldBlock));
+
+    for _ in 0..3 {
+        assert_eq!(semaphore.give(), Ok(()));
+    }
+    assert_eq!(semaphore.give(), Err(KernelError::Full));
+    assert_eq!(semaphore.count(), 3);
+}
+
+#[test_case]
+fn binary_semaphore_test() {
+    let semaphore = Semaphore::binary(false);
+
+    assert_eq!(semaphore.take(0), Err(KernelError::WouldBlock));
+    assert_eq!(semaphore.give_from_isr(), Ok(()));
+    assert_eq!(semaphore.give_from_isr(), Err(KernelError::Full));
+    assert_eq!(semaphore.take(0), Ok(()));
+}
+
+#[test_case]
+fn semaphore_waiters_test() {
+    let semaphore = Semaphore::binary(false);
+
+    // Two tasks block on the semaphore, the one with the lowest priority first
+    let mut ids = [0; 2];
+    for (i, priority) in [1, 4].iter().enumerate() {
+        let tcb = registered_task(*priority);
+        ids[i] = tcb.id;
+        unsafe{ RUNNING = Some(tcb) };
+        assert_eq!(semaphore.take(10), Err(KernelError::Pending));
+        assert!(unsafe{ RUNNING.is_none() });
+    }
+    assert_eq!(semaphore.waiting_tasks(), 2);
+
+    // Each unit is handed over to the waiting task with the highest priority
+    assert_eq!(semaphore.give(), Ok(()));
+    let woken = WAITING_QUEUE.dequeue().unwrap();
+    assert_eq!(woken.id, ids[1]);
+    assert_eq!(woken.wait_result, Ok(()));
+    assert_eq!(semaphore.count(), 0);
+
+    assert_eq!(semaphore.give(), Ok(()));
+    assert_eq!(WAITING_QUEUE.dequeue().unwrap().id, ids[0]);
+    assert_eq!(semaphore.waiting_tasks(), 0);
+
+    for id in ids {
+        TASK_TABLE.lock().unregister(id);
+    }
+}
+
+static STATIC_SEMAPHORE: Semaphore = Semaphore::new(0, 4);
+
+#[test_case]
+fn registry_test() {
+    let semaphores = REGISTRY.lock().count(ObjectKind::SemaphoreObject);
+
+    assert_eq!(registry::register(ObjectKind::SemaphoreObject, &STATIC_SEMAPHORE), Ok(()));
+    assert_eq!(REGISTRY.lock().count(ObjectKind::SemaphoreObject), semaphores + 1);
+    // Registering the same object again has no effect
+    assert_eq!(registry::register(ObjectKind::SemaphoreObject, &STATIC_SEMAPHORE), Ok(()));
+    assert_eq!(REGISTRY.lock().count(ObjectKind::SemaphoreObject), semaphores + 1);
+
+    let address = &STATIC_SEMAPHORE as *const Semaphore as usize;
+    assert!(REGISTRY.lock().contains(address));
+    REGISTRY.lock().unregister(address);
+    assert!(!REGISTRY.lock().contains(address));
+}
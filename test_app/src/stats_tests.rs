@@ -0,0 +1,82 @@
+use kernel::WAITING_QUEUE;
+use kernel::stats::{self, TaskStats};
+use kernel::task::{schedule, TaskState, CURRENT, MAX_TASKS, RUNNING, TASK_TABLE};
+use kernel::time::step_ticks;
+use crate::test_support::ready_task;
+use core::ptr;
+
+#[test_case]
+fn task_stats_test() {
+    let first = ready_task(1);
+    unsafe{ schedule() };
+    step_ticks(5);
+
+    // The first task is charged for its time when it is switched out
+    let second = ready_task(2);
+    unsafe{ schedule() };
+    unsafe{ CURRENT = ptr::null_mut() };
+
+    let mut buffer = [TaskStats {
+        id: 0,
+        priority: 0,
+        state: TaskState::Ready,
+        idle: false,
+        cpu_time: 0,
+        cpu_percent: 0,
+        context_switches: 0,
+        last_run: 0,
+        deadline_misses: 0,
+    }; MAX_TASKS];
+    let count = stats::snapshot(&mut buffer);
+    let tasks = &buffer[..count];
+
+    let stats = tasks.iter().find(|stats| stats.id == first).unwrap();
+    assert_eq!(stats.context_switches, 1);
+    assert_eq!(stats.state, TaskState::Ready);
+    if stats::cpu_time_in_cycles() {
+        assert!(stats.cpu_time > 0);
+    } else {
+        assert_eq!(stats.cpu_time, 5);
+    }
+    let stats = tasks.iter().find(|stats| stats.id == second).unwrap();
+    assert_eq!(stats.state, TaskState::Running);
+    assert_eq!(stats.context_switches, 1);
+
+    // The idle task is listed as well
+    assert!(tasks.iter().any(|stats| stats.idle));
+    assert!(tasks.iter().map(|stats| stats.cpu_percent).sum::<u32>() <= 100);
+
+    unsafe{ RUNNING = None };
+    drop(WAITING_QUEUE.remove(first));
+    for id in [first, second] {
+        TASK_TABLE.lock().unregister(id);
+    }
+}
+
+#[test_case]
+fn running_task_charged_test() {
+    let id = ready_task(1);
+    unsafe{ schedule() };
+    unsafe{ CURRENT = ptr::null_mut() };
+
+    // A task that is never switched out is charged on every tick
+    step_ticks(3);
+    stats::tick();
+    let cpu_time = unsafe{ RUNNING.as_ref().unwrap().cpu_time };
+    if stats::cpu_time_in_cycles() {
+        assert!(cpu_time > 0);
+    } else {
+        assert_eq!(cpu_time, 3);
+    }
+    step_ticks(2);
+    stats::tick();
+    let tcb = unsafe{ RUNNING.take().unwrap() };
+    assert_eq!(tcb.id, id);
+    if stats::cpu_time_in_cycles() {
+        assert!(tcb.cpu_time > cpu_time);
+    } else {
+        assert_eq!(tcb.cpu_time, 5);
+    }
+
+    TASK_TABLE.lock().unregister(id);
+}
@@ -0,0 +1,59 @@
+use kernel::WAITING_QUEUE;
+use kernel::error::KernelError;
+use kernel::stream_buffer::StreamBuffer;
+use kernel::task::{RUNNING, TASK_TABLE};
+use crate::test_support::registered_task;
+
+#[test_case]
+fn stream_buffer_test() {
+    let stream = StreamBuffer::new(8, 1).unwrap();
+    let data: [u8; 10] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+    let mut buffer: [u8; 10] = [0; 10];
+
+    // Only the bytes that fit are written
+    assert_eq!(stream.write(&data[0], 10), 8);
+    assert_eq!(stream.available(), 8);
+    assert_eq!(stream.space(), 0);
+
+    assert_eq!(stream.try_read(&mut buffer[0], 5), 5);
+    assert_eq!(buffer[..5], data[..5]);
+
+    // The bytes wrap around the end of the buffer
+    assert_eq!(stream.write(&data[8], 2), 2);
+    assert_eq!(stream.try_read(&mut buffer[0], 10), 5);
+    assert_eq!(buffer[..5], data[5..]);
+    assert_eq!(stream.available(), 0);
+
+    assert_eq!(stream.read(&mut buffer[0], 10, 0), Err(KernelError::WouldBlock));
+    assert_eq!(StreamBuffer::new(8, 9).err(), Some(KernelError::InvalidArgument));
+}
+
+#[test_case]
+fn stream_buffer_trigger_test() {
+    let stream = StreamBuffer::new(16, 4).unwrap();
+    let data: [u8; 4] = [1, 2, 3, 4];
+    let mut buffer: [u8; 4] = [0; 4];
+
+    // Below the trigger level the bytes are read anyway, if the reader
+    // doesn't wait
+    assert_eq!(stream.write(&data[0], 2), 2);
+    assert_eq!(stream.read(&mut buffer[0], 4, 0), Ok(2));
+
+    // A waiting reader is woken up once the trigger level is reached
+    let tcb = registered_task(1);
+    let id = tcb.id;
+    unsafe{ RUNNING = Some(tcb) };
+    assert_eq!(stream.wait_for_data(10), Err(KernelError::Pending));
+    assert!(unsafe{ RUNNING.is_none() });
+
+    assert_eq!(stream.write(&data[0], 3), 3);
+    assert!(WAITING_QUEUE.empty());
+    assert_eq!(stream.write(&data[3], 1), 1);
+    let reader = WAITING_QUEUE.dequeue().unwrap();
+    assert_eq!(reader.id, id);
+    assert_eq!(reader.wait_result, Ok(()));
+
+    assert_eq!(stream.try_read(&mut buffer[0], 4), 4);
+    assert_eq!(buffer, data);
+    TASK_TABLE.lock().unregister(id);
+}
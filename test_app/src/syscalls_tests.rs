@@ -3,7 +3,8 @@ use core::arch::asm;
 use cortex_m_semihosting::hprintln;
 use kernel::syscalls::{create_task, task_switch, kcreate_task};
 use kernel::{WAITING_QUEUE};
-use kernel::task::{TaskTCB, RUNNING};
+use kernel::task::{TaskTCB, RUNNING, TASK_TABLE};
+use kernel::error::SUCCESS;
 use alloc::boxed::Box;
 
 const ARGS_PTR: *mut u8 = 123 as *mut u8;
@@ -11,10 +12,11 @@ const ARGS_PTR: *mut u8 = 123 as *mut u8;
 
 #[test_case]
 fn test_create_task() {
-    create_task(mock_task, ARGS_PTR, 0);
+    assert_eq!(create_task(mock_task, ARGS_PTR, 0), SUCCESS);
     assert_eq!(WAITING_QUEUE.count_tasks(), 1);
 
     let mut created_task = WAITING_QUEUE.dequeue().unwrap();
+    TASK_TABLE.lock().unregister(created_task.id);
 
     let stack_top = created_task.stp as *mut usize;
 
@@ -0,0 +1,144 @@
+use kernel::WAITING_QUEUE;
+use kernel::error::KernelError;
+use kernel::task::{self, TaskState, KERNEL_ID, RUNNING, TASK_TABLE};
+use kernel::task_mutex::TaskMutex;
+use kernel::time::kernel_tick;
+use crate::test_support::registered_task;
+
+#[test_case]
+fn task_mutex_lock_test() {
+    let mutex = TaskMutex::new(false);
+
+    assert_eq!(mutex.try_lock(), Ok(()));
+    assert_eq!(mutex.owner(), Some(KERNEL_ID));
+
+    // A non-recursive mutex cannot be locked twice by its owner
+    assert_eq!(mutex.try_lock(), Err(KernelError::Deadlock));
+
+    assert_eq!(mutex.unlock(), Ok(()));
+    assert_eq!(mutex.owner(), None);
+    assert_eq!(mutex.unlock(), Err(KernelError::NotOwner));
+}
+
+#[test_case]
+fn recursive_task_mutex_test() {
+    let mutex = TaskMutex::new(true);
+
+    for _ in 0..3 {
+        assert_eq!(mutex.try_lock(), Ok(()));
+    }
+    // The mutex is released by the last unlock only
+    for _ in 0..3 {
+        assert_eq!(mutex.owner(), Some(KERNEL_ID));
+        assert_eq!(mutex.unlock(), Ok(()));
+    }
+    assert_eq!(mutex.owner(), None);
+}
+
+#[test_case]
+fn task_mutex_owner_test() {
+    let mutex = TaskMutex::new(false);
+    assert_eq!(mutex.try_lock(), Ok(()));
+
+    // Another task can neither lock nor unlock the mutex
+    let tcb = registered_task(1);
+    let id = tcb.id;
+    unsafe{ RUNNING = Some(tcb) };
+    assert_eq!(mutex.try_lock(), Err(KernelError::WouldBlock));
+    assert_eq!(mutex.unlock(), Err(KernelError::NotOwner));
+    unsafe{ RUNNING = None };
+    TASK_TABLE.lock().unregister(id);
+
+    assert_eq!(mutex.unlock(), Ok(()));
+}
+
+#[test_case]
+fn task_mutex_inheritance_test() {
+    let mutex = TaskMutex::new(false);
+
+    // A low priority task locks the mutex, then it is preempted
+    let owner = registered_task(1);
+    let owner_id = owner.id;
+    unsafe{ RUNNING = Some(owner) };
+    assert_eq!(mutex.try_lock(), Ok(()));
+    WAITING_QUEUE.enqueue(unsafe{ RUNNING.take().unwrap() });
+
+    // A high priority task blocks on it for 2 ticks, and the owner inherits
+    // its priority
+    let waiter = registered_task(5);
+    let waiter_id = waiter.id;
+    unsafe{ RUNNING = Some(waiter) };
+    assert_eq!(mutex.lock_timeout(2), Err(KernelError::Pending));
+    assert!(unsafe{ RUNNING.is_none() });
+    assert_eq!(WAITING_QUEUE.highest_priority(), Some(5));
+
+    // When the wait times out the waiter is ready again, and the owner gets
+    // its own priority back
+    kernel_tick();
+    assert_eq!(WAITING_QUEUE.count_tasks(), 1);
+    kernel_tick();
+    assert_eq!(WAITING_QUEUE.count_tasks(), 2);
+
+    let waiter = WAITING_QUEUE.dequeue().unwrap();
+    assert_eq!(waiter.id, waiter_id);
+    assert_eq!(waiter.state, TaskState::Ready);
+    assert_eq!(waiter.wait_result, Err(KernelError::Timeout));
+    let owner = WAITING_QUEUE.dequeue().unwrap();
+    assert_eq!(owner.priority, 1);
+
+    unsafe{ RUNNING = Some(owner) };
+    assert_eq!(mutex.unlock(), Ok(()));
+    unsafe{ RUNNING = None };
+    TASK_TABLE.lock().unregister(owner_id);
+    TASK_TABLE.lock().unregister(waiter_id);
+}
+
+#[test_case]
+fn task_mutex_set_priority_test() {
+    let mutex = TaskMutex::new(false);
+
+    // A task locks the mutex, then it is preempted, and another task blocks
+    // on it
+    let owner = registered_task(1);
+    let owner_id = owner.id;
+    unsafe{ RUNNING = Some(owner) };
+    assert_eq!(mutex.try_lock(), Ok(()));
+    WAITING_QUEUE.enqueue(unsafe{ RUNNING.take().unwrap() });
+
+    let waiter = registered_task(2);
+    let waiter_id = waiter.id;
+    unsafe{ RUNNING = Some(waiter) };
+    assert_eq!(mutex.lock(), Err(KernelError::Pending));
+    assert_eq!(task::get_priority(owner_id), Some(2));
+
+    // Raising the priority of the waiter raises the owner's as well
+    assert_eq!(task::set_priority(waiter_id, 6), Ok(()));
+    assert_eq!(task::get_priority(waiter_id), Some(6));
+    assert_eq!(task::get_priority(owner_id), Some(6));
+    assert_eq!(WAITING_QUEUE.highest_priority(), Some(6));
+
+    // The owner keeps the inherited priority when its own is lowered, and
+    // only that one once the waiter's is lowered too
+    assert_eq!(task::set_priority(owner_id, 0), Ok(()));
+    assert_eq!(task::get_priority(owner_id), Some(6));
+    assert_eq!(task::set_priority(waiter_id, 3), Ok(()));
+    assert_eq!(task::get_priority(owner_id), Some(3));
+    assert_eq!(task::set_priority(owner_id, 4), Ok(()));
+    assert_eq!(task::get_priority(owner_id), Some(4));
+
+    // The mutex is handed over to the waiter, and the owner gets its own
+    // priority back
+    let owner = WAITING_QUEUE.dequeue().unwrap();
+    unsafe{ RUNNING = Some(owner) };
+    assert_eq!(mutex.unlock(), Ok(()));
+    assert_eq!(mutex.owner(), Some(waiter_id));
+    assert_eq!(task::get_priority(owner_id), Some(4));
+    let waiter = WAITING_QUEUE.dequeue().unwrap();
+    assert_eq!(waiter.id, waiter_id);
+
+    TASK_TABLE.lock().unregister(owner_id);
+    unsafe{ RUNNING = Some(waiter) };
+    assert_eq!(mutex.unlock(), Ok(()));
+    unsafe{ RUNNING = None };
+    TASK_TABLE.lock().unregister(waiter_id);
+}
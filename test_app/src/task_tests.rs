@@ -1,7 +1,14 @@
 use cortex_m_semihosting::hprintln;
-use kernel::{HEAP};
-use kernel::task::{Queue, TaskTCB, STACK_SIZE};
+use kernel::{HEAP, WAITING_QUEUE};
+use kernel::idle;
+use kernel::config::{NO_TIME_SLICE, TICK_RATE_HZ};
+use kernel::error::KernelError;
+use kernel::scheduler::{FixedPriority, Scheduler};
+use kernel::semaphore::Semaphore;
+use kernel::task::{self, schedule, time_slice_tick, Queue, TaskState, TaskTCB, CURRENT, RUNNING, STACK_SIZE, SUSPENDED_TASKS, TASK_TABLE};
+use kernel::time::ms_to_ticks;
 use alloc::boxed::Box;
+use core::ptr;
 
 #[test_case]
 fn test_queue_empty() {
@@ -66,4 +73,208 @@ fn test_stack_start() {
 fn test_stack_end() {
     let mut task_tcb = TaskTCB::new(None, 0);
     assert_eq!(task_tcb.stack_end(), unsafe{ (&mut task_tcb.stack[0] as *mut u8).add(STACK_SIZE)});
-}
\ No newline at end of file
+}
+
+#[test_case]
+fn test_insert_by_priority() {
+    let mut queue = Queue::new();
+
+    for priority in [1, 3, 2, 3, 0] {
+        queue.insert_by_priority(Box::new(TaskTCB::new(None, priority)));
+    }
+
+    // Tasks are sorted by priority, and FIFO among the same priority
+    let priorities: [usize; 5] = [3, 3, 2, 1, 0];
+    for priority in priorities {
+        assert_eq!(queue.dequeue().unwrap().priority, priority);
+    }
+    assert!(queue.empty());
+}
+
+#[test_case]
+fn test_queue_remove() {
+    let mut queue = Queue::new();
+
+    for id in 1..4 {
+        let mut task = Box::new(TaskTCB::new(None, 0));
+        task.id = id;
+        queue.enqueue(task);
+    }
+
+    assert_eq!(queue.remove(3).unwrap().id, 3);
+    assert!(queue.remove(3).is_none());
+
+    // The tail is still valid after removing the last task
+    let mut task = Box::new(TaskTCB::new(None, 0));
+    task.id = 4;
+    queue.enqueue(task);
+    assert_eq!(queue.remove(1).unwrap().id, 1);
+
+    assert_eq!(queue.dequeue().unwrap().id, 2);
+    assert_eq!(queue.dequeue().unwrap().id, 4);
+    assert!(queue.empty());
+}
+
+#[test_case]
+fn test_ready_queue() {
+    let mut queue = FixedPriority::new();
+
+    for priority in [0, 2, 1, 2] {
+        queue.enqueue(Box::new(TaskTCB::new(None, priority)));
+    }
+    assert_eq!(queue.count_tasks(), 4);
+    assert_eq!(queue.first().map(|tcb| tcb.priority), Some(2));
+
+    let priorities: [usize; 4] = [2, 2, 1, 0];
+    for priority in priorities {
+        assert_eq!(queue.dequeue_next().unwrap().priority, priority);
+    }
+    assert!(queue.empty());
+}
+
+#[test_case]
+fn test_idle_task() {
+    // With no task ready, the idle task is scheduled
+    let idle_tcb = unsafe{ schedule() };
+    assert!(!idle_tcb.is_null());
+    assert!(idle::is_running());
+    // No context switch must take place during the test
+    unsafe{ CURRENT = ptr::null_mut() };
+
+    // As soon as a task is ready, it replaces the idle task, which is not
+    // put into the ready queues
+    let mut tcb = Box::new(TaskTCB::new(None, 0));
+    TASK_TABLE.lock().register(&mut tcb).unwrap();
+    let id = tcb.id;
+    WAITING_QUEUE.enqueue(tcb);
+    let next = unsafe{ schedule() };
+    unsafe{ CURRENT = ptr::null_mut() };
+    assert_eq!(unsafe{ (*next).id }, id);
+    assert!(!idle::is_running());
+    assert!(WAITING_QUEUE.empty());
+
+    unsafe{ RUNNING = None };
+    TASK_TABLE.lock().unregister(id);
+}
+
+#[test_case]
+fn test_cpu_load() {
+    // The current window is completed while no task is running
+    idle::account_ticks(idle::CPU_LOAD_WINDOW);
+    assert_eq!(idle::cpu_load(), 100);
+
+    // The idle task runs for three quarters of the next window
+    unsafe{ schedule() };
+    unsafe{ CURRENT = ptr::null_mut() };
+    assert!(idle::is_running());
+    idle::account_ticks(idle::CPU_LOAD_WINDOW / 4 * 3);
+
+    let mut tcb = Box::new(TaskTCB::new(None, 0));
+    TASK_TABLE.lock().register(&mut tcb).unwrap();
+    let id = tcb.id;
+    WAITING_QUEUE.enqueue(tcb);
+    unsafe{ schedule() };
+    unsafe{ CURRENT = ptr::null_mut() };
+    idle::account_ticks(idle::CPU_LOAD_WINDOW / 4);
+    assert_eq!(idle::cpu_load(), 25);
+
+    unsafe{ RUNNING = None };
+    TASK_TABLE.lock().unregister(id);
+}
+
+#[test_case]
+fn test_time_slice() {
+    assert_eq!(ms_to_ticks(1000), TICK_RATE_HZ);
+    assert_eq!(ms_to_ticks(1), 1);
+
+    let mut tcb = Box::new(TaskTCB::new(None, 1));
+    tcb.quantum = 2;
+    WAITING_QUEUE.enqueue(tcb);
+    unsafe{ schedule() };
+    unsafe{ CURRENT = ptr::null_mut() };
+
+    // With no other task ready, the slice simply starts over
+    assert!(!time_slice_tick());
+    assert!(!time_slice_tick());
+
+    // A task with a lower priority does not get the CPU
+    WAITING_QUEUE.enqueue(Box::new(TaskTCB::new(None, 0)));
+    assert!(!time_slice_tick());
+    assert!(!time_slice_tick());
+
+    // A task with the same priority gets it once the slice is over
+    WAITING_QUEUE.enqueue(Box::new(TaskTCB::new(None, 1)));
+    assert!(!time_slice_tick());
+    assert!(time_slice_tick());
+
+    // Unless round robin is off for the running task
+    unsafe{ RUNNING.as_mut().unwrap().quantum = NO_TIME_SLICE };
+    for _ in 0..4 {
+        assert!(!time_slice_tick());
+    }
+
+    unsafe{ RUNNING = None };
+    while WAITING_QUEUE.dequeue().is_some() {}
+}
+
+#[test_case]
+fn test_set_priority() {
+    let mut tcb = Box::new(TaskTCB::new(None, 1));
+    TASK_TABLE.lock().register(&mut tcb).unwrap();
+    let id = tcb.id;
+    WAITING_QUEUE.enqueue(tcb);
+
+    // The task is moved to the ready queue of its new priority
+    assert_eq!(task::set_priority(id, 5), Ok(()));
+    assert_eq!(task::get_priority(id), Some(5));
+    assert_eq!(WAITING_QUEUE.highest_priority(), Some(5));
+
+    assert_eq!(task::set_priority(id, 100), Err(KernelError::InvalidArgument));
+    assert_eq!(task::set_priority(usize::MAX, 1), Err(KernelError::InvalidArgument));
+    assert_eq!(task::get_priority(usize::MAX), None);
+
+    let tcb = WAITING_QUEUE.dequeue().unwrap();
+    assert_eq!(tcb.id, id);
+    TASK_TABLE.lock().unregister(id);
+}
+
+#[test_case]
+fn test_suspend_resume() {
+    let mut tcb = Box::new(TaskTCB::new(None, 2));
+    TASK_TABLE.lock().register(&mut tcb).unwrap();
+    let id = tcb.id;
+    WAITING_QUEUE.enqueue(tcb);
+
+    // A ready task leaves the ready queue until it is resumed
+    assert_eq!(task::suspend(id), Ok(()));
+    assert!(WAITING_QUEUE.dequeue().is_none());
+    assert_eq!(TASK_TABLE.lock().get(id).unwrap().state, TaskState::Suspended);
+    assert_eq!(task::resume(id), Ok(()));
+    assert!(SUSPENDED_TASKS.lock().remove(id).is_none());
+    let tcb = WAITING_QUEUE.dequeue().unwrap();
+    assert_eq!(tcb.state, TaskState::Ready);
+
+    // A blocked task completes its wait, but stays suspended
+    let semaphore = Semaphore::binary(false);
+    unsafe{ RUNNING = Some(tcb) };
+    assert_eq!(semaphore.take(10), Err(KernelError::Pending));
+    assert_eq!(task::suspend(id), Ok(()));
+    assert_eq!(semaphore.give(), Ok(()));
+    assert!(WAITING_QUEUE.dequeue().is_none());
+    assert_eq!(task::resume(id), Ok(()));
+    let tcb = WAITING_QUEUE.dequeue().unwrap();
+    assert_eq!(tcb.wait_result, Ok(()));
+
+    // The running task is suspended once it is switched out
+    unsafe{ RUNNING = Some(tcb) };
+    assert_eq!(task::suspend(id), Ok(()));
+    WAITING_QUEUE.enqueue(Box::new(TaskTCB::new(None, 0)));
+    unsafe{ schedule() };
+    unsafe{ CURRENT = ptr::null_mut() };
+    assert_ne!(unsafe{ RUNNING.as_ref().unwrap().id }, id);
+    assert!(SUSPENDED_TASKS.lock().remove(id).is_some());
+
+    unsafe{ RUNNING = None };
+    while WAITING_QUEUE.dequeue().is_some() {}
+    TASK_TABLE.lock().unregister(id);
+}
@@ -0,0 +1,23 @@
+use kernel::WAITING_QUEUE;
+use kernel::task::{TaskTCB, TASK_TABLE};
+use alloc::boxed::Box;
+
+/*
+Helpers shared by the tests that need tasks of their own. The tasks have no
+code: the tests play their part by making them the RUNNING task.
+*/
+
+// Creates a task and registers it, without scheduling it
+pub fn registered_task(priority: usize) -> Box<TaskTCB> {
+    let mut tcb = Box::new(TaskTCB::new(None, priority));
+    TASK_TABLE.lock().register(&mut tcb).unwrap();
+    tcb
+}
+
+// Creates a task and makes it ready, without running it
+pub fn ready_task(priority: usize) -> usize {
+    let tcb = registered_task(priority);
+    let id = tcb.id;
+    WAITING_QUEUE.enqueue(tcb);
+    id
+}
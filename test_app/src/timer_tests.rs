@@ -0,0 +1,79 @@
+use kernel::error::KernelError;
+use kernel::time::ticks;
+use kernel::timer::{self, Timer, TimerList, TIMER_LIST};
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+static FIRED: AtomicUsize = AtomicUsize::new(0);
+
+fn count_expiry(_arg: *mut u8) {
+    FIRED.fetch_add(1, Ordering::Relaxed);
+}
+
+#[test_case]
+fn timer_list_order_test() {
+    let first = Timer::new(count_expiry, ptr::null_mut(), 1, false);
+    let second = Timer::new(count_expiry, ptr::null_mut(), 1, false);
+    let mut list = TimerList::new();
+
+    assert_eq!(list.insert(&second, 20), Ok(()));
+    assert_eq!(list.insert(&first, 10), Ok(()));
+    assert_eq!(list.next_expiry(), Some(10));
+    assert!(list.contains(&first));
+
+    assert_eq!(list.pop_expired(5), None);
+    assert_eq!(list.pop_expired(10), Some((10, &first as *const Timer)));
+    list.remove(&second);
+    assert_eq!(list.len(), 0);
+    assert_eq!(list.next_expiry(), None);
+}
+
+#[test_case]
+fn timer_start_stop_test() {
+    let timer = Timer::new(count_expiry, ptr::null_mut(), 10, false);
+    assert!(!timer.is_active());
+
+    assert_eq!(timer.start(), Ok(()));
+    assert!(timer.is_active());
+    // Starting an active timer has no effect
+    assert_eq!(timer.start(), Ok(()));
+    assert_eq!(TIMER_LIST.lock().next_expiry(), Some(ticks().wrapping_add(10)));
+
+    timer.stop();
+    assert!(!timer.is_active());
+
+    assert_eq!(timer.change_period(0), Err(KernelError::InvalidArgument));
+    assert_eq!(timer.change_period(20), Ok(()));
+    assert_eq!(timer.period(), 20);
+    assert!(timer.is_active());
+    assert_eq!(TIMER_LIST.lock().next_expiry(), Some(ticks().wrapping_add(20)));
+}
+
+#[test_case]
+fn timer_expiry_test() {
+    let one_shot = Timer::new(count_expiry, ptr::null_mut(), 5, false);
+    let periodic = Timer::new(count_expiry, ptr::null_mut(), 3, true);
+    let now = ticks();
+    FIRED.store(0, Ordering::Relaxed);
+
+    assert_eq!(one_shot.start(), Ok(()));
+    assert_eq!(periodic.start(), Ok(()));
+    timer::run_expired(now.wrapping_add(2));
+    assert_eq!(FIRED.load(Ordering::Relaxed), 0);
+
+    // The auto-reload timer is reloaded from its expiry time
+    timer::run_expired(now.wrapping_add(3));
+    assert_eq!(FIRED.load(Ordering::Relaxed), 1);
+    assert!(periodic.is_active());
+
+    // Both expire, and the one-shot timer stops
+    timer::run_expired(now.wrapping_add(6));
+    assert_eq!(FIRED.load(Ordering::Relaxed), 3);
+    assert!(!one_shot.is_active());
+    assert!(periodic.is_active());
+    assert_eq!(TIMER_LIST.lock().next_expiry(), Some(now.wrapping_add(9)));
+
+    periodic.stop();
+    timer::run_expired(now.wrapping_add(9));
+    assert_eq!(FIRED.load(Ordering::Relaxed), 3);
+}
@@ -0,0 +1,96 @@
+use kernel::WAITING_QUEUE;
+use kernel::error::KernelError;
+use kernel::semaphore::Semaphore;
+use kernel::task::{RUNNING, TASK_TABLE};
+use kernel::time::{kernel_tick, ticks};
+use kernel::wait::{TimeoutList, MAX_TIMEOUT, NO_WAIT, TIMEOUT_LIST, WAIT_FOREVER};
+use crate::test_support::registered_task;
+
+#[test_case]
+fn timeout_list_order_test() {
+    let mut list = TimeoutList::new();
+    list.insert(1, 30);
+    list.insert(2, 10);
+    list.insert(3, 20);
+    list.insert(4, 10);
+    assert_eq!(list.len(), 4);
+    assert_eq!(list.next_deadline(), Some(10));
+
+    // Tasks with the same deadline expire in the order they were added
+    assert_eq!(list.pop_expired(9), None);
+    assert_eq!(list.pop_expired(15), Some(2));
+    assert_eq!(list.pop_expired(15), Some(4));
+    assert_eq!(list.pop_expired(15), None);
+
+    list.remove(3);
+    assert_eq!(list.pop_expired(40), Some(1));
+    assert_eq!(list.len(), 0);
+}
+
+#[test_case]
+fn timeout_list_wrap_around_test() {
+    let mut list = TimeoutList::new();
+    let now = u32::MAX - 5;
+    list.insert(1, now.wrapping_add(10));
+    list.insert(2, now.wrapping_add(3));
+
+    // The deadline past the wrap-around comes last
+    assert_eq!(list.next_deadline(), Some(now.wrapping_add(3)));
+    assert_eq!(list.pop_expired(now), None);
+    assert_eq!(list.pop_expired(now.wrapping_add(3)), Some(2));
+    assert_eq!(list.pop_expired(now.wrapping_add(9)), None);
+    assert_eq!(list.pop_expired(now.wrapping_add(10)), Some(1));
+}
+
+#[test_case]
+fn timeout_list_wakeup_test() {
+    let semaphore = Semaphore::binary(false);
+    assert_eq!(semaphore.take(NO_WAIT), Err(KernelError::WouldBlock));
+
+    // Only the tasks waiting with a timeout are in the list
+    let before = TIMEOUT_LIST.lock().len();
+    let mut ids = [0; 2];
+    for (i, timeout) in [WAIT_FOREVER, 5].iter().enumerate() {
+        let tcb = registered_task(1);
+        ids[i] = tcb.id;
+        unsafe{ RUNNING = Some(tcb) };
+        assert_eq!(semaphore.take(*timeout), Err(KernelError::Pending));
+    }
+    assert_eq!(TIMEOUT_LIST.lock().len(), before + 1);
+
+    // A task woken up before its deadline leaves the list
+    assert_eq!(semaphore.give(), Ok(()));
+    assert_eq!(semaphore.give(), Ok(()));
+    assert_eq!(TIMEOUT_LIST.lock().len(), before);
+    for _ in 0..5 {
+        kernel_tick();
+    }
+    for _ in 0..2 {
+        assert_eq!(WAITING_QUEUE.dequeue().unwrap().wait_result, Ok(()));
+    }
+
+    for id in ids {
+        TASK_TABLE.lock().unregister(id);
+    }
+}
+
+#[test_case]
+fn large_timeout_test() {
+    let semaphore = Semaphore::binary(false);
+    let tcb = registered_task(1);
+    let id = tcb.id;
+    unsafe{ RUNNING = Some(tcb) };
+
+    // A timeout beyond 2^31 ticks is clamped, instead of wrapping into the
+    // past and expiring on the next tick
+    let now = ticks();
+    assert_eq!(semaphore.take(WAIT_FOREVER - 1), Err(KernelError::Pending));
+    let wake_tick = TASK_TABLE.lock().get(id).unwrap().wake_tick;
+    assert_eq!(wake_tick, Some(now.wrapping_add(MAX_TIMEOUT)));
+    kernel_tick();
+    assert!(WAITING_QUEUE.empty());
+
+    assert_eq!(semaphore.give(), Ok(()));
+    assert_eq!(WAITING_QUEUE.dequeue().unwrap().wait_result, Ok(()));
+    TASK_TABLE.lock().unregister(id);
+}